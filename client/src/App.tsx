@@ -90,6 +90,8 @@ const App = () => {
     useState<PlayerSpriteName>(randomSprite);
 
   const [playerName, setPlayerName] = useState<string>("Player");
+  const [password, setPassword] = useState<string>("");
+  const [newAccount, setNewAccount] = useState<boolean>(false);
 
   const [playerStats, setPlayerStats] = useState<PlayerStats>();
   const [allPlayerStats, setAllPlayerStats] = useState<any | undefined>();
@@ -155,6 +157,8 @@ const App = () => {
           (payload) => setDamageNumbers((prev) => [...prev, payload]),
           playerSprite,
           playerName,
+          password,
+          newAccount,
           setPlayerStats,
           addLogEntry
         ).then(
@@ -263,6 +267,21 @@ const App = () => {
             value={playerName}
             onChange={(e) => setPlayerName(e.target.value)}
           />
+          <input
+            type="password"
+            placeholder="Password"
+            value={password}
+            onChange={(e) => setPassword(e.target.value)}
+          />
+          <div>
+            <input
+              type="checkbox"
+              id="new-account"
+              checked={newAccount}
+              onChange={(e) => setNewAccount(e.target.checked)}
+            />
+            <label htmlFor="new-account">New account</label>
+          </div>
           <div>
             <input
               type="range"
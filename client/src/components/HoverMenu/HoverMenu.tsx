@@ -19,6 +19,7 @@ export const HoverMenu: React.FC<HoverMenuProps> = ({
       }}
     >
       <p>{entityData.name}</p>
+      {entityData.description && <p>{entityData.description}</p>}
       <p>Blocks Light: {entityData.blocksLight ? "Yes" : "No"}</p>
       <p>Can See: {entityData.visibleToPlayer ? "Yes" : "No"}</p>
     </div>
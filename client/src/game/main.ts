@@ -18,7 +18,7 @@ import {
   SpriteTexture,
 } from "../utility/types";
 import { assertNever, log } from "../utility/functions";
-import { PLAYER_STATS_URI } from "../utility/config";
+import { PLAYER_STATS_URI, PROTOCOL_VERSION } from "../utility/config";
 import { addInputListeners, GameInputState } from "./input";
 import { mapPosToScreenPos, setCamera, GAME_CONFIG } from "./camera";
 import { DebugMenuProps } from "../components/DebugMenu/DebugMenu";
@@ -77,6 +77,8 @@ var eatBones = new Audio("audio/sfx/bones.ogg");
 //   request.send();
 // }
 
+const SESSION_TOKEN_KEY = "sessionToken";
+
 let xPixel = 0;
 let yPixel = 0;
 
@@ -110,6 +112,8 @@ export const initializeGame = async (
   setDamageNumbers: (payload: DamageNumberProps) => void,
   playerSpriteName: PlayerSpriteName,
   playerName: string,
+  password: string,
+  newAccount: boolean,
   setPlayerStats: (payload: PlayerStats) => void,
   addLogEntry: (log: string) => void
 ) => {
@@ -130,10 +134,18 @@ export const initializeGame = async (
       console.error("Received invalid message", msg.data);
       throw Error;
     }
-    const response: ServerMessageAllClients | ServerMessageSingleClient =
-      JSON.parse(msg.data);
+    handleMessage(JSON.parse(msg.data));
+  };
 
+  const handleMessage = (
+    response: ServerMessageAllClients | ServerMessageSingleClient
+  ) => {
     switch (response.type) {
+      case "batch":
+        // Everything the server sent us during one tick, in order
+        response.content.messages.forEach(handleMessage);
+        response.content.broadcasts.forEach(handleMessage);
+        break;
       case "showAnimation":
         showAttackAnimation(response.content.position, response.content.time);
         break;
@@ -211,6 +223,38 @@ export const initializeGame = async (
         // console.log(response.content);
         setDebugMenuProps(response.content);
         break;
+      case "sessionToken":
+        sessionStorage.setItem(SESSION_TOKEN_KEY, response.content);
+        break;
+      case "resumeFailed":
+        sessionStorage.removeItem(SESSION_TOKEN_KEY);
+        sendLogin();
+        break;
+      case "loggedIn":
+        log.trace("Logged in as", response.content.name);
+        sendInitialize();
+        break;
+      case "loginFailed":
+        alert(response.content.reason);
+        break;
+      case "shutdownNotice":
+        addLogEntry(
+          `Server shutting down in ${response.content.secondsRemaining} seconds`
+        );
+        break;
+      case "handshakeAccepted":
+        log.trace("Server accepted protocol version", response.content);
+        break;
+      case "handshakeRejected":
+        // The server closes the connection after this, an old tab needs the new client
+        alert(
+          "This game client is out of date, please refresh the page.\n\n" +
+            response.content.reason
+        );
+        break;
+      case "protocolError":
+        console.error("Server could not understand a message", response.content);
+        break;
       case "showDamage":
         // console.log(response.content);
         // setDebugMenuProps(response.content);
@@ -248,14 +292,35 @@ export const initializeGame = async (
     gameInputState
   );
 
-  let interval = setInterval(() => {
-    let result = safeSend({
-      type: "initialize",
+  const sendLogin = () =>
+    safeSend({
+      type: newAccount ? "register" : "login",
       content: {
         name: playerName,
+        password,
+        protocolVersion: PROTOCOL_VERSION,
+      },
+    });
+
+  // Only sent once the server has told us we're logged in
+  const sendInitialize = () =>
+    safeSend({
+      type: "initialize",
+      content: {
         sprite: SPRITE_NAME_TO_TEXTURE[playerSpriteName],
+        protocolVersion: PROTOCOL_VERSION,
       },
     });
+
+  let interval = setInterval(() => {
+    // Take back our existing player if this tab has been connected before
+    const token = sessionStorage.getItem(SESSION_TOKEN_KEY);
+    let result = token
+      ? safeSend({
+          type: "resume",
+          content: { token, protocolVersion: PROTOCOL_VERSION },
+        })
+      : sendLogin();
     if (result === "success") {
       clearInterval(interval);
     }
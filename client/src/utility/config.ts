@@ -2,6 +2,9 @@
 export const STRICT_MODE: boolean = true;
 export const LOG_LEVEL: "trace" | "none" = "trace";
 
+// Must match PROTOCOL_VERSION in crates/core-api
+export const PROTOCOL_VERSION: number = 3;
+
 const CURRENT_URL = new URL(document.URL);
 
 const SSL = CURRENT_URL.protocol === "http:" ? "" : "s";
@@ -1,11 +1,11 @@
 use std::fmt::Display;
 
 use rand::{distributions::Standard, prelude::Distribution};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use typeshare::typeshare;
 
 #[typeshare]
-#[derive(Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 #[serde(rename_all = "camelCase")]
 /// Yes I know this name sucks. What's better?
 /// https://en.wikipedia.org/wiki/Body_relative_direction
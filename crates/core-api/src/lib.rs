@@ -1,21 +1,29 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{atomic::AtomicI64, RwLock},
+};
 
 use ae_direction::BodyRelative;
 use ae_position::Position;
 use serde::{Deserialize, Serialize};
 use typeshare::typeshare;
 
+/// Bump whenever a change to these types would break an existing client. Clients send
+/// it with `Register`, `Login`, `Initialize` and `Resume` and are turned away if it
+/// doesn't match the server's.
+pub const PROTOCOL_VERSION: u32 = 3;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct UserId(pub i32);
 
 #[typeshare]
-#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
 pub struct EntityIndex {
     pub idx: u32,
 }
 
 #[typeshare]
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 /// Information about a sprite to render
 pub struct SpriteUpdate {
@@ -25,23 +33,25 @@ pub struct SpriteUpdate {
 }
 
 #[typeshare]
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 /// Information about an entity to display to the user
 pub struct EntityData {
     pub name: String,
     pub blocks_light: bool,
     pub visible_to_player: bool,
+    /// Set for things that are part of the map
+    pub description: Option<String>,
 }
 
 #[typeshare]
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 /// A single entry in the game log
 pub struct LogMessage(pub String);
 
 #[typeshare]
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 /// A sprite to render that represents a visible entity
 pub enum SpriteTexture {
@@ -144,27 +154,92 @@ pub struct DebugData {
 }
 
 #[typeshare]
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
+/// A password on its way to be hashed, never printed in logs
+pub struct Password(pub String);
+
+impl std::fmt::Debug for Password {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Password(..)")
+    }
+}
+
+#[typeshare]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase", tag = "type", content = "content")]
 /// An input interaction from the client
 pub enum ClientMessage {
     TileHover(Position),
     TileClick(Position),
+    /// Create an account and log in to it
     #[serde(rename_all = "camelCase")]
-    Initialize {
+    Register {
+        name: String,
+        password: Password,
+        #[serde(default)]
+        protocol_version: Option<u32>,
+    },
+    /// Must succeed (or `Register` must) before `Initialize` will spawn a player
+    #[serde(rename_all = "camelCase")]
+    Login {
         name: String,
+        password: Password,
+        #[serde(default)]
+        protocol_version: Option<u32>,
+    },
+    /// Join the game as the logged in account, whose name the player is given
+    #[serde(rename_all = "camelCase")]
+    Initialize {
         sprite: SpriteTexture,
+        /// Must match [`PROTOCOL_VERSION`]. Optional only so that clients from before
+        /// versioning get a readable rejection rather than a parse error.
+        #[serde(default)]
+        protocol_version: Option<u32>,
     },
     Keypress(BodyRelative),
+    /// Sent instead of `Initialize` by a client that was given a session token
+    /// and wants to take back control of its existing player entity
+    #[serde(rename_all = "camelCase")]
+    Resume {
+        token: String,
+        #[serde(default)]
+        protocol_version: Option<u32>,
+    },
     Disconnect,
     /// Clients should send every 30 seconds or so to
     /// keep from getting your socket closed when hosting on free services
     KeepAlive,
     Spawn(SpawnableEnemy),
+    /// Watch a map without joining the game, sent by the server for connections on
+    /// `api/spectate`. Any other input from a spectator is ignored. Clients can't send it,
+    /// a player's connection can't be turned into a spectator's.
+    #[serde(rename_all = "camelCase", skip_deserializing)]
+    Spectate {
+        map_id: i32,
+    },
+}
+
+impl ClientMessage {
+    /// The message's `type` on the wire, used to label metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ClientMessage::TileHover(_) => "tileHover",
+            ClientMessage::TileClick(_) => "tileClick",
+            ClientMessage::Register { .. } => "register",
+            ClientMessage::Login { .. } => "login",
+            ClientMessage::Initialize { .. } => "initialize",
+            ClientMessage::Keypress(_) => "keypress",
+            ClientMessage::Resume { .. } => "resume",
+            ClientMessage::Disconnect => "disconnect",
+            ClientMessage::KeepAlive => "keepAlive",
+            ClientMessage::Spawn(_) => "spawn",
+            ClientMessage::Spectate { .. } => "spectate",
+        }
+    }
 }
 
 #[typeshare]
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase", tag = "type", content = "content")]
 /// Communicates information about the active game to one client
 pub enum ServerMessageSingleClient {
@@ -200,10 +275,91 @@ pub enum ServerMessageSingleClient {
         current_hp: i32,
         max_hp: i32,
     },
+    /// A token the client can present with `Resume` to reclaim its player after a reconnect
+    SessionToken(String),
+    /// The token sent with `Resume` does not match any player, the client should `Initialize` instead
+    ResumeFailed,
+    /// `Register` or `Login` succeeded, the client can `Initialize` now
+    LoggedIn {
+        name: String,
+    },
+    /// `Register`, `Login` or `Initialize` was refused, the reason can be shown to the player
+    LoginFailed {
+        reason: String,
+    },
+    /// The client's protocol version is supported and the server is ready for it
+    #[serde(rename_all = "camelCase")]
+    HandshakeAccepted {
+        server_version: u32,
+    },
+    /// The client's protocol version is not supported, the connection is closed after this
+    #[serde(rename_all = "camelCase")]
+    HandshakeRejected {
+        server_version: u32,
+        reason: String,
+    },
+    /// A message from the client could not be understood
+    #[serde(rename_all = "camelCase")]
+    ProtocolError {
+        error: String,
+        /// The `type` of the rejected message, if it was possible to read one
+        message_type: Option<String>,
+    },
+    /// Every other message is delivered inside one of these, never sent by the engine directly
+    Batch(TickBatch),
+}
+
+impl ServerMessageSingleClient {
+    /// The message's `type` on the wire, used to label metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ServerMessageSingleClient::TileHover(_) => "tileHover",
+            ServerMessageSingleClient::EntityPositionChange(_) => "entityPositionChange",
+            ServerMessageSingleClient::AddSprite(_) => "addSprite",
+            ServerMessageSingleClient::CentreCamera(_) => "centreCamera",
+            ServerMessageSingleClient::UpdateFullGameMap { .. } => "updateFullGameMap",
+            ServerMessageSingleClient::RemoveSprite(_) => "removeSprite",
+            ServerMessageSingleClient::PlaySound(_) => "playSound",
+            ServerMessageSingleClient::ShowDialogue { .. } => "showDialogue",
+            ServerMessageSingleClient::ShowAnimation { .. } => "showAnimation",
+            ServerMessageSingleClient::ShowDamage { .. } => "showDamage",
+            ServerMessageSingleClient::SessionToken(_) => "sessionToken",
+            ServerMessageSingleClient::ResumeFailed => "resumeFailed",
+            ServerMessageSingleClient::LoggedIn { .. } => "loggedIn",
+            ServerMessageSingleClient::LoginFailed { .. } => "loginFailed",
+            ServerMessageSingleClient::HandshakeAccepted { .. } => "handshakeAccepted",
+            ServerMessageSingleClient::HandshakeRejected { .. } => "handshakeRejected",
+            ServerMessageSingleClient::ProtocolError { .. } => "protocolError",
+            ServerMessageSingleClient::Batch(_) => "batch",
+        }
+    }
 }
 
 #[typeshare]
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Everything sent to one client during a single engine tick, in the order it was sent
+pub struct TickBatch {
+    /// Counts up by one every tick, so a client can put events from different batches in order
+    pub tick: u32,
+    pub messages: Vec<ServerMessageSingleClient>,
+    /// Messages sent to every client during the same tick
+    pub broadcasts: Vec<ServerMessageAllClients>,
+}
+
+#[derive(Debug, Default)]
+/// Everything the engine sent during one tick, handed to the server in one piece so each
+/// client's batch for the tick is complete before the next tick's arrives
+pub struct TickOutput {
+    pub tick: u32,
+    /// The messages for each user, in the order they were sent
+    pub batches: Vec<(UserId, Vec<ServerMessageSingleClient>)>,
+    /// Messages for every client
+    pub broadcasts: Vec<ServerMessageAllClients>,
+}
+
+#[typeshare]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase", tag = "type", content = "content")]
 /// Communicates information about the active game to one client
 pub enum ServerMessageAllClients {
@@ -217,16 +373,152 @@ pub enum ServerMessageAllClients {
     Death(LogMessage),
     Debug(DebugData),
     Log(LogMessage),
+    /// The server is stopping, every connection is closed once the countdown runs out
+    #[serde(rename_all = "camelCase")]
+    ShutdownNotice {
+        seconds_remaining: u32,
+    },
+}
+
+impl ServerMessageAllClients {
+    /// The message's `type` on the wire, used to label metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ServerMessageAllClients::TileClick(_) => "tileClick",
+            ServerMessageAllClients::MoveCount(_) => "moveCount",
+            ServerMessageAllClients::Damage(_) => "damage",
+            ServerMessageAllClients::Death(_) => "death",
+            ServerMessageAllClients::Debug(_) => "debug",
+            ServerMessageAllClients::Log(_) => "log",
+            ServerMessageAllClients::ShutdownNotice { .. } => "shutdownNotice",
+        }
+    }
 }
 
 #[derive(Debug)]
 /// Communicates information from the game engine to the database
 pub enum DatabaseRequest {
     Placeholder,
+    Register { name: String, password: Password },
+    Login { name: String, password: Password },
 }
 
 #[derive(Debug)]
 /// Communicates information from the database back to the game engine
 pub enum DatabaseResponse {
     MoveCount(i32),
+    LoggedIn { account_id: i64, name: String },
+    LoginFailed(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// How a generated map is laid out
+pub enum MapAlgorithm {
+    /// Rectangular rooms joined by corridors
+    Bsp,
+    /// Winding caves grown from random noise
+    Caves,
+    /// Tunnels dug by wandering about at random
+    DrunkardsWalk,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Everything that decides what a generated map looks like
+pub struct MapGeneratorSettings {
+    pub algorithm: MapAlgorithm,
+    pub width: i32,
+    pub height: i32,
+    /// The same seed and settings always give the same map, a random one is used when unset
+    #[serde(default)]
+    pub seed: Option<u64>,
+    pub wall: SpriteTexture,
+    pub floor: SpriteTexture,
+    /// The chance of each open tile starting with an enemy on it, from 0 to 1
+    #[serde(default)]
+    pub enemy_density: f32,
+    /// The enemies to place, picked from evenly
+    #[serde(default)]
+    pub enemies: Vec<SpawnableEnemy>,
+}
+
+#[derive(Debug)]
+/// An operation requested through the admin API, applied by the game engine on its next tick
+pub enum AdminCommand {
+    ListUsers,
+    /// Removes the user's player from the game straight away, the server closes their socket
+    Kick(UserId),
+    Broadcast(String),
+    Spawn {
+        map_id: i32,
+        enemy: SpawnableEnemy,
+    },
+    Teleport {
+        user_id: UserId,
+        map_id: i32,
+        pos: Position,
+    },
+    /// Adds a new map to the game, built by a map generator
+    GenerateMap {
+        name: String,
+        settings: MapGeneratorSettings,
+    },
+    /// Adds a private copy of a map to the game, removed again once the last player leaves it
+    CreateInstance {
+        template_map_id: i32,
+    },
+    /// Re-reads the player, enemy and dialogue data files, keeping the current data if any
+    /// of them has a mistake
+    ReloadData,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// A user with a socket open to the game, as reported by the admin API
+pub struct ConnectedUser {
+    pub user_id: i32,
+    /// `None` for spectators, who have no player
+    pub name: Option<String>,
+    pub map_id: i32,
+    pub pos: Position,
+    pub spectator: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase", tag = "type", content = "content")]
+/// Communicates the result of an `AdminCommand` back from the game engine
+pub enum AdminResponse {
+    Users(Vec<ConnectedUser>),
+    Done,
+    /// The id of a map that was just added
+    MapCreated(i32),
+}
+
+/// Either the command's result or why it could not be applied
+pub type AdminReply = Result<AdminResponse, String>;
+
+#[derive(Debug, Default, Clone)]
+/// The game engine's figures for the metrics endpoint, replaced about once a second
+pub struct EngineMetrics {
+    /// Ticks run since the engine started
+    pub ticks: u64,
+    /// Seconds spent running those ticks
+    pub tick_seconds_total: f64,
+    /// The slowest tick since the previous publish, in seconds
+    pub tick_seconds_max: f64,
+    /// Entities with a position on each map, by map id
+    pub entities_per_map: BTreeMap<i32, u64>,
+    /// Living enemies by name
+    pub enemies_by_type: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Default)]
+/// Written by the game engine and the database listener, read by the metrics endpoint
+pub struct SharedMetrics {
+    /// The engine only ever `try_write`s this, a reader holding the lock costs it one
+    /// publish rather than a stalled tick
+    pub engine: RwLock<EngineMetrics>,
+    /// Requests sent by the engine that the database hasn't finished with
+    pub pending_database_requests: AtomicI64,
 }
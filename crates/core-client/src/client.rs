@@ -0,0 +1,265 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use core_api::{
+    ClientMessage, Password, ServerMessageSingleClient, SpriteTexture, PROTOCOL_VERSION,
+};
+use futures_util::{ready, SinkExt, Stream, StreamExt};
+use log::trace;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{self, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::{message::ServerMessage, sprites::SpriteMirror};
+
+#[derive(Debug)]
+pub enum ClientError {
+    WebSocket(Box<tungstenite::Error>),
+    Json(serde_json::Error),
+    /// The server refused a login, registration or handshake, with its reason
+    Refused(String),
+    /// The server closed the connection
+    Closed,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::WebSocket(e) => write!(f, "Websocket error: {}", e),
+            ClientError::Json(e) => write!(f, "Couldn't read or write a message: {}", e),
+            ClientError::Refused(reason) => write!(f, "Refused by the server: {}", reason),
+            ClientError::Closed => write!(f, "The server closed the connection"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<tungstenite::Error> for ClientError {
+    fn from(e: tungstenite::Error) -> Self {
+        ClientError::WebSocket(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for ClientError {
+    fn from(e: serde_json::Error) -> Self {
+        ClientError::Json(e)
+    }
+}
+
+/// A connection to the game that speaks JSON, the same as the browser client.
+///
+/// Read it as a `Stream` of server messages, batches arrive already unpacked. Every
+/// message read is applied to `sprites` first, so the mirror always matches what has
+/// been read so far.
+pub struct GameClient {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// The rest of a batch that has been read from the socket but not handed out yet
+    unread: VecDeque<ServerMessage>,
+    sprites: SpriteMirror,
+}
+
+impl GameClient {
+    /// Connects to a websocket endpoint such as `ws://localhost:8080/api/game`, or
+    /// `api/spectate/<map id>` to watch without playing
+    pub async fn connect(url: &str) -> Result<Self, ClientError> {
+        let (socket, _) = connect_async(url).await?;
+
+        Ok(Self {
+            socket,
+            unread: VecDeque::new(),
+            sprites: SpriteMirror::default(),
+        })
+    }
+
+    pub async fn send(&mut self, message: &ClientMessage) -> Result<(), ClientError> {
+        trace!("Sending {:?}", message);
+        let text = serde_json::to_string(message)?;
+        self.socket.send(Message::Text(text)).await?;
+        Ok(())
+    }
+
+    /// The next message from the server, or `Closed` once there are no more
+    pub async fn next_message(&mut self) -> Result<ServerMessage, ClientError> {
+        self.next().await.unwrap_or(Err(ClientError::Closed))
+    }
+
+    /// Reads messages until `select` picks one out, the messages skipped on the way still
+    /// update the sprite mirror
+    pub async fn wait_for<T>(
+        &mut self,
+        mut select: impl FnMut(ServerMessage) -> Option<T>,
+    ) -> Result<T, ClientError> {
+        loop {
+            if let Some(selected) = select(self.next_message().await?) {
+                return Ok(selected);
+            }
+        }
+    }
+
+    /// Logs in, or creates the account first when `register` is set. Returns the
+    /// account's name as the server has it.
+    pub async fn login(
+        &mut self,
+        name: &str,
+        password: &str,
+        register: bool,
+    ) -> Result<String, ClientError> {
+        let name = name.to_string();
+        let password = Password(password.to_string());
+        let protocol_version = Some(PROTOCOL_VERSION);
+
+        self.send(&if register {
+            ClientMessage::Register {
+                name,
+                password,
+                protocol_version,
+            }
+        } else {
+            ClientMessage::Login {
+                name,
+                password,
+                protocol_version,
+            }
+        })
+        .await?;
+
+        self.wait_for(|message| match message {
+            ServerMessage::SingleClient(ServerMessageSingleClient::LoggedIn { name }) => {
+                Some(Ok(name))
+            }
+            ServerMessage::SingleClient(
+                ServerMessageSingleClient::LoginFailed { reason }
+                | ServerMessageSingleClient::HandshakeRejected { reason, .. },
+            ) => Some(Err(ClientError::Refused(reason))),
+            _ => None,
+        })
+        .await?
+    }
+
+    /// Joins the game as the logged in account. Returns the session token that can be
+    /// sent with `Resume` to take the player back after a reconnect.
+    pub async fn initialize(&mut self, sprite: SpriteTexture) -> Result<String, ClientError> {
+        self.send(&ClientMessage::Initialize {
+            sprite,
+            protocol_version: Some(PROTOCOL_VERSION),
+        })
+        .await?;
+
+        self.wait_for(|message| match message {
+            ServerMessage::SingleClient(ServerMessageSingleClient::SessionToken(token)) => {
+                Some(Ok(token))
+            }
+            ServerMessage::SingleClient(ServerMessageSingleClient::LoginFailed { reason }) => {
+                Some(Err(ClientError::Refused(reason)))
+            }
+            _ => None,
+        })
+        .await?
+    }
+
+    /// What the client would be drawing right now
+    pub fn sprites(&self) -> &SpriteMirror {
+        &self.sprites
+    }
+
+    /// Tells the server the client is leaving and closes the websocket
+    pub async fn close(mut self) -> Result<(), ClientError> {
+        self.send(&ClientMessage::Disconnect).await?;
+        self.socket.close(None).await?;
+        Ok(())
+    }
+}
+
+impl Stream for GameClient {
+    type Item = Result<ServerMessage, ClientError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(message) = this.unread.pop_front() {
+                if let ServerMessage::SingleClient(message) = &message {
+                    this.sprites.apply(message);
+                }
+                return Poll::Ready(Some(Ok(message)));
+            }
+
+            // Pings are answered by tungstenite itself, only text frames carry messages
+            match ready!(this.socket.poll_next_unpin(cx)) {
+                Some(Ok(Message::Text(text))) => match ServerMessage::decode_all(&text) {
+                    Ok(messages) => this.unread.extend(messages),
+                    Err(e) => return Poll::Ready(Some(Err(e.into()))),
+                },
+                Some(Ok(_)) => {}
+                Some(Err(tungstenite::Error::ConnectionClosed)) | None => return Poll::Ready(None),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_server::{
+        connections::{ConnectionRole, ConnectionsLock},
+        new_connection::upgrade_connection,
+        settings::ConnectionSettings,
+    };
+    use tokio::sync::mpsc;
+    use warp::Filter;
+
+    #[tokio::test]
+    async fn talks_to_a_real_server() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let connections = ConnectionsLock::default();
+        let route = warp::path!("api" / "game").and(warp::ws()).map(move |ws| {
+            upgrade_connection(
+                ws,
+                None,
+                ConnectionRole::Player,
+                ConnectionSettings::default(),
+                connections.clone(),
+                sender.clone(),
+            )
+        });
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::task::spawn(server);
+
+        let mut client = GameClient::connect(&format!("ws://{}/api/game", address))
+            .await
+            .unwrap();
+        client
+            .send(&ClientMessage::Initialize {
+                sprite: SpriteTexture::PcBoneyBoi,
+                protocol_version: Some(PROTOCOL_VERSION),
+            })
+            .await
+            .unwrap();
+
+        // Handshakes are answered by the server without waiting for the game engine
+        let accepted = client
+            .wait_for(|message| match message {
+                ServerMessage::SingleClient(ServerMessageSingleClient::HandshakeAccepted {
+                    server_version,
+                }) => Some(server_version),
+                _ => None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(accepted, PROTOCOL_VERSION);
+
+        let (_, forwarded) = receiver.recv().await.unwrap();
+        assert!(matches!(forwarded, ClientMessage::Initialize { .. }));
+
+        client.close().await.unwrap();
+    }
+}
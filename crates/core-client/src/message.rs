@@ -0,0 +1,70 @@
+use core_api::{ServerMessageAllClients, ServerMessageSingleClient};
+use serde::Deserialize;
+
+/// Anything the server can send, both kinds share the `{ type, content }` shape and
+/// none of their `type`s overlap
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ServerMessage {
+    SingleClient(ServerMessageSingleClient),
+    AllClients(ServerMessageAllClients),
+}
+
+impl ServerMessage {
+    /// Decodes one text frame from the server. A batch comes out as the messages inside
+    /// it, in the order they were sent, followed by the ones sent to every client.
+    pub fn decode_all(text: &str) -> Result<Vec<ServerMessage>, serde_json::Error> {
+        Ok(match serde_json::from_str(text)? {
+            ServerMessage::SingleClient(ServerMessageSingleClient::Batch(batch)) => batch
+                .messages
+                .into_iter()
+                .map(ServerMessage::SingleClient)
+                .chain(batch.broadcasts.into_iter().map(ServerMessage::AllClients))
+                .collect(),
+            message => vec![message],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches_are_unpacked() {
+        let text = r#"{"type":"batch","content":{"tick":7,"messages":[
+            {"type":"centreCamera","content":{"x":1,"y":2}},
+            {"type":"resumeFailed"}
+        ],"broadcasts":[
+            {"type":"moveCount","content":4}
+        ]}}"#;
+
+        let messages = ServerMessage::decode_all(text).unwrap();
+
+        assert!(matches!(
+            messages.as_slice(),
+            [
+                ServerMessage::SingleClient(ServerMessageSingleClient::CentreCamera(_)),
+                ServerMessage::SingleClient(ServerMessageSingleClient::ResumeFailed),
+                ServerMessage::AllClients(ServerMessageAllClients::MoveCount(4))
+            ]
+        ));
+    }
+
+    #[test]
+    fn messages_for_everyone_are_recognised() {
+        let text = r#"{"type":"shutdownNotice","content":{"secondsRemaining":3}}"#;
+
+        let messages = ServerMessage::decode_all(text).unwrap();
+
+        assert!(matches!(
+            messages.as_slice(),
+            [ServerMessage::AllClients(
+                ServerMessageAllClients::ShutdownNotice {
+                    seconds_remaining: 3
+                }
+            )]
+        ));
+        assert!(ServerMessage::decode_all(r#"{"type":"nonsense"}"#).is_err());
+    }
+}
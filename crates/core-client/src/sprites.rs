@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use ae_position::Position;
+use core_api::{EntityIndex, ServerMessageSingleClient, SpriteTexture};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sprite {
+    pub pos: Position,
+    pub texture: SpriteTexture,
+}
+
+/// The sprites a client would be drawing, rebuilt from the messages it has received
+#[derive(Debug, Default, Clone)]
+pub struct SpriteMirror {
+    sprites: HashMap<EntityIndex, Sprite>,
+    camera: Option<Position>,
+}
+
+impl SpriteMirror {
+    /// Updates the mirror from a message, anything that doesn't move sprites is ignored
+    pub fn apply(&mut self, message: &ServerMessageSingleClient) {
+        match message {
+            ServerMessageSingleClient::UpdateFullGameMap { camera, entities } => {
+                self.camera = Some(camera.clone());
+                self.sprites = entities
+                    .iter()
+                    .map(|update| {
+                        (
+                            update.entity,
+                            Sprite {
+                                pos: update.pos.clone(),
+                                texture: update.sprite,
+                            },
+                        )
+                    })
+                    .collect();
+            }
+            ServerMessageSingleClient::AddSprite(update)
+            | ServerMessageSingleClient::EntityPositionChange(update) => {
+                self.sprites.insert(
+                    update.entity,
+                    Sprite {
+                        pos: update.pos.clone(),
+                        texture: update.sprite,
+                    },
+                );
+            }
+            ServerMessageSingleClient::RemoveSprite(entity) => {
+                self.sprites.remove(entity);
+            }
+            ServerMessageSingleClient::CentreCamera(camera) => {
+                self.camera = Some(camera.clone());
+            }
+            _ => {}
+        }
+    }
+
+    pub fn get(&self, entity: EntityIndex) -> Option<&Sprite> {
+        self.sprites.get(&entity)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityIndex, &Sprite)> {
+        self.sprites
+            .iter()
+            .map(|(entity, sprite)| (*entity, sprite))
+    }
+
+    /// Every sprite standing on `pos`
+    pub fn at<'a>(&'a self, pos: &'a Position) -> impl Iterator<Item = (EntityIndex, &'a Sprite)> {
+        self.iter().filter(move |(_, sprite)| sprite.pos == *pos)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+
+    /// Where the server last centred this client's camera, usually on its player
+    pub fn camera(&self) -> Option<&Position> {
+        self.camera.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_api::SpriteUpdate;
+
+    fn update(idx: u32, x: i32, y: i32, sprite: SpriteTexture) -> SpriteUpdate {
+        SpriteUpdate {
+            entity: EntityIndex { idx },
+            pos: Position { x, y },
+            sprite,
+        }
+    }
+
+    #[test]
+    fn mirror_follows_sprite_messages() {
+        let mut mirror = SpriteMirror::default();
+
+        mirror.apply(&ServerMessageSingleClient::UpdateFullGameMap {
+            camera: Position { x: 5, y: 5 },
+            entities: vec![
+                update(1, 5, 5, SpriteTexture::PcBoneyBoi),
+                update(2, 6, 5, SpriteTexture::NpcRatFrames4),
+            ],
+        });
+        mirror.apply(&ServerMessageSingleClient::AddSprite(update(
+            3,
+            7,
+            7,
+            SpriteTexture::NpcSlime,
+        )));
+        mirror.apply(&ServerMessageSingleClient::EntityPositionChange(update(
+            2,
+            6,
+            6,
+            SpriteTexture::NpcRatFrames4,
+        )));
+        mirror.apply(&ServerMessageSingleClient::RemoveSprite(EntityIndex {
+            idx: 1,
+        }));
+
+        assert_eq!(mirror.len(), 2);
+        assert_eq!(mirror.camera(), Some(&Position { x: 5, y: 5 }));
+        assert_eq!(mirror.get(EntityIndex { idx: 1 }), None);
+        assert_eq!(
+            mirror.get(EntityIndex { idx: 2 }).unwrap().pos,
+            Position { x: 6, y: 6 }
+        );
+        assert_eq!(mirror.at(&Position { x: 7, y: 7 }).count(), 1);
+
+        // A full map update replaces everything that came before it
+        mirror.apply(&ServerMessageSingleClient::UpdateFullGameMap {
+            camera: Position { x: 0, y: 0 },
+            entities: vec![],
+        });
+        assert!(mirror.is_empty());
+    }
+}
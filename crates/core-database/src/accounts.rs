@@ -0,0 +1,285 @@
+use ring::{
+    digest, pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::{fmt, num::NonZeroU32};
+
+use crate::DatabaseLock;
+
+const MAX_NAME_LENGTH: usize = 20;
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+const HASH_SCHEME: &str = "pbkdf2-sha256";
+const HASH_ITERATIONS: u32 = 100_000;
+const SALT_LENGTH: usize = 16;
+const HASH_LENGTH: usize = digest::SHA256_OUTPUT_LEN;
+
+/// SQLite's extended result code for a broken UNIQUE constraint
+const SQLITE_CONSTRAINT_UNIQUE: &str = "2067";
+
+/// A row of the accounts table, without the password hash
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(Debug)]
+pub enum AccountError {
+    InvalidName,
+    PasswordTooShort,
+    NameTaken,
+    /// Deliberately doesn't say whether it was the name or the password that was wrong
+    WrongNameOrPassword,
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountError::InvalidName => write!(
+                f,
+                "Names must be 1 to {} letters, numbers, spaces, dashes or underscores",
+                MAX_NAME_LENGTH
+            ),
+            AccountError::PasswordTooShort => write!(
+                f,
+                "Passwords must be at least {} characters",
+                MIN_PASSWORD_LENGTH
+            ),
+            AccountError::NameTaken => write!(f, "That name is already taken"),
+            AccountError::WrongNameOrPassword => write!(f, "Wrong name or password"),
+            AccountError::Database(_) => write!(f, "Something went wrong, please try again"),
+        }
+    }
+}
+
+impl From<sqlx::Error> for AccountError {
+    fn from(error: sqlx::Error) -> Self {
+        AccountError::Database(error)
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    let length = name.chars().count();
+
+    (1..=MAX_NAME_LENGTH).contains(&length)
+        && name.trim() == name
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_')
+}
+
+/// Salts and hashes a password into `<scheme>$<iterations>$<salt>$<hash>` so the
+/// iteration count can be raised later without breaking existing accounts
+fn hash_password(password: &str) -> String {
+    let mut salt = [0u8; SALT_LENGTH];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .expect("Couldn't generate a password salt");
+
+    let mut hash = [0u8; HASH_LENGTH];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(HASH_ITERATIONS).unwrap(),
+        &salt,
+        password.as_bytes(),
+        &mut hash,
+    );
+
+    format!(
+        "{}${}${}${}",
+        HASH_SCHEME,
+        HASH_ITERATIONS,
+        hex::encode(salt),
+        hex::encode(hash)
+    )
+}
+
+/// Checks a password against a hash made by `hash_password`, in constant time
+fn verify_password(password: &str, password_hash: &str) -> bool {
+    let mut parts = password_hash.split('$');
+
+    let (Some(HASH_SCHEME), Some(iterations), Some(salt), Some(hash), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return false;
+    };
+
+    let (Ok(iterations), Ok(salt), Ok(hash)) = (
+        iterations.parse::<NonZeroU32>(),
+        hex::decode(salt),
+        hex::decode(hash),
+    ) else {
+        return false;
+    };
+
+    pbkdf2::verify(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        &salt,
+        password.as_bytes(),
+        &hash,
+    )
+    .is_ok()
+}
+
+/// Hashing is deliberately slow, so it's kept off the threads running the async tasks
+async fn run_blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    tokio::task::spawn_blocking(f)
+        .await
+        .expect("Password hashing panicked")
+}
+
+/// Creates a new account, only the hash of the password is stored
+pub async fn register_account(
+    db: &DatabaseLock,
+    name: &str,
+    password: &str,
+) -> Result<Account, AccountError> {
+    if !valid_name(name) {
+        return Err(AccountError::InvalidName);
+    }
+
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AccountError::PasswordTooShort);
+    }
+
+    let password = password.to_string();
+    let password_hash = run_blocking(move || hash_password(&password)).await;
+
+    let db = db.read().await;
+
+    let result = sqlx::query!(
+        "INSERT INTO accounts (name, password_hash) VALUES (?, ?)",
+        name,
+        password_hash
+    )
+    .execute(&db.0)
+    .await;
+
+    match result {
+        Ok(done) => Ok(Account {
+            id: done.last_insert_rowid(),
+            name: name.to_string(),
+        }),
+        Err(sqlx::Error::Database(error))
+            if error.code().as_deref() == Some(SQLITE_CONSTRAINT_UNIQUE) =>
+        {
+            Err(AccountError::NameTaken)
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Finds the account with this name (ignoring case) and checks its password
+pub async fn verify_login(
+    db: &DatabaseLock,
+    name: &str,
+    password: &str,
+) -> Result<Account, AccountError> {
+    let row = {
+        let db = db.read().await;
+
+        sqlx::query!(
+            "SELECT id, name, password_hash FROM accounts WHERE name = ?",
+            name
+        )
+        .fetch_optional(&db.0)
+        .await?
+    };
+
+    let Some(row) = row else {
+        return Err(AccountError::WrongNameOrPassword);
+    };
+
+    let password = password.to_string();
+    let password_hash = row.password_hash;
+    let password_matches = run_blocking(move || verify_password(&password, &password_hash)).await;
+
+    if password_matches {
+        Ok(Account {
+            id: row.id,
+            name: row.name,
+        })
+    } else {
+        Err(AccountError::WrongNameOrPassword)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Database;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    async fn test_database() -> DatabaseLock {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        sqlx::migrate!("../../migrations").run(&pool).await.unwrap();
+
+        Arc::new(RwLock::new(Database(pool)))
+    }
+
+    #[test]
+    fn passwords_are_hashed_with_a_salt() {
+        let first = hash_password("hunter22");
+        let second = hash_password("hunter22");
+
+        assert_ne!(first, second);
+        assert!(!first.contains("hunter22"));
+        assert!(verify_password("hunter22", &first));
+        assert!(verify_password("hunter22", &second));
+        assert!(!verify_password("hunter23", &first));
+        assert!(!verify_password("hunter22", "not a hash"));
+    }
+
+    #[test]
+    fn names_are_checked() {
+        assert!(valid_name("Sewer Kid"));
+        assert!(valid_name("ant_boi-2"));
+        assert!(!valid_name(""));
+        assert!(!valid_name(" padded "));
+        assert!(!valid_name("<script>"));
+        assert!(!valid_name("a name that is much too long"));
+    }
+
+    #[tokio::test]
+    async fn register_then_login() {
+        let db = test_database().await;
+
+        let account = register_account(&db, "Ghost Boy", "boo boo boo")
+            .await
+            .unwrap();
+
+        let logged_in = verify_login(&db, "ghost boy", "boo boo boo").await.unwrap();
+        assert_eq!(logged_in.id, account.id);
+        assert_eq!(logged_in.name, "Ghost Boy");
+
+        assert!(matches!(
+            verify_login(&db, "Ghost Boy", "wrong password").await,
+            Err(AccountError::WrongNameOrPassword)
+        ));
+        assert!(matches!(
+            verify_login(&db, "Nobody", "boo boo boo").await,
+            Err(AccountError::WrongNameOrPassword)
+        ));
+        assert!(matches!(
+            register_account(&db, "GHOST BOY", "another password").await,
+            Err(AccountError::NameTaken)
+        ));
+        assert!(matches!(
+            register_account(&db, "Kidzilla", "short").await,
+            Err(AccountError::PasswordTooShort)
+        ));
+    }
+}
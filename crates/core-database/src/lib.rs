@@ -1,7 +1,28 @@
+pub mod accounts;
+
+use serde::Deserialize;
 use sqlx::SqlitePool;
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 use tokio::sync::RwLock;
 
+/// Where the database lives, read from the `database` section of the server config
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// The SQLite file, created if it doesn't exist
+    pub file: PathBuf,
+    pub max_connections: u32,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            file: PathBuf::from("database.sqlite"),
+            max_connections: 5,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Database(pub SqlitePool);
 
@@ -26,14 +47,14 @@ pub async fn increment_db_move_count_and_get_total(db: &DatabaseLock) -> i32 {
     move_count
 }
 
-pub async fn database_setup() -> DatabaseLock {
+pub async fn database_setup(config: &DatabaseConfig) -> DatabaseLock {
     // Database setup
     // Initiate a connection to the database file, creating the file if required.
     let database = sqlx::sqlite::SqlitePoolOptions::new()
-        .max_connections(5)
+        .max_connections(config.max_connections)
         .connect_with(
             sqlx::sqlite::SqliteConnectOptions::new()
-                .filename("database.sqlite")
+                .filename(&config.file)
                 .create_if_missing(true),
         )
         .await
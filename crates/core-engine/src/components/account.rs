@@ -0,0 +1,8 @@
+use bevy::prelude::*;
+
+/// The account a player entity belongs to, which stays the same across connections
+#[derive(Component, Debug, Clone)]
+pub struct Account {
+    pub id: i64,
+    pub name: String,
+}
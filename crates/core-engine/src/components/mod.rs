@@ -1,3 +1,4 @@
+pub mod account;
 pub mod combat_stats;
 pub mod eyes;
 pub mod hp;
@@ -6,6 +7,7 @@ pub mod intend_melee_attack;
 pub mod intend_move;
 pub mod intend_speak;
 pub mod paths;
+pub mod session;
 pub mod speaks;
 use ae_position::Position;
 use bevy::prelude::Component;
@@ -27,6 +29,14 @@ pub struct BlocksLight;
 #[derive(Component)]
 pub struct BlocksMovement;
 
+/// Drawn under everything else on its tile
+#[derive(Component)]
+pub struct Floor;
+
+/// Shown when the entity is hovered
+#[derive(Component)]
+pub struct Description(pub String);
+
 #[derive(Component)]
 pub struct Renderable {
     pub texture: SpriteTexture,
@@ -44,3 +54,13 @@ pub struct Bones;
 
 #[derive(Component)]
 pub struct Enemy;
+
+/// A tile enemies can appear on, in preference to anywhere else on its map
+#[derive(Component)]
+pub struct SpawnPoint;
+
+/// A tile that leads somewhere else, possibly on another map
+#[derive(Component)]
+pub struct Portal {
+    pub destination: MapPosition,
+}
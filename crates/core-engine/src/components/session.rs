@@ -0,0 +1,29 @@
+use bevy::prelude::*;
+use rand::{distributions::Alphanumeric, Rng};
+
+const SESSION_TOKEN_LENGTH: usize = 32;
+
+/// Identifies the player entity a reconnecting client is allowed to take back
+#[derive(Component, Debug)]
+pub struct Session {
+    pub token: String,
+}
+
+impl Session {
+    /// A new session with a random token that is hard to guess
+    pub fn generate() -> Self {
+        let token = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(SESSION_TOKEN_LENGTH)
+            .map(char::from)
+            .collect();
+
+        Self { token }
+    }
+}
+
+/// A player whose socket has closed, kept in the world until the grace period runs out
+#[derive(Component, Debug)]
+pub struct Disconnected {
+    pub time_remaining: f32,
+}
@@ -1,5 +1,6 @@
 use bevy::prelude::*;
-use core_api::DialogueMap;
 
+/// The name of the conversation in `DialogueContents` to show when talked to, looked up
+/// each time so reloaded dialogue is used straight away
 #[derive(Component)]
-pub struct Speaks(pub DialogueMap);
+pub struct Speaks(pub String);
@@ -1,12 +1,9 @@
+use std::collections::HashMap;
+
 use bevy::prelude::Resource;
 use core_api::DialogueMap;
 use serde::{Deserialize, Serialize};
 
+/// Every conversation in the game, map legends refer to them by name
 #[derive(Debug, Deserialize, Serialize, Resource, Clone)]
-pub struct DialogueContents {
-    pub rat: DialogueMap,
-    pub sewer_kid: DialogueMap,
-    pub grace_jones: DialogueMap,
-    pub voidcat: DialogueMap,
-    pub real_estate_dick: DialogueMap,
-}
+pub struct DialogueContents(pub HashMap<String, DialogueMap>);
@@ -0,0 +1,208 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::de::DeserializeOwned;
+
+use crate::{
+    components::hp::Hp,
+    data::{
+        dialogue_contents::DialogueContents, enemy_configs::EnemyConfigs,
+        player_configs::PlayerConfigs,
+    },
+};
+
+pub const PLAYER_CONFIGS_FILE: &str = "player_configs.ron";
+pub const ENEMY_CONFIGS_FILE: &str = "enemy_configs.ron";
+pub const DIALOGUE_CONTENTS_FILE: &str = "dialogue_contents.ron";
+
+/// The game's balancing and conversations, read from the data directory so they can be
+/// changed without a rebuild and reloaded while the game runs
+pub struct GameData {
+    pub player_configs: PlayerConfigs,
+    pub enemy_configs: EnemyConfigs,
+    pub dialogue_contents: DialogueContents,
+}
+
+/// Why the game data could not be loaded
+#[derive(Debug)]
+pub enum DataError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        error: ron::error::SpannedError,
+    },
+    Invalid {
+        path: PathBuf,
+        reason: String,
+    },
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataError::Io { path, error } => {
+                write!(f, "Couldn't read {}: {}", path.display(), error)
+            }
+            DataError::Parse { path, error } => {
+                write!(f, "{} is not valid: {}", path.display(), error)
+            }
+            DataError::Invalid { path, reason } => {
+                write!(f, "{} is not valid: {}", path.display(), reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+fn read<T: DeserializeOwned>(dir: &Path, file: &str) -> Result<(PathBuf, T), DataError> {
+    let path = dir.join(file);
+    let text = fs::read_to_string(&path).map_err(|error| DataError::Io {
+        path: path.clone(),
+        error,
+    })?;
+    let data = ron::from_str(&text).map_err(|error| DataError::Parse {
+        path: path.clone(),
+        error,
+    })?;
+
+    Ok((path, data))
+}
+
+/// Stats that would break the game even though they parse
+fn check_stats(name: &str, hp: &Hp, attack_time: f32, move_time: f32) -> Result<(), String> {
+    if !(1..=hp.max).contains(&hp.current) {
+        return Err(format!(
+            "{} has {}/{} hp, it needs at least 1 and no more than its max",
+            name, hp.current, hp.max
+        ));
+    }
+
+    for (time, setting) in [(attack_time, "attack_time"), (move_time, "move_time")] {
+        if time.is_nan() || time < 0.0 {
+            return Err(format!(
+                "{} has a {} of {}, it needs to be 0 or more",
+                name, setting, time
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn check_players(configs: &PlayerConfigs) -> Result<(), String> {
+    for (name, config) in [
+        ("ghost_boy", &configs.ghost_boy),
+        ("kidzilla", &configs.kidzilla),
+        ("sewer_kid", &configs.sewer_kid),
+        ("boney_boy", &configs.boney_boy),
+        ("ant_boy", &configs.ant_boy),
+    ] {
+        check_stats(name, &config.hp, config.attack_time, config.move_time)?;
+    }
+
+    Ok(())
+}
+
+fn check_enemies(configs: &EnemyConfigs) -> Result<(), String> {
+    for (name, config) in [
+        ("slime", &configs.slime),
+        ("rat_king", &configs.rat_king),
+        ("rat", &configs.rat),
+    ] {
+        check_stats(name, &config.hp, config.attack_time, config.move_time)?;
+    }
+
+    Ok(())
+}
+
+fn check_dialogue(dialogue_contents: &DialogueContents) -> Result<(), String> {
+    for (name, conversation) in &dialogue_contents.0 {
+        for (id, content) in &conversation.0 {
+            for response_id in [content.response_1_id, content.response_2_id]
+                .into_iter()
+                .flatten()
+            {
+                if !conversation.0.contains_key(&response_id) {
+                    return Err(format!(
+                        "a response to line {} of \"{}\" leads to line {}, which does not exist",
+                        id, name, response_id
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn checked<T>(
+    (path, data): (PathBuf, T),
+    check: impl FnOnce(&T) -> Result<(), String>,
+) -> Result<T, DataError> {
+    match check(&data) {
+        Ok(()) => Ok(data),
+        Err(reason) => Err(DataError::Invalid { path, reason }),
+    }
+}
+
+/// Reads and checks every data file, nothing is returned unless all of them are fine
+pub fn load_game_data(dir: &Path) -> Result<GameData, DataError> {
+    Ok(GameData {
+        player_configs: checked(read(dir, PLAYER_CONFIGS_FILE)?, check_players)?,
+        enemy_configs: checked(read(dir, ENEMY_CONFIGS_FILE)?, check_enemies)?,
+        dialogue_contents: checked(read(dir, DIALOGUE_CONTENTS_FILE)?, check_dialogue)?,
+    })
+}
+
+/// When a data file was last saved, to notice that one has changed
+pub fn last_modified(dir: &Path) -> Option<SystemTime> {
+    [
+        PLAYER_CONFIGS_FILE,
+        ENEMY_CONFIGS_FILE,
+        DIALOGUE_CONTENTS_FILE,
+    ]
+    .iter()
+    .filter_map(|file| fs::metadata(dir.join(file)).ok()?.modified().ok())
+    .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../../data")
+    }
+
+    #[test]
+    fn the_data_that_ships_with_the_game_loads() {
+        let data = load_game_data(&data_dir()).unwrap();
+        assert!(data.dialogue_contents.0.contains_key("rat"));
+    }
+
+    #[test]
+    fn mistakes_in_the_data_are_explained() {
+        let mut enemy_configs: EnemyConfigs = read(&data_dir(), ENEMY_CONFIGS_FILE).unwrap().1;
+        enemy_configs.rat.hp.current = 0;
+        assert_eq!(
+            check_enemies(&enemy_configs).unwrap_err(),
+            "rat has 0/10 hp, it needs at least 1 and no more than its max"
+        );
+
+        let mut dialogue_contents: DialogueContents =
+            read(&data_dir(), DIALOGUE_CONTENTS_FILE).unwrap().1;
+        let rat = dialogue_contents.0.get_mut("rat").unwrap();
+        rat.0.get_mut(&0).unwrap().response_1_id = Some(9);
+        assert_eq!(
+            check_dialogue(&dialogue_contents).unwrap_err(),
+            "a response to line 0 of \"rat\" leads to line 9, which does not exist"
+        );
+    }
+}
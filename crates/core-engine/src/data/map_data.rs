@@ -1,184 +1,512 @@
-use ae_position::Dimensions2d;
-use core_api::{DialogueMap, SpriteTexture};
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
 
-use crate::data::{dialogue_contents::DialogueContents, dialogue_contents_str};
+use ae_position::{Dimensions2d, Position};
+use core_api::{MapGeneratorSettings, SpriteTexture};
+use serde::Deserialize;
 
-// Utility
+use crate::{
+    data::{
+        dialogue_contents::DialogueContents,
+        map_layout::{MapLayout, MapObject},
+        tile_properties::TileProperties,
+        tiled, TILE_PROPERTIES,
+    },
+    generation,
+    resources::config::EngineConfig,
+};
 
-pub fn str_map_dimensions(str_map: &str) -> Dimensions2d {
-    let lines = str_map
-        .lines()
-        .filter_map(|line| {
-            let trimmed_line = line.trim();
+/// What a character in a map's layout stands for
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LegendEntry {
+    /// Placed on top of the floor, `empty` for bare floor
+    pub sprite: SpriteTexture,
+    /// The name of a conversation in `dialogue_contents.ron`, for NPCs that can be talked to
+    #[serde(default)]
+    pub dialogue: Option<String>,
+    /// Where stepping onto the tile leads, for sprites that can be warped through
+    #[serde(default)]
+    pub portal: Option<PortalDestination>,
+}
+
+/// A tile on a map, possibly this one, that a portal leads to
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PortalDestination {
+    pub map: i32,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// One map drawn as text, read from a `.ron` file in the maps directory
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MapData {
+    pub id: i32,
+    pub name: String,
+    /// Goes under every tile, whatever else is on it
+    pub floor: SpriteTexture,
+    pub legend: HashMap<char, LegendEntry>,
+    /// One character per tile. Blank lines and whitespace around each row are ignored.
+    pub layout: String,
+}
+
+/// A map laid out by a map generator when the server starts, read from a `.ron` file in
+/// the maps directory
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GeneratedMap {
+    pub id: i32,
+    pub name: String,
+    pub generator: MapGeneratorSettings,
+}
+
+impl MapData {
+    fn rows(&self) -> impl Iterator<Item = &str> {
+        self.layout
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+    }
+
+    pub fn dimensions(&self) -> Dimensions2d {
+        Dimensions2d {
+            width: self.rows().next().map_or(0, |row| row.chars().count()) as i32,
+            height: self.rows().count() as i32,
+        }
+    }
 
-            (!trimmed_line.is_empty()).then_some(trimmed_line)
+    /// Every tile in the layout along with what is on it
+    pub fn tiles(&self) -> impl Iterator<Item = (Position, &LegendEntry)> {
+        self.rows().enumerate().flat_map(move |(y, row)| {
+            row.chars().enumerate().map(move |(x, character)| {
+                let pos = Position {
+                    x: x as i32,
+                    y: y as i32,
+                };
+
+                (pos, &self.legend[&character])
+            })
         })
-        .collect::<Vec<_>>();
+    }
+
+    /// Catches anything that would stop the map being built, so `tiles` can't fail
+    fn validate(&self, dialogue_contents: &DialogueContents) -> Result<(), String> {
+        let width = self.dimensions().width as usize;
+        if width == 0 {
+            return Err("the layout is empty".to_string());
+        }
+
+        for (y, row) in self.rows().enumerate() {
+            let row_width = row.chars().count();
+            if row_width != width {
+                return Err(format!(
+                    "row {} is {} tiles wide but the first row is {}",
+                    y + 1,
+                    row_width,
+                    width
+                ));
+            }
+
+            if let Some((x, character)) = row
+                .chars()
+                .enumerate()
+                .find(|(_, character)| !self.legend.contains_key(character))
+            {
+                return Err(format!(
+                    "'{}' in row {}, column {} is not in the legend",
+                    character,
+                    y + 1,
+                    x + 1
+                ));
+            }
+        }
+
+        for (character, entry) in &self.legend {
+            if let Some(name) = &entry.dialogue {
+                if !dialogue_contents.0.contains_key(name) {
+                    return Err(format!(
+                        "'{}' has the dialogue \"{}\" which is not in dialogue_contents.ron",
+                        character, name
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A floor on every tile with the legend's sprites and portals on top
+    pub fn layout(&self) -> MapLayout {
+        let mut objects = Vec::new();
+        for (pos, entry) in self.tiles() {
+            objects.push((pos.clone(), MapObject::Floor(self.floor)));
+
+            if entry.sprite != SpriteTexture::Empty {
+                objects.push((
+                    pos.clone(),
+                    MapObject::Sprite {
+                        sprite: entry.sprite,
+                        dialogue: entry.dialogue.clone(),
+                    },
+                ));
+            }
 
-    let height = lines.len();
-    let width = lines[0].chars().count();
+            if let Some(portal) = &entry.portal {
+                objects.push((
+                    pos,
+                    MapObject::Portal {
+                        map_id: portal.map,
+                        pos: Position {
+                            x: portal.x,
+                            y: portal.y,
+                        },
+                    },
+                ));
+            }
+        }
 
-    Dimensions2d {
-        width: width as i32,
-        height: height as i32,
+        MapLayout {
+            id: self.id,
+            name: self.name.clone(),
+            dimensions: self.dimensions(),
+            objects,
+        }
     }
 }
 
-// EXAMPLE_MAP_1
-
-pub const DEFAULT_FLOOR_MAP_1: SpriteTexture = SpriteTexture::FloorGrass;
-
-pub const OLD_EXAMPLE_MAP_1: &str = r#"
-#################################################BBBBBBBBBBBBBBBB
-#q.#######........#..........144.............CCABBBBBBBBBBBBBBBBB
-#.......##...ww...#..........3...............CCABBBBBBBBBBBBBBBBB
-##...........www..#.......@..244......o......CCABBBBBBBBBBBBBBBBB
-##......##........#..........y.z.............CCABBBBBBBBBBBBBBBBB
-##......##........#..hh...............n......CCABBBBBBBBBBBBBBBBB
-##......##........#..h.......................CCABBBBBBBBBBBBBBBBB
-##.............s..#............x......m......CCABBBBBBBBBBBBBBBBB
-##########...................................CCABBBBBBBBBBBBBBBBB
-#########################.............l......CCABBBBBBBBBBBBBBBBB
-#............................................CCABBBBBBBBBBBBBBBBB
-#.................a.b.c.d.e.f.g.h.i.j.k......CCABBBBBBBBBBBBBBBBB
-#.........p...r.s.t.u.v......................CCABBBBBBBBBBBBBBBBB
-#............................................CCABBBBBBBBBBBBBBBBB
-############################################.CCABBBBBBBBBBBBBBBBB
-#################################################BBBBBBBBBBBBBBBB
-"#;
-
-pub const EXAMPLE_MAP_1: &str = r#"
-#################################################################
-#qy########.....................................................#
-#........##.....................................................#
-#........##.....................................................#
-#........##.........144...1444444444............................#
-#.........#.........3.....3.....................................#
-#...................3.....3...................CCCCCCCCCCCCCCCCCC#
-#............................................CCABBBBBBBBBBBBBBBBB
-#...................3........................CCABBBBBBBBBBBBBBBBB
-#...................3........................CCABBBBBBBBBBBBBBBBB
-#55555555...........244444444444.............CCABBBBBBBBBBBBBBBBB
-#55555555....................................CCABBBBBBBBBBBBBBBBB
-#55555555555.................................CCABBBBBBBBBBBBBBBBB
-#55555555555.................................CCABBBBBBBBBBBBBBBBB
-#55555555555.................................CCABBBBBBBBBBBBBBBBB
-#################################################################
-"#;
-
-pub fn example_map_1_legend(character: char) -> (SpriteTexture, Option<DialogueMap>) {
-    match character {
-        '#' => (SpriteTexture::WallStone, None),
-        's' => (SpriteTexture::ObjectSewerGrate, None),
-        '.' => (SpriteTexture::Empty, None),
-        'w' => (SpriteTexture::ObjectWater, None),
-        'a' => (SpriteTexture::NpcFatherNeilFrames6, None),
-        'b' => (
-            SpriteTexture::NpcFootballFrames4,
-            Some(
-                ron::from_str::<DialogueContents>(dialogue_contents_str)
-                    .unwrap()
-                    .sewer_kid,
-            ),
-        ),
-        'c' => (SpriteTexture::NpcGoon1Frames4, None),
-        'd' => (SpriteTexture::NpcGoon2Frames4, None),
-        'e' => (SpriteTexture::NpcGoon3Frames4, None),
-        'f' => (SpriteTexture::NpcGoon4Frames4, None),
-        'g' => (
-            SpriteTexture::NpcGraceJonesFrames6,
-            Some(
-                ron::from_str::<DialogueContents>(dialogue_contents_str)
-                    .unwrap()
-                    .grace_jones,
-            ),
-        ),
-        'h' => (SpriteTexture::NpcKingRatFrames4, None),
-        'i' => (SpriteTexture::NpcMallChick1Frames6, None),
-        'j' => (SpriteTexture::NpcMallChick2Frames6, None),
-        'k' => (SpriteTexture::NpcPersonFrames2, None),
-        'l' => (
-            SpriteTexture::NpcRatFrames4,
-            Some(
-                ron::from_str::<DialogueContents>(dialogue_contents_str)
-                    .unwrap()
-                    .voidcat,
-            ),
-        ),
-        'm' => (SpriteTexture::PcSewerKidFrames6, None),
-        'n' => (
-            SpriteTexture::NpcSmallRatFrames6,
-            Some(
-                ron::from_str::<DialogueContents>(dialogue_contents_str)
-                    .unwrap()
-                    .voidcat,
+/// Why the maps could not be loaded
+#[derive(Debug)]
+pub enum MapError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        error: ron::error::SpannedError,
+    },
+    Invalid {
+        path: PathBuf,
+        reason: String,
+    },
+    DuplicateId {
+        id: i32,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    /// A map the engine config needs is not in the maps directory
+    Missing {
+        id: i32,
+        setting: &'static str,
+    },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io { path, error } => {
+                write!(f, "Couldn't read {}: {}", path.display(), error)
+            }
+            MapError::Parse { path, error } => {
+                write!(f, "{} is not a valid map: {}", path.display(), error)
+            }
+            MapError::Invalid { path, reason } => {
+                write!(f, "{} is not a valid map: {}", path.display(), reason)
+            }
+            MapError::DuplicateId { id, first, second } => write!(
+                f,
+                "{} and {} both have the map id {}",
+                first.display(),
+                second.display(),
+                id
             ),
-        ),
-        'o' => (SpriteTexture::ObjectLadderDown, None),
-        'p' => (SpriteTexture::ObjectLadderUp, None),
-        'q' => (SpriteTexture::ObjectWarpTeeveeFrames3, None),
-        'r' => (SpriteTexture::ObjectWindow, None),
-        'x' => (SpriteTexture::PcAntBoiFrames4, None),
-        't' => (SpriteTexture::PcBoneyBoiFrames4, None),
-        'u' => (SpriteTexture::PcGhostBoyFrames8, None),
-        'v' => (SpriteTexture::FloorSlime, None),
-        'y' => (
-            SpriteTexture::NpcRealEstateDickFrames21,
-            Some(
-                ron::from_str::<DialogueContents>(dialogue_contents_str)
-                    .unwrap()
-                    .real_estate_dick,
+            MapError::Missing { id, setting } => write!(
+                f,
+                "engine.{} is {} but there is no map with that id",
+                setting, id
             ),
-        ),
-        'z' => (SpriteTexture::ObjectNewspaper, None),
-        '@' => (SpriteTexture::ObjectRedSoda, None),
-        'A' => (SpriteTexture::ObjectShoreFrames4, None),
-        'B' => (SpriteTexture::ObjectWaterFrames4, None),
-        'C' => (SpriteTexture::ObjectSand, None),
-        '1' => (SpriteTexture::WallFenceCornerIn, None),
-        '2' => (SpriteTexture::WallFenceCornerOut, None),
-        '3' => (SpriteTexture::WallFenceVertical, None),
-        '4' => (SpriteTexture::WallFenceHorizontal, None),
-        '5' => (SpriteTexture::ObjectWood, None),
-        _ => panic!(
-            "Encountered an unrecognized character on map 1: {}",
-            character
-        ),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+pub(crate) fn read_error(path: &Path) -> impl FnOnce(io::Error) -> MapError + '_ {
+    move |error| MapError::Io {
+        path: path.to_path_buf(),
+        error,
+    }
+}
+
+fn load_ron(
+    path: &Path,
+    dialogue_contents: &DialogueContents,
+    config: &EngineConfig,
+) -> Result<MapLayout, MapError> {
+    let text = fs::read_to_string(path).map_err(read_error(path))?;
+    if ron_struct_name(&text) == "GeneratedMap" {
+        return load_generated(path, &text, config);
+    }
+
+    let map = ron::from_str::<MapData>(&text).map_err(|error| MapError::Parse {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    map.validate(dialogue_contents)
+        .map_err(|reason| MapError::Invalid {
+            path: path.to_path_buf(),
+            reason,
+        })?;
+
+    Ok(map.layout())
+}
+
+/// The name of the struct a RON file holds, skipping any comments and attributes before it
+fn ron_struct_name(text: &str) -> &str {
+    let mut rest = text.trim_start();
+    loop {
+        if let Some(comment) = rest.strip_prefix("//") {
+            rest = comment.split_once('\n').map_or("", |(_, after)| after);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.split_once("*/").map_or("", |(_, after)| after);
+        } else if let Some(attribute) = rest.strip_prefix("#!") {
+            rest = attribute.split_once(']').map_or("", |(_, after)| after);
+        } else {
+            break;
+        }
+        rest = rest.trim_start();
     }
+
+    let end = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    &rest[..end]
 }
 
-// EXAMPLE_MAP_2
-
-pub const DEFAULT_FLOOR_MAP_2: SpriteTexture = SpriteTexture::FloorConcrete;
-
-pub const EXAMPLE_MAP_2: &str = r#"
-#############################################
-#t.#######..........................#.......#
-#..................#.........g......#.......#
-#...#######........#................#.......#
-#...##....#......###........#########.......#
-#.........#.....................#...........#
-#....g....#.....................#...........#
-#.........#.....................#......g....#
-#.........#............g....................#
-#...........................................#
-#...................#............##.........#
-#...................####.........##.........#
-#..######..............#..........#.........#
-#.......#####.................g.............#
-#...........................................#
-#############################################
-"#;
-
-pub fn example_map_2_legend(character: char) -> (SpriteTexture, Option<DialogueMap>) {
-    match character {
-        '#' => (SpriteTexture::WallBrick, None),
-        't' => (SpriteTexture::ObjectWarpTeeveeFrames3, None),
-        '.' => (SpriteTexture::Empty, None),
-        'g' => (SpriteTexture::ObjectSewerGrate, None),
-        _ => panic!(
-            "Encountered an unrecognized character on map 2: {}",
-            character
-        ),
+/// Generates the map a `GeneratedMap` file describes. Without a seed in the file the map
+/// follows the engine's seed, so a replay gets the same map, and is new every run otherwise.
+fn load_generated(path: &Path, text: &str, config: &EngineConfig) -> Result<MapLayout, MapError> {
+    let map = ron::from_str::<GeneratedMap>(text).map_err(|error| MapError::Parse {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    let seed = map.generator.seed.unwrap_or_else(|| match config.rng_seed {
+        // Mixed with the id so maps with the same settings still differ
+        Some(seed) => seed ^ (map.id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15),
+        None => rand::random(),
+    });
+
+    generation::generate(map.id, &map.name, &map.generator, seed).map_err(|reason| {
+        MapError::Invalid {
+            path: path.to_path_buf(),
+            reason,
+        }
+    })
+}
+
+/// Reads every map in the configured maps directory, in file name order, and checks that the
+/// maps the config refers to are among them and that their dialogue exists. Maps are either
+/// text maps or generated maps in `.ron` files, or Tiled maps in `.tmj` or `.tmx` files.
+pub fn load_maps(
+    config: &EngineConfig,
+    dialogue_contents: &DialogueContents,
+) -> Result<Vec<MapLayout>, MapError> {
+    let dir = config.maps_dir.as_path();
+    let mut paths = fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(read_error(dir))?;
+    paths.retain(|path| {
+        path.extension().map_or(false, |extension| {
+            extension == "ron" || extension == "tmj" || extension == "tmx"
+        })
+    });
+    paths.sort();
+
+    let tile_properties = ron::from_str::<TileProperties>(TILE_PROPERTIES)
+        .expect("tile_properties.ron is compiled in and should always parse");
+
+    let mut maps: Vec<(PathBuf, MapLayout)> = Vec::new();
+    for path in paths {
+        let map = if path
+            .extension()
+            .map_or(false, |extension| extension == "ron")
+        {
+            load_ron(&path, dialogue_contents, config)?
+        } else {
+            tiled::load(&path)?
+        };
+
+        if let Err(reason) = map.validate(dialogue_contents, &tile_properties) {
+            return Err(MapError::Invalid { path, reason });
+        }
+
+        if let Some((first, _)) = maps.iter().find(|(_, other)| other.id == map.id) {
+            return Err(MapError::DuplicateId {
+                id: map.id,
+                first: first.clone(),
+                second: path,
+            });
+        }
+
+        maps.push((path, map));
+    }
+
+    for (path, map) in &maps {
+        for (pos, map_id, destination) in map.portals() {
+            let reason = match maps.iter().find(|(_, other)| other.id == map_id) {
+                Some((_, other)) if !other.contains(destination) => "which is off the map",
+                Some((_, other)) if other.movement_blocked(destination, &tile_properties) => {
+                    "where something is in the way"
+                }
+                Some(_) => continue,
+                None => "which does not exist",
+            };
+
+            return Err(MapError::Invalid {
+                path: path.clone(),
+                reason: format!(
+                    "the portal at ({}, {}) leads to ({}, {}) on map {}, {}",
+                    pos.x, pos.y, destination.x, destination.y, map_id, reason
+                ),
+            });
+        }
+    }
+
+    for (id, setting) in [
+        (config.start_map_id, "start_map_id"),
+        (config.enemy_map_id, "enemy_map_id"),
+    ] {
+        if !maps.iter().any(|(_, map)| map.id == id) {
+            return Err(MapError::Missing { id, setting });
+        }
+    }
+
+    Ok(maps.into_iter().map(|(_, map)| map).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::game_data::load_game_data;
+
+    fn dialogue_contents() -> DialogueContents {
+        let data_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../data");
+        load_game_data(&data_dir).unwrap().dialogue_contents
+    }
+
+    fn map(layout: &str) -> MapData {
+        ron::from_str(&format!(
+            r#"MapData(
+                id: 1,
+                name: "Test",
+                floor: floorGrass,
+                legend: {{
+                    '#': (sprite: wallStone),
+                    '.': (sprite: empty),
+                    'r': (sprite: npcRatFrames4, dialogue: Some("rat")),
+                }},
+                layout: "{}",
+            )"#,
+            layout
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn layout_is_read_row_by_row() {
+        let map = map("\n  ###\n  #r.\n");
+        assert!(map.validate(&dialogue_contents()).is_ok());
+
+        let dimensions = map.dimensions();
+        assert_eq!((dimensions.width, dimensions.height), (3, 2));
+
+        let (pos, rat) = map
+            .tiles()
+            .find(|(_, entry)| entry.dialogue.is_some())
+            .unwrap();
+        assert_eq!((pos.x, pos.y), (1, 1));
+        assert_eq!(rat.sprite, SpriteTexture::NpcRatFrames4);
+    }
+
+    #[test]
+    fn mistakes_in_a_layout_are_explained() {
+        let reason = map("###\n#x.").validate(&dialogue_contents()).unwrap_err();
+        assert_eq!(reason, "'x' in row 2, column 2 is not in the legend");
+
+        let reason = map("###\n#.").validate(&dialogue_contents()).unwrap_err();
+        assert_eq!(reason, "row 2 is 2 tiles wide but the first row is 3");
+    }
+
+    #[test]
+    fn only_tiles_that_can_be_talked_to_get_dialogue() {
+        let tile_properties = ron::from_str(TILE_PROPERTIES).unwrap();
+        let mut map = map("###\n#r.");
+        assert!(map
+            .layout()
+            .validate(&dialogue_contents(), &tile_properties)
+            .is_ok());
+
+        map.legend.get_mut(&'#').unwrap().dialogue = Some("rat".to_string());
+        let reason = map
+            .layout()
+            .validate(&dialogue_contents(), &tile_properties)
+            .unwrap_err();
+        assert_eq!(
+            reason,
+            "the WallStone at (0, 0) has dialogue but can't be talked to"
+        );
+    }
+
+    #[test]
+    fn portals_need_something_to_warp_through() {
+        let tile_properties = ron::from_str(TILE_PROPERTIES).unwrap();
+        let mut map = map("###\n#r.");
+        map.legend.get_mut(&'.').unwrap().portal = Some(PortalDestination { map: 1, x: 1, y: 1 });
+        let reason = map
+            .layout()
+            .validate(&dialogue_contents(), &tile_properties)
+            .unwrap_err();
+        assert_eq!(
+            reason,
+            "the portal at (2, 1) isn't on anything with the Warp interaction"
+        );
+
+        map.legend.get_mut(&'.').unwrap().sprite = SpriteTexture::ObjectLadderDown;
+        assert!(map
+            .layout()
+            .validate(&dialogue_contents(), &tile_properties)
+            .is_ok());
+    }
+
+    #[test]
+    fn generated_maps_are_told_apart_from_text_maps() {
+        assert_eq!(ron_struct_name("MapData(id: 1)"), "MapData");
+        assert_eq!(
+            ron_struct_name("// Caves\n#![enable(implicit_some)]\n/* deep */ GeneratedMap(\n"),
+            "GeneratedMap"
+        );
+        assert_eq!(ron_struct_name("(id: 1)"), "");
+    }
+
+    #[test]
+    fn the_maps_that_ship_with_the_game_load() {
+        let config = EngineConfig {
+            maps_dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("../../maps"),
+            ..Default::default()
+        };
+
+        let maps = load_maps(&config, &dialogue_contents()).unwrap();
+        assert!(maps.iter().any(|map| map.id == config.start_map_id));
     }
 }
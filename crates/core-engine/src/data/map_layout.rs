@@ -0,0 +1,168 @@
+use ae_position::{Dimensions2d, Position};
+use core_api::{SpawnableEnemy, SpriteTexture};
+
+use crate::data::{
+    dialogue_contents::DialogueContents,
+    tile_properties::{Interaction, TileProperties},
+};
+
+/// Something placed on a tile when a map is built
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapObject {
+    /// Drawn under everything else on the tile, never in anyone's way
+    Floor(SpriteTexture),
+    /// Anything standing on the floor. `dialogue` names a conversation in
+    /// `dialogue_contents.ron`, for NPCs that can be talked to.
+    Sprite {
+        sprite: SpriteTexture,
+        dialogue: Option<String>,
+    },
+    /// Somewhere enemies can appear
+    SpawnPoint,
+    /// An enemy already waiting on the tile when the map is built
+    Enemy(SpawnableEnemy),
+    /// Leads to `pos` on the map with the id `map_id`
+    Portal { map_id: i32, pos: Position },
+}
+
+/// A map ready to be built, whichever format it was authored in
+#[derive(Debug, Clone)]
+pub struct MapLayout {
+    pub id: i32,
+    pub name: String,
+    pub dimensions: Dimensions2d,
+    /// Built in order, so floors come before whatever stands on them
+    pub objects: Vec<(Position, MapObject)>,
+}
+
+impl MapLayout {
+    pub fn contains(&self, pos: &Position) -> bool {
+        (0..self.dimensions.width).contains(&pos.x) && (0..self.dimensions.height).contains(&pos.y)
+    }
+
+    /// A copy of the map under another id, with portals that led elsewhere on the map now
+    /// leading elsewhere on the copy
+    pub fn instance(&self, id: i32) -> MapLayout {
+        let mut instance = self.clone();
+        instance.id = id;
+        for (_, object) in instance.objects.iter_mut() {
+            if let MapObject::Portal { map_id, .. } = object {
+                if *map_id == self.id {
+                    *map_id = id;
+                }
+            }
+        }
+        instance
+    }
+
+    /// Whether a sprite on the tile stands in the way of anything moving onto it
+    pub fn movement_blocked(&self, pos: &Position, tile_properties: &TileProperties) -> bool {
+        self.objects
+            .iter()
+            .any(|(object_pos, object)| match object {
+                MapObject::Sprite { sprite, .. } => {
+                    object_pos == pos
+                        && tile_properties
+                            .0
+                            .get(sprite)
+                            .map_or(false, |property| property.blocks_movement)
+                }
+                _ => false,
+            })
+    }
+
+    /// Whether a sprite on the tile sends players through the portal there
+    fn warps(&self, pos: &Position, tile_properties: &TileProperties) -> bool {
+        self.objects
+            .iter()
+            .any(|(object_pos, object)| match object {
+                MapObject::Sprite { sprite, .. } => {
+                    object_pos == pos
+                        && tile_properties
+                            .0
+                            .get(sprite)
+                            .map_or(false, |property| property.interaction == Interaction::Warp)
+                }
+                _ => false,
+            })
+    }
+
+    /// Where each portal on the map is and where it leads
+    pub fn portals(&self) -> impl Iterator<Item = (&Position, i32, &Position)> {
+        self.objects
+            .iter()
+            .filter_map(|(pos, object)| match object {
+                MapObject::Portal {
+                    map_id,
+                    pos: destination,
+                } => Some((pos, *map_id, destination)),
+                _ => None,
+            })
+    }
+
+    /// Catches anything that would stop the map being built. Portals can only be checked
+    /// once every map has been loaded.
+    pub(crate) fn validate(
+        &self,
+        dialogue_contents: &DialogueContents,
+        tile_properties: &TileProperties,
+    ) -> Result<(), String> {
+        if self.dimensions.width <= 0 || self.dimensions.height <= 0 {
+            return Err("the map is empty".to_string());
+        }
+
+        for (pos, object) in &self.objects {
+            if !self.contains(pos) {
+                return Err(format!(
+                    "{:?} at ({}, {}) is outside the {}x{} map",
+                    object, pos.x, pos.y, self.dimensions.width, self.dimensions.height
+                ));
+            }
+
+            let (sprite, dialogue) = match object {
+                MapObject::Floor(sprite) => (sprite, None),
+                MapObject::Sprite { sprite, dialogue } => (sprite, dialogue.as_ref()),
+                MapObject::Portal { .. } if !self.warps(pos, tile_properties) => {
+                    return Err(format!(
+                        "the portal at ({}, {}) isn't on anything with the Warp interaction",
+                        pos.x, pos.y
+                    ));
+                }
+                MapObject::SpawnPoint | MapObject::Enemy(_) | MapObject::Portal { .. } => continue,
+            };
+
+            let Some(property) = tile_properties.0.get(sprite) else {
+                return Err(format!(
+                    "the {:?} at ({}, {}) is not in tile_properties.ron",
+                    sprite, pos.x, pos.y
+                ));
+            };
+
+            if let Some(name) = dialogue {
+                if property.interaction != Interaction::Talk {
+                    return Err(format!(
+                        "the {:?} at ({}, {}) has dialogue but can't be talked to",
+                        sprite, pos.x, pos.y
+                    ));
+                }
+                if !dialogue_contents.0.contains_key(name) {
+                    return Err(format!(
+                        "the {:?} at ({}, {}) has the dialogue \"{}\" which is not in dialogue_contents.ron",
+                        sprite, pos.x, pos.y, name
+                    ));
+                }
+            }
+
+            if property.interaction == Interaction::Warp
+                && !self.portals().any(|(portal_pos, _, _)| portal_pos == pos)
+            {
+                return Err(format!(
+                    "the {:?} at ({}, {}) can be warped through but has no portal",
+                    sprite, pos.x, pos.y
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
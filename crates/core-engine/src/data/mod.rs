@@ -1,11 +1,12 @@
 pub mod dialogue_contents;
 pub mod enemy_config;
 pub mod enemy_configs;
+pub mod game_data;
 pub mod map_data;
+pub mod map_layout;
 pub mod player_config;
 pub mod player_configs;
+pub mod tile_properties;
+pub mod tiled;
 
-
-pub const player_configs_str: &str = include_str!("./player_configs.ron");
-pub const enemy_configs_str: &str = include_str!("./enemy_configs.ron");
-pub const dialogue_contents_str: &str = include_str!("./dialogue_contents.ron");
+pub const TILE_PROPERTIES: &str = include_str!("./tile_properties.ron");
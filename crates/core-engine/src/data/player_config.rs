@@ -3,7 +3,7 @@ use bevy::prelude::Resource;
 use core_api::SpriteTexture;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize, Resource)]
+#[derive(Debug, Clone, Deserialize, Serialize, Resource)]
 pub struct PlayerConfig {
     pub visibility: u32,
     pub blocks_movement: bool,
@@ -1,10 +1,11 @@
 use bevy::prelude::Resource;
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
 
 
 use super::player_config::PlayerConfig;
 
-#[derive(Debug, Deserialize, Serialize, Resource)]
+#[derive(Debug, Clone, Deserialize, Serialize, Resource)]
 pub struct PlayerConfigs {
     pub ghost_boy: PlayerConfig,
     pub kidzilla: PlayerConfig,
@@ -12,3 +13,24 @@ pub struct PlayerConfigs {
     pub boney_boy: PlayerConfig,
     pub ant_boy: PlayerConfig,
 }
+
+/// The player configs the engine is playing with, kept up to date for readers outside it
+#[derive(Debug, Clone, Resource)]
+pub struct SharedPlayerConfigs(Arc<RwLock<PlayerConfigs>>);
+
+impl SharedPlayerConfigs {
+    pub fn new(player_configs: PlayerConfigs) -> Self {
+        Self(Arc::new(RwLock::new(player_configs)))
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, PlayerConfigs> {
+        // Writers only ever swap in a whole value, so a poisoned lock still holds a good one
+        self.0
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(crate) fn replace(&self, player_configs: PlayerConfigs) {
+        *self.0.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = player_configs;
+    }
+}
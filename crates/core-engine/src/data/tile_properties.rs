@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use bevy::prelude::{Component, Resource};
+use core_api::SpriteTexture;
+use serde::{Deserialize, Serialize};
+
+/// What happens when a player walks into a tile. Sprites that do something carry it as a
+/// component, which is what the movement and warp systems go by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize, Component)]
+pub enum Interaction {
+    #[default]
+    None,
+    /// Starts a conversation, for tiles a map gives dialogue to
+    Talk,
+    /// Leads somewhere else
+    Warp,
+}
+
+/// How a texture behaves when it is placed on a map
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TileProperty {
+    pub name: String,
+    #[serde(default)]
+    pub blocks_movement: bool,
+    #[serde(default)]
+    pub blocks_light: bool,
+    #[serde(default)]
+    pub interaction: Interaction,
+    /// Shown when the tile is hovered
+    pub description: String,
+}
+
+/// Every texture that can be placed on a map, along with how it behaves
+#[derive(Debug, Deserialize, Serialize, Resource, Clone)]
+pub struct TileProperties(pub HashMap<SpriteTexture, TileProperty>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::TILE_PROPERTIES;
+
+    #[test]
+    fn windows_and_water_let_light_through() {
+        let tile_properties = ron::from_str::<TileProperties>(TILE_PROPERTIES).unwrap();
+
+        for texture in [SpriteTexture::ObjectWindow, SpriteTexture::ObjectWater] {
+            let property = &tile_properties.0[&texture];
+            assert!(property.blocks_movement && !property.blocks_light);
+        }
+        assert_eq!(
+            tile_properties.0[&SpriteTexture::ObjectWarpTeeveeFrames3].interaction,
+            Interaction::Warp
+        );
+    }
+}
@@ -0,0 +1,563 @@
+mod tmx;
+
+use std::{collections::HashMap, fs, path::Path};
+
+use ae_position::{Dimensions2d, Position};
+use base64::Engine;
+use core_api::SpriteTexture;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::data::{
+    map_data::{read_error, MapError},
+    map_layout::{MapLayout, MapObject},
+};
+
+/// The top bits of a tile id say how the tile is flipped, which makes no difference here
+const FLIP_FLAGS: u32 = 0xF000_0000;
+
+const INFINITE_MAPS: &str =
+    "infinite maps are not supported, untick Infinite in the map properties";
+
+/// The parts of a Tiled map the engine uses, read from either format
+#[derive(Debug, Deserialize)]
+struct TiledMap {
+    width: i32,
+    height: i32,
+    tilewidth: i32,
+    tileheight: i32,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    properties: Vec<Property>,
+    layers: Vec<Layer>,
+    #[serde(default)]
+    tilesets: Vec<Tileset>,
+}
+
+/// A custom property, set in Tiled's properties panel
+#[derive(Debug, Deserialize)]
+struct Property {
+    name: String,
+    value: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum Layer {
+    #[serde(rename = "tilelayer")]
+    Tiles(TileLayer),
+    #[serde(rename = "objectgroup")]
+    Objects(ObjectGroup),
+    #[serde(rename = "group")]
+    Group { layers: Vec<Layer> },
+    #[serde(rename = "imagelayer")]
+    Image {},
+}
+
+#[derive(Debug, Deserialize)]
+struct TileLayer {
+    #[serde(default)]
+    name: String,
+    data: Option<LayerData>,
+    #[serde(default)]
+    compression: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum LayerData {
+    Gids(Vec<u32>),
+    /// Little endian tile ids in base64
+    Encoded(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectGroup {
+    objects: Vec<Object>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Object {
+    id: u32,
+    #[serde(default)]
+    name: String,
+    /// Called `type` before Tiled 1.9
+    #[serde(default, rename = "type", alias = "class")]
+    class: String,
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    height: f64,
+    /// Set when the object is a tile
+    gid: Option<u32>,
+    #[serde(default)]
+    properties: Vec<Property>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tileset {
+    firstgid: u32,
+    /// Where the tiles are when they are kept in their own file
+    source: Option<String>,
+    #[serde(default)]
+    tiles: Vec<Tile>,
+}
+
+/// A tileset kept in its own `.tsj` file
+#[derive(Debug, Deserialize)]
+struct TilesetFile {
+    #[serde(default)]
+    tiles: Vec<Tile>,
+}
+
+/// Only tiles with properties are listed in a tileset
+#[derive(Debug, Deserialize)]
+struct Tile {
+    id: u32,
+    #[serde(default)]
+    properties: Vec<Property>,
+}
+
+fn property<'a>(properties: &'a [Property], name: &str) -> Option<&'a Value> {
+    properties
+        .iter()
+        .find(|property| property.name == name)
+        .map(|property| &property.value)
+}
+
+fn sprite_named(name: &str) -> Result<SpriteTexture, String> {
+    serde_json::from_value(Value::String(name.to_string()))
+        .map_err(|_| format!("\"{}\" is not a sprite", name))
+}
+
+fn flatten(layers: Vec<Layer>, flattened: &mut Vec<Layer>) {
+    for layer in layers {
+        match layer {
+            Layer::Group { layers } => flatten(layers, flattened),
+            layer => flattened.push(layer),
+        }
+    }
+}
+
+impl TileLayer {
+    /// One tile id per tile, row by row
+    fn gids(self, tile_count: usize) -> Result<Vec<u32>, String> {
+        let gids = match (self.data, self.compression.filter(|c| !c.is_empty())) {
+            (_, Some(compression)) => {
+                return Err(format!(
+                    "layer \"{}\" is compressed with {}, save it as CSV or uncompressed Base64",
+                    self.name, compression
+                ))
+            }
+            (None, None) => return Err(format!("layer \"{}\" has no tiles", self.name)),
+            (Some(LayerData::Gids(gids)), None) => gids,
+            (Some(LayerData::Encoded(text)), None) => {
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(text.trim())
+                    .map_err(|error| {
+                        format!("layer \"{}\" is not valid base64: {}", self.name, error)
+                    })?;
+                bytes
+                    .chunks_exact(4)
+                    .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+                    .collect()
+            }
+        };
+
+        if gids.len() != tile_count {
+            return Err(format!(
+                "layer \"{}\" has {} tiles but the map has {}",
+                self.name,
+                gids.len(),
+                tile_count
+            ));
+        }
+
+        Ok(gids)
+    }
+}
+
+impl Object {
+    fn place(
+        self,
+        sprites: &HashMap<u32, SpriteTexture>,
+        tile_width: i32,
+        tile_height: i32,
+    ) -> Result<(Position, MapObject), String> {
+        // Tile objects hang up from their position, everything else hangs down
+        let top = match self.gid {
+            Some(_) => self.y - self.height,
+            None => self.y,
+        };
+        let pos = Position {
+            x: (self.x / tile_width as f64).floor() as i32,
+            y: (top / tile_height as f64).floor() as i32,
+        };
+
+        let description = if self.name.is_empty() {
+            format!("object {}", self.id)
+        } else {
+            format!("object {} ({})", self.id, self.name)
+        };
+        let int_property = |name: &str| {
+            property(&self.properties, name)
+                .and_then(Value::as_i64)
+                .map(|value| value as i32)
+                .ok_or_else(|| format!("{} needs an int property called {}", description, name))
+        };
+
+        let object = match (self.class.as_str(), self.gid) {
+            ("portal", _) => MapObject::Portal {
+                map_id: int_property("map")?,
+                pos: Position {
+                    x: int_property("x")?,
+                    y: int_property("y")?,
+                },
+            },
+            ("spawn", _) => MapObject::SpawnPoint,
+            ("npc", _) | ("", Some(_)) => {
+                let sprite = match (self.gid, property(&self.properties, "sprite")) {
+                    (Some(gid), _) => *sprites.get(&(gid & !FLIP_FLAGS)).ok_or_else(|| {
+                        format!("{} is a tile without a sprite property", description)
+                    })?,
+                    (None, Some(Value::String(name))) => sprite_named(name)?,
+                    (None, _) => {
+                        return Err(format!(
+                            "{} needs to be a tile or have a string property called sprite",
+                            description
+                        ))
+                    }
+                };
+                let dialogue = property(&self.properties, "dialogue")
+                    .and_then(Value::as_str)
+                    .map(str::to_string);
+
+                MapObject::Sprite { sprite, dialogue }
+            }
+            (class, _) => {
+                return Err(format!(
+                    "{} has the class \"{}\", it should be npc, spawn or portal",
+                    description, class
+                ))
+            }
+        };
+
+        Ok((pos, object))
+    }
+}
+
+impl TiledMap {
+    /// The sprite each tile id stands for, from the `sprite` property of the tiles in the
+    /// tilesets
+    fn sprites(&self) -> Result<HashMap<u32, SpriteTexture>, String> {
+        let mut sprites = HashMap::new();
+        for tileset in &self.tilesets {
+            for tile in &tileset.tiles {
+                if let Some(name) = property(&tile.properties, "sprite").and_then(Value::as_str) {
+                    sprites.insert(tileset.firstgid + tile.id, sprite_named(name)?);
+                }
+            }
+        }
+
+        Ok(sprites)
+    }
+
+    /// The first tile layer is the floor, the tile layers above it are sprites, and the
+    /// objects in object layers are NPCs, spawn points and portals
+    fn layout(self, default_name: &str) -> Result<MapLayout, String> {
+        if self.infinite {
+            return Err(INFINITE_MAPS.to_string());
+        }
+
+        let id = property(&self.properties, "id")
+            .and_then(Value::as_i64)
+            .ok_or_else(|| "the map needs an int property called id".to_string())?;
+        let name = property(&self.properties, "name")
+            .and_then(Value::as_str)
+            .unwrap_or(default_name);
+        let sprites = self.sprites()?;
+
+        let mut layout = MapLayout {
+            id: id as i32,
+            name: name.to_string(),
+            dimensions: Dimensions2d {
+                width: self.width,
+                height: self.height,
+            },
+            objects: Vec::new(),
+        };
+
+        let mut layers = Vec::new();
+        flatten(self.layers, &mut layers);
+
+        let tile_count = match (usize::try_from(self.width), usize::try_from(self.height)) {
+            (Ok(width), Ok(height)) => width.checked_mul(height),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            format!(
+                "a map {} tiles wide and {} tiles high can't be played",
+                self.width, self.height
+            )
+        })?;
+
+        let mut found_floor = false;
+        for layer in layers {
+            match layer {
+                Layer::Tiles(tile_layer) => {
+                    let layer_name = tile_layer.name.clone();
+                    let gids = tile_layer.gids(tile_count)?;
+
+                    for (idx, gid) in gids.into_iter().enumerate() {
+                        let gid = gid & !FLIP_FLAGS;
+                        if gid == 0 {
+                            continue;
+                        }
+
+                        let pos = Position::from_idx(idx, self.width as usize);
+                        let sprite = *sprites.get(&gid).ok_or_else(|| {
+                            format!(
+                                "layer \"{}\" has a tile at ({}, {}) without a sprite property",
+                                layer_name, pos.x, pos.y
+                            )
+                        })?;
+
+                        let object = if !found_floor {
+                            MapObject::Floor(sprite)
+                        } else if sprite != SpriteTexture::Empty {
+                            MapObject::Sprite {
+                                sprite,
+                                dialogue: None,
+                            }
+                        } else {
+                            continue;
+                        };
+                        layout.objects.push((pos, object));
+                    }
+
+                    found_floor = true;
+                }
+                Layer::Objects(object_group) => {
+                    for object in object_group.objects {
+                        layout.objects.push(object.place(
+                            &sprites,
+                            self.tilewidth,
+                            self.tileheight,
+                        )?);
+                    }
+                }
+                Layer::Group { .. } | Layer::Image {} => {}
+            }
+        }
+
+        Ok(layout)
+    }
+}
+
+/// Reads a map saved by Tiled, as JSON (`.tmj`) or XML (`.tmx`). Tilesets can be in the map
+/// or in their own `.tsj` or `.tsx` files next to it.
+pub fn load(path: &Path) -> Result<MapLayout, MapError> {
+    let invalid = |reason: String| MapError::Invalid {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let is_xml = |path: &Path| {
+        path.extension()
+            .map_or(false, |extension| extension == "tmx" || extension == "tsx")
+    };
+
+    let text = fs::read_to_string(path).map_err(read_error(path))?;
+    let mut map = if is_xml(path) {
+        tmx::map(&text)
+    } else {
+        serde_json::from_str::<TiledMap>(&text).map_err(|error| error.to_string())
+    }
+    .map_err(invalid)?;
+
+    for tileset in &mut map.tilesets {
+        if let Some(source) = tileset.source.take() {
+            let tileset_path = path.parent().unwrap_or_else(|| Path::new("")).join(source);
+            let text = fs::read_to_string(&tileset_path).map_err(read_error(&tileset_path))?;
+            tileset.tiles = if is_xml(&tileset_path) {
+                tmx::tileset_tiles(&text)
+            } else {
+                serde_json::from_str::<TilesetFile>(&text)
+                    .map(|tileset| tileset.tiles)
+                    .map_err(|error| error.to_string())
+            }
+            .map_err(|reason| invalid(format!("tileset {}: {}", tileset_path.display(), reason)))?;
+        }
+    }
+
+    let default_name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    map.layout(&default_name).map_err(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TMJ: &str = r#"{
+        "width": 3, "height": 2, "tilewidth": 16, "tileheight": 16, "infinite": false,
+        "properties": [
+            {"name": "id", "type": "int", "value": 5},
+            {"name": "name", "type": "string", "value": "The Arcade"}
+        ],
+        "tilesets": [{"firstgid": 1, "name": "tiles", "tiles": [
+            {"id": 0, "properties": [{"name": "sprite", "type": "string", "value": "floorGrass"}]},
+            {"id": 1, "properties": [{"name": "sprite", "type": "string", "value": "wallStone"}]},
+            {"id": 2, "properties": [{"name": "sprite", "type": "string", "value": "npcRatFrames4"}]}
+        ]}],
+        "layers": [
+            {"type": "tilelayer", "name": "floor", "data": [1, 1, 1, 1, 1, 1]},
+            {"type": "group", "name": "buildings", "layers": [
+                {"type": "tilelayer", "name": "walls", "data": [2, 0, 0, 0, 0, 2]}
+            ]},
+            {"type": "objectgroup", "name": "things", "objects": [
+                {"id": 1, "name": "Ratty", "type": "npc", "gid": 3, "x": 16, "y": 16,
+                    "width": 16, "height": 16,
+                    "properties": [{"name": "dialogue", "type": "string", "value": "rat"}]},
+                {"id": 2, "type": "spawn", "x": 32, "y": 0, "width": 16, "height": 16},
+                {"id": 3, "class": "portal", "x": 4, "y": 20, "point": true, "properties": [
+                    {"name": "map", "type": "int", "value": 1},
+                    {"name": "x", "type": "int", "value": 4},
+                    {"name": "y", "type": "int", "value": 2}
+                ]}
+            ]}
+        ]
+    }"#;
+
+    const TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <map version="1.10" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16" infinite="0">
+         <properties>
+          <property name="id" type="int" value="5"/>
+          <property name="name" value="The Arcade"/>
+         </properties>
+         <tileset firstgid="1" name="tiles">
+          <tile id="0"><properties><property name="sprite" value="floorGrass"/></properties></tile>
+          <tile id="1"><properties><property name="sprite" value="wallStone"/></properties></tile>
+          <tile id="2"><properties><property name="sprite" value="npcRatFrames4"/></properties></tile>
+         </tileset>
+         <layer id="1" name="floor" width="3" height="2">
+          <data encoding="csv">
+        1,1,1,
+        1,1,1
+        </data>
+         </layer>
+         <group id="2" name="buildings">
+          <layer id="3" name="walls" width="3" height="2">
+           <data encoding="base64">AgAAAAAAAAAAAAAAAAAAAAAAAAACAAAA</data>
+          </layer>
+         </group>
+         <objectgroup id="4" name="things">
+          <object id="1" name="Ratty" type="npc" gid="3" x="16" y="16" width="16" height="16">
+           <properties><property name="dialogue" value="rat"/></properties>
+          </object>
+          <object id="2" type="spawn" x="32" y="0" width="16" height="16"/>
+          <object id="3" class="portal" x="4" y="20">
+           <properties>
+            <property name="map" type="int" value="1"/>
+            <property name="x" type="int" value="4"/>
+            <property name="y" type="int" value="2"/>
+           </properties>
+           <point/>
+          </object>
+         </objectgroup>
+        </map>"#;
+
+    fn tmj(text: &str) -> Result<MapLayout, String> {
+        serde_json::from_str::<TiledMap>(text)
+            .map_err(|error| error.to_string())?
+            .layout("arcade")
+    }
+
+    #[test]
+    fn layers_and_objects_are_placed_on_the_map() {
+        let layout = tmj(TMJ).unwrap();
+        assert_eq!((layout.id, layout.name.as_str()), (5, "The Arcade"));
+
+        let floors = layout
+            .objects
+            .iter()
+            .filter(|(_, object)| *object == MapObject::Floor(SpriteTexture::FloorGrass))
+            .count();
+        assert_eq!(floors, 6);
+
+        let on_the_floor: Vec<_> = layout
+            .objects
+            .iter()
+            .filter(|(_, object)| !matches!(object, MapObject::Floor(_)))
+            .map(|(pos, object)| ((pos.x, pos.y), object.clone()))
+            .collect();
+        let wall = MapObject::Sprite {
+            sprite: SpriteTexture::WallStone,
+            dialogue: None,
+        };
+        assert_eq!(
+            on_the_floor,
+            [
+                ((0, 0), wall.clone()),
+                ((2, 1), wall),
+                (
+                    (1, 0),
+                    MapObject::Sprite {
+                        sprite: SpriteTexture::NpcRatFrames4,
+                        dialogue: Some("rat".to_string()),
+                    }
+                ),
+                ((2, 0), MapObject::SpawnPoint),
+                (
+                    (0, 1),
+                    MapObject::Portal {
+                        map_id: 1,
+                        pos: Position { x: 4, y: 2 },
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn tmx_maps_are_read_the_same_as_tmj_maps() {
+        let from_tmx = tmx::map(TMX).unwrap().layout("arcade").unwrap();
+        let from_tmj = tmj(TMJ).unwrap();
+
+        assert_eq!((from_tmx.id, from_tmx.name), (from_tmj.id, from_tmj.name));
+        assert_eq!(from_tmx.dimensions, from_tmj.dimensions);
+        assert_eq!(from_tmx.objects, from_tmj.objects);
+    }
+
+    #[test]
+    fn maps_the_engine_cannot_use_are_explained() {
+        let unknown_tile = TMJ.replace("[2, 0, 0, 0, 0, 2]", "[9, 0, 0, 0, 0, 2]");
+        assert_eq!(
+            tmj(&unknown_tile).unwrap_err(),
+            "layer \"walls\" has a tile at (0, 0) without a sprite property"
+        );
+
+        let compressed = TMX.replace(
+            r#"encoding="base64""#,
+            r#"encoding="base64" compression="zlib""#,
+        );
+        assert_eq!(
+            tmx::map(&compressed).unwrap().layout("arcade").unwrap_err(),
+            "layer \"walls\" is compressed with zlib, save it as CSV or uncompressed Base64"
+        );
+
+        let too_big = TMJ.replace(r#""width": 3"#, r#""width": -3"#);
+        assert_eq!(
+            tmj(&too_big).unwrap_err(),
+            "a map -3 tiles wide and 2 tiles high can't be played"
+        );
+
+        let unknown_class = TMJ.replace(r#""type": "spawn""#, r#""type": "chest""#);
+        assert_eq!(
+            tmj(&unknown_class).unwrap_err(),
+            "object 2 has the class \"chest\", it should be npc, spawn or portal"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::str::FromStr;
+
+use roxmltree::{Document, Node};
+use serde_json::Value;
+
+use super::{
+    Layer, LayerData, Object, ObjectGroup, Property, Tile, TileLayer, TiledMap, Tileset,
+    INFINITE_MAPS,
+};
+
+fn child<'a, 'input>(element: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    element.children().find(|child| child.has_tag_name(name))
+}
+
+fn children_named<'a, 'input: 'a>(
+    element: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    element
+        .children()
+        .filter(move |child| child.has_tag_name(name))
+}
+
+fn number<T: FromStr>(element: Node, name: &str) -> Result<Option<T>, String> {
+    element
+        .attribute(name)
+        .map(|value| {
+            value.parse().map_err(|_| {
+                format!(
+                    "<{}> has the {} \"{}\", which is not a number",
+                    element.tag_name().name(),
+                    name,
+                    value
+                )
+            })
+        })
+        .transpose()
+}
+
+fn required_number<T: FromStr>(element: Node, name: &str) -> Result<T, String> {
+    number(element, name)?.ok_or_else(|| format!("<{}> has no {}", element.tag_name().name(), name))
+}
+
+fn properties(element: Node) -> Result<Vec<Property>, String> {
+    let Some(properties) = child(element, "properties") else {
+        return Ok(Vec::new());
+    };
+
+    children_named(properties, "property")
+        .map(|property| {
+            let name = property.attribute("name").unwrap_or_default().to_string();
+            // Strings with more than one line are kept in the element rather than an attribute
+            let text = property
+                .attribute("value")
+                .or_else(|| property.text())
+                .unwrap_or_default();
+            let value = match property.attribute("type") {
+                Some("int") | Some("object") => Value::from(
+                    text.parse::<i64>()
+                        .map_err(|_| format!("the property {} is not an int", name))?,
+                ),
+                Some("float") => Value::from(
+                    text.parse::<f64>()
+                        .map_err(|_| format!("the property {} is not a float", name))?,
+                ),
+                Some("bool") => Value::Bool(text == "true"),
+                _ => Value::String(text.to_string()),
+            };
+
+            Ok(Property { name, value })
+        })
+        .collect()
+}
+
+fn tile_layer(element: Node) -> Result<TileLayer, String> {
+    let name = element.attribute("name").unwrap_or_default().to_string();
+    let Some(data) = child(element, "data") else {
+        return Err(format!("layer \"{}\" has no tiles", name));
+    };
+    let text = data.text().unwrap_or_default();
+
+    let gids = match data.attribute("encoding") {
+        Some("csv") => LayerData::Gids(
+            text.trim()
+                .split(',')
+                .map(|gid| gid.trim().parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("layer \"{}\" is not valid CSV", name))?,
+        ),
+        Some("base64") => LayerData::Encoded(text.to_string()),
+        Some(encoding) => {
+            return Err(format!(
+                "layer \"{}\" has the unknown encoding {}",
+                name, encoding
+            ))
+        }
+        // The oldest format, one element per tile
+        None => LayerData::Gids(
+            children_named(data, "tile")
+                .map(|tile| number(tile, "gid").map(Option::unwrap_or_default))
+                .collect::<Result<_, _>>()?,
+        ),
+    };
+
+    Ok(TileLayer {
+        name,
+        data: Some(gids),
+        compression: data.attribute("compression").map(str::to_string),
+    })
+}
+
+fn object(element: Node) -> Result<Object, String> {
+    Ok(Object {
+        id: required_number(element, "id")?,
+        name: element.attribute("name").unwrap_or_default().to_string(),
+        class: element
+            .attribute("type")
+            .or_else(|| element.attribute("class"))
+            .unwrap_or_default()
+            .to_string(),
+        x: number(element, "x")?.unwrap_or_default(),
+        y: number(element, "y")?.unwrap_or_default(),
+        height: number(element, "height")?.unwrap_or_default(),
+        gid: number(element, "gid")?,
+        properties: properties(element)?,
+    })
+}
+
+fn layers(element: Node) -> Result<Vec<Layer>, String> {
+    element
+        .children()
+        .filter_map(|child| match child.tag_name().name() {
+            "layer" => Some(tile_layer(child).map(Layer::Tiles)),
+            "objectgroup" => Some(
+                children_named(child, "object")
+                    .map(object)
+                    .collect::<Result<_, _>>()
+                    .map(|objects| Layer::Objects(ObjectGroup { objects })),
+            ),
+            "group" => Some(layers(child).map(|layers| Layer::Group { layers })),
+            "imagelayer" => Some(Ok(Layer::Image {})),
+            _ => None,
+        })
+        .collect()
+}
+
+fn tiles(tileset: Node) -> Result<Vec<Tile>, String> {
+    children_named(tileset, "tile")
+        .map(|tile| {
+            Ok(Tile {
+                id: required_number(tile, "id")?,
+                properties: properties(tile)?,
+            })
+        })
+        .collect()
+}
+
+/// Reads a `.tmx` map
+pub(super) fn map(text: &str) -> Result<TiledMap, String> {
+    let document = Document::parse(text).map_err(|error| error.to_string())?;
+    let root = document.root_element();
+    if !root.has_tag_name("map") {
+        return Err(format!(
+            "expected a <map> but found a <{}>",
+            root.tag_name().name()
+        ));
+    }
+    if root.attribute("infinite") == Some("1") {
+        return Err(INFINITE_MAPS.to_string());
+    }
+
+    let tilesets = children_named(root, "tileset")
+        .map(|tileset| {
+            Ok(Tileset {
+                firstgid: required_number(tileset, "firstgid")?,
+                source: tileset.attribute("source").map(str::to_string),
+                tiles: tiles(tileset)?,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+
+    Ok(TiledMap {
+        width: required_number(root, "width")?,
+        height: required_number(root, "height")?,
+        tilewidth: required_number(root, "tilewidth")?,
+        tileheight: required_number(root, "tileheight")?,
+        infinite: false,
+        properties: properties(root)?,
+        layers: layers(root)?,
+        tilesets,
+    })
+}
+
+/// Reads the tiles from a `.tsx` tileset
+pub(super) fn tileset_tiles(text: &str) -> Result<Vec<Tile>, String> {
+    let document = Document::parse(text).map_err(|error| error.to_string())?;
+    let root = document.root_element();
+    if !root.has_tag_name("tileset") {
+        return Err(format!(
+            "expected a <tileset> but found a <{}>",
+            root.tag_name().name()
+        ));
+    }
+
+    tiles(root)
+}
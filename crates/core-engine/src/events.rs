@@ -1,5 +1,9 @@
+use core_api::UserId;
+
 use super::resources::world::MapId;
 
 pub struct ShouldUpdateMap(pub MapId);
 
 pub struct ShouldSendFullMapUpdateToClient(pub MapId);
+
+pub struct ShouldSendFullMapUpdateToUser(pub UserId);
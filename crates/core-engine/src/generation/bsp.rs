@@ -0,0 +1,105 @@
+use rand::Rng;
+
+use super::Grid;
+
+/// Areas narrower than twice this are not split any further
+const MIN_LEAF: i32 = 8;
+const MIN_ROOM: i32 = 3;
+
+#[derive(Clone, Copy)]
+struct Area {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+/// Splits the map in two over and over, puts a room in each piece and joins every pair of
+/// pieces with a corridor
+pub(super) fn generate(width: i32, height: i32, rng: &mut impl Rng) -> Grid {
+    let mut grid = Grid::new(width, height);
+    let inside = Area {
+        x: 1,
+        y: 1,
+        width: width - 2,
+        height: height - 2,
+    };
+    split(&mut grid, inside, rng);
+    grid
+}
+
+/// Carves rooms into `area` and joins them up, returning a tile in one of them
+fn split(grid: &mut Grid, area: Area, rng: &mut impl Rng) -> (i32, i32) {
+    let split_across = match (area.width >= MIN_LEAF * 2, area.height >= MIN_LEAF * 2) {
+        (false, false) => return room(grid, area, rng),
+        (true, false) => true,
+        (false, true) => false,
+        // Splitting the longer side keeps the rooms from getting too thin
+        (true, true) => area.width >= area.height,
+    };
+
+    let (first, second) = if split_across {
+        let at = rng.gen_range(MIN_LEAF..=area.width - MIN_LEAF);
+        (
+            Area { width: at, ..area },
+            Area {
+                x: area.x + at,
+                width: area.width - at,
+                ..area
+            },
+        )
+    } else {
+        let at = rng.gen_range(MIN_LEAF..=area.height - MIN_LEAF);
+        (
+            Area { height: at, ..area },
+            Area {
+                y: area.y + at,
+                height: area.height - at,
+                ..area
+            },
+        )
+    };
+
+    let from = split(grid, first, rng);
+    let to = split(grid, second, rng);
+    corridor(grid, from, to, rng);
+
+    if rng.gen_bool(0.5) {
+        from
+    } else {
+        to
+    }
+}
+
+fn room(grid: &mut Grid, area: Area, rng: &mut impl Rng) -> (i32, i32) {
+    // A tile is left around the room so rooms in neighbouring areas never merge
+    let width = rng.gen_range(MIN_ROOM.min(area.width - 2)..=area.width - 2);
+    let height = rng.gen_range(MIN_ROOM.min(area.height - 2)..=area.height - 2);
+    let x = area.x + 1 + rng.gen_range(0..=area.width - 2 - width);
+    let y = area.y + 1 + rng.gen_range(0..=area.height - 2 - height);
+
+    for y in y..y + height {
+        for x in x..x + width {
+            grid.set_open(x, y, true);
+        }
+    }
+
+    (x + width / 2, y + height / 2)
+}
+
+/// An L shaped corridor, going across or down first at random
+fn corridor(grid: &mut Grid, from: (i32, i32), to: (i32, i32), rng: &mut impl Rng) {
+    let corner = if rng.gen_bool(0.5) {
+        (to.0, from.1)
+    } else {
+        (from.0, to.1)
+    };
+
+    for (start, end) in [(from, corner), (corner, to)] {
+        for x in start.0.min(end.0)..=start.0.max(end.0) {
+            for y in start.1.min(end.1)..=start.1.max(end.1) {
+                grid.set_open(x, y, true);
+            }
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use rand::Rng;
+
+use super::Grid;
+
+/// The share of tiles that start out as wall
+const INITIAL_WALLS: f64 = 0.45;
+const SMOOTHING_PASSES: usize = 5;
+
+/// Fills the map with random walls, then smooths them into caves by letting each tile
+/// take after most of its neighbours
+pub(super) fn generate(width: i32, height: i32, rng: &mut impl Rng) -> Grid {
+    let mut grid = Grid::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            grid.set_open(x, y, !rng.gen_bool(INITIAL_WALLS));
+        }
+    }
+
+    for _ in 0..SMOOTHING_PASSES {
+        let mut smoothed = Grid::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let walls = (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+                    .filter(|(nx, ny)| (*nx, *ny) != (x, y) && !grid.is_open(*nx, *ny))
+                    .count();
+
+                let open = match walls {
+                    0..=3 => true,
+                    4 => grid.is_open(x, y),
+                    _ => false,
+                };
+                smoothed.set_open(x, y, open);
+            }
+        }
+        grid = smoothed;
+    }
+
+    grid
+}
@@ -0,0 +1,39 @@
+use rand::Rng;
+
+use super::Grid;
+
+/// The share of the map to dig out
+const OPEN_FRACTION: f64 = 0.4;
+
+/// Wanders about from the middle of the map, digging out every tile it steps on until
+/// enough of the map is open
+pub(super) fn generate(width: i32, height: i32, rng: &mut impl Rng) -> Grid {
+    let mut grid = Grid::new(width, height);
+    let inside_area = ((width - 2) * (height - 2)) as usize;
+    let target = (inside_area as f64 * OPEN_FRACTION) as usize;
+
+    let (mut x, mut y) = (width / 2, height / 2);
+    grid.set_open(x, y, true);
+    let mut open = 1;
+
+    // Plenty of steps for the walk to cover the map, without risking an endless loop
+    for _ in 0..inside_area * 50 {
+        if open >= target {
+            break;
+        }
+
+        let (dx, dy) = [(1, 0), (-1, 0), (0, 1), (0, -1)][rng.gen_range(0..4)];
+        if !grid.inside(x + dx, y + dy) {
+            continue;
+        }
+
+        x += dx;
+        y += dy;
+        if !grid.is_open(x, y) {
+            grid.set_open(x, y, true);
+            open += 1;
+        }
+    }
+
+    grid
+}
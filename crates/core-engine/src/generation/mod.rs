@@ -0,0 +1,308 @@
+mod bsp;
+mod caves;
+mod drunkards_walk;
+
+use std::collections::VecDeque;
+
+use ae_position::{Dimensions2d, Position};
+use core_api::{MapAlgorithm, MapGeneratorSettings};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::data::map_layout::{MapLayout, MapObject};
+
+/// The smallest width or height a map can be generated with
+pub const MIN_SIZE: i32 = 10;
+/// The largest width or height a map can be generated with
+pub const MAX_SIZE: i32 = 256;
+
+/// Which tiles of a map are open, row by row. The generators only carve inside the
+/// edge, so a generated map is always walled in.
+struct Grid {
+    width: i32,
+    height: i32,
+    open: Vec<bool>,
+}
+
+impl Grid {
+    fn new(width: i32, height: i32) -> Self {
+        Self {
+            width,
+            height,
+            open: vec![false; (width * height) as usize],
+        }
+    }
+
+    fn inside(&self, x: i32, y: i32) -> bool {
+        x > 0 && y > 0 && x < self.width - 1 && y < self.height - 1
+    }
+
+    fn is_open(&self, x: i32, y: i32) -> bool {
+        self.inside(x, y) && self.open[(y * self.width + x) as usize]
+    }
+
+    fn set_open(&mut self, x: i32, y: i32, open: bool) {
+        if self.inside(x, y) {
+            self.open[(y * self.width + x) as usize] = open;
+        }
+    }
+
+    /// Closes every open tile that can't be walked to from the biggest open area, so
+    /// nothing is placed where it can never be reached
+    fn keep_largest_region(&mut self) {
+        let mut region = vec![usize::MAX; self.open.len()];
+        let mut region_sizes = Vec::new();
+
+        for start in 0..self.open.len() {
+            if !self.open[start] || region[start] != usize::MAX {
+                continue;
+            }
+
+            let id = region_sizes.len();
+            let mut size = 0;
+            let mut queue = VecDeque::from([start]);
+            region[start] = id;
+
+            while let Some(idx) = queue.pop_front() {
+                size += 1;
+                let (x, y) = (idx as i32 % self.width, idx as i32 / self.width);
+                for (x, y) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                    let next = (y * self.width + x) as usize;
+                    if self.is_open(x, y) && region[next] == usize::MAX {
+                        region[next] = id;
+                        queue.push_back(next);
+                    }
+                }
+            }
+
+            region_sizes.push(size);
+        }
+
+        let largest = (0..region_sizes.len()).max_by_key(|id| region_sizes[*id]);
+        for (open, region) in self.open.iter_mut().zip(region) {
+            *open = *open && Some(region) == largest;
+        }
+    }
+}
+
+/// Why a map can't be generated with these settings
+pub fn check_settings(settings: &MapGeneratorSettings) -> Result<(), String> {
+    for (size, setting) in [(settings.width, "width"), (settings.height, "height")] {
+        if !(MIN_SIZE..=MAX_SIZE).contains(&size) {
+            return Err(format!(
+                "the {} is {} but has to be from {} to {}",
+                setting, size, MIN_SIZE, MAX_SIZE
+            ));
+        }
+    }
+
+    if !(0.0..=1.0).contains(&settings.enemy_density) {
+        return Err(format!(
+            "the enemy density is {} but has to be from 0 to 1",
+            settings.enemy_density
+        ));
+    }
+
+    if settings.enemy_density > 0.0 && settings.enemies.is_empty() {
+        return Err("there is an enemy density but no enemies to place".to_string());
+    }
+
+    Ok(())
+}
+
+/// Lays out a new map, the same settings and seed always give the same map. `seed` is used
+/// in place of the settings' seed, so callers decide where an unset one comes from.
+pub fn generate(
+    id: i32,
+    name: &str,
+    settings: &MapGeneratorSettings,
+    seed: u64,
+) -> Result<MapLayout, String> {
+    check_settings(settings)?;
+
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let (width, height) = (settings.width, settings.height);
+    let mut grid = match settings.algorithm {
+        MapAlgorithm::Bsp => bsp::generate(width, height, &mut rng),
+        MapAlgorithm::Caves => caves::generate(width, height, &mut rng),
+        MapAlgorithm::DrunkardsWalk => drunkards_walk::generate(width, height, &mut rng),
+    };
+    grid.keep_largest_region();
+
+    let mut objects = Vec::new();
+    let mut enemies = Vec::new();
+    for (idx, open) in grid.open.iter().enumerate() {
+        let pos = Position::from_idx(idx, width as usize);
+
+        if !open {
+            objects.push((
+                pos,
+                MapObject::Sprite {
+                    sprite: settings.wall,
+                    dialogue: None,
+                },
+            ));
+            continue;
+        }
+
+        objects.push((pos.clone(), MapObject::Floor(settings.floor)));
+        if rng.gen_bool(settings.enemy_density as f64) {
+            if let Some(enemy) = settings.enemies.choose(&mut rng) {
+                enemies.push((pos, MapObject::Enemy(*enemy)));
+            }
+        }
+    }
+
+    // Players and anything spawned later need somewhere to stand
+    let floors = grid.open.iter().filter(|open| **open).count();
+    if floors == 0 {
+        return Err("the map has no floor, try another seed".to_string());
+    }
+    if enemies.len() >= floors {
+        return Err(format!(
+            "all {} floor tiles have an enemy on them, lower the enemy density",
+            floors
+        ));
+    }
+    objects.extend(enemies);
+
+    Ok(MapLayout {
+        id,
+        name: name.to_string(),
+        dimensions: Dimensions2d { width, height },
+        objects,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use core_api::{SpawnableEnemy, SpriteTexture};
+
+    use super::*;
+
+    const ALGORITHMS: [MapAlgorithm; 3] = [
+        MapAlgorithm::Bsp,
+        MapAlgorithm::Caves,
+        MapAlgorithm::DrunkardsWalk,
+    ];
+
+    fn settings(algorithm: MapAlgorithm) -> MapGeneratorSettings {
+        MapGeneratorSettings {
+            algorithm,
+            width: 48,
+            height: 32,
+            seed: None,
+            wall: SpriteTexture::WallBrick,
+            floor: SpriteTexture::FloorConcrete,
+            enemy_density: 0.0,
+            enemies: Vec::new(),
+        }
+    }
+
+    fn floor_tiles(layout: &MapLayout) -> Vec<(i32, i32)> {
+        layout
+            .objects
+            .iter()
+            .filter(|(_, object)| matches!(object, MapObject::Floor(_)))
+            .map(|(pos, _)| (pos.x, pos.y))
+            .collect()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_map() {
+        for algorithm in ALGORITHMS {
+            let settings = settings(algorithm);
+            let first = generate(3, "Sewers", &settings, 11).unwrap();
+            let again = generate(3, "Sewers", &settings, 11).unwrap();
+            let other = generate(3, "Sewers", &settings, 12).unwrap();
+
+            assert_eq!(first.objects, again.objects, "{:?}", algorithm);
+            assert_ne!(first.objects, other.objects, "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn every_open_tile_can_be_reached_and_the_edge_is_walled() {
+        for algorithm in ALGORITHMS {
+            let layout = generate(3, "Sewers", &settings(algorithm), 5).unwrap();
+            let floors = floor_tiles(&layout);
+            assert!(
+                floors.len() > 48 * 32 / 10,
+                "{:?} is too cramped",
+                algorithm
+            );
+
+            assert!(floors
+                .iter()
+                .all(|(x, y)| *x > 0 && *y > 0 && *x < 47 && *y < 31));
+
+            // Walk from the first open tile, everything else should be found
+            let mut found = vec![floors[0]];
+            let mut queue = vec![floors[0]];
+            while let Some((x, y)) = queue.pop() {
+                for next in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                    if floors.contains(&next) && !found.contains(&next) {
+                        found.push(next);
+                        queue.push(next);
+                    }
+                }
+            }
+            assert_eq!(found.len(), floors.len(), "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn enemies_are_placed_on_open_tiles_by_density() {
+        let mut settings = settings(MapAlgorithm::Caves);
+        settings.enemy_density = 0.5;
+        settings.enemies = vec![SpawnableEnemy::Rat];
+
+        let layout = generate(3, "Sewers", &settings, 5).unwrap();
+        let floors = floor_tiles(&layout);
+        let enemies: Vec<_> = layout
+            .objects
+            .iter()
+            .filter(|(_, object)| *object == MapObject::Enemy(SpawnableEnemy::Rat))
+            .map(|(pos, _)| (pos.x, pos.y))
+            .collect();
+        assert!(!enemies.is_empty());
+        assert!(enemies.len() < floors.len());
+        assert!(enemies.iter().all(|enemy| floors.contains(enemy)));
+    }
+
+    #[test]
+    fn maps_are_refused_when_enemies_leave_no_room() {
+        let mut settings = settings(MapAlgorithm::Bsp);
+        (settings.width, settings.height) = (10, 10);
+        settings.enemy_density = 1.0;
+        settings.enemies = vec![SpawnableEnemy::Rat];
+
+        for algorithm in ALGORITHMS {
+            settings.algorithm = algorithm;
+            let reason = generate(3, "Sewers", &settings, 1).unwrap_err();
+            assert!(
+                reason.ends_with("floor tiles have an enemy on them, lower the enemy density"),
+                "{:?}: {}",
+                algorithm,
+                reason
+            );
+        }
+    }
+
+    #[test]
+    fn bad_settings_are_explained() {
+        let mut settings = settings(MapAlgorithm::Bsp);
+        settings.width = 4;
+        assert_eq!(
+            generate(3, "Sewers", &settings, 5).unwrap_err(),
+            "the width is 4 but has to be from 10 to 256"
+        );
+
+        settings.width = 20;
+        settings.enemy_density = 0.5;
+        assert_eq!(
+            generate(3, "Sewers", &settings, 5).unwrap_err(),
+            "there is an enemy density but no enemies to place"
+        );
+    }
+}
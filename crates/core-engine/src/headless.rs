@@ -0,0 +1,686 @@
+use std::time::Duration;
+
+use bevy::{
+    core::CorePlugin,
+    prelude::{App, World},
+};
+use core_api::{
+    AdminCommand, AdminReply, ClientMessage, DatabaseRequest, DatabaseResponse, Password,
+    ServerMessageAllClients, ServerMessageSingleClient, SpriteTexture, TickOutput, UserId,
+};
+use tokio::sync::{
+    mpsc::{self, Receiver, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+
+use crate::{resources::GameClock, EngineBuilder, EngineChannels};
+
+/// Ticks of output the channel holds, it is emptied after every tick
+const OUTPUT_CAPACITY: usize = 1;
+
+/// A game engine that only runs a tick when asked to. It stands in for the server and the
+/// database by holding the other ends of the engine's channels, so tests and tools can
+/// feed it client messages and read back everything it sends.
+pub struct HeadlessEngine {
+    app: App,
+    client_sender: UnboundedSender<(UserId, ClientMessage)>,
+    output_receiver: Receiver<TickOutput>,
+    /// Read out of the channel after each tick so the engine never waits on it
+    single_client_output: Vec<(UserId, ServerMessageSingleClient)>,
+    all_clients_output: Vec<(u32, ServerMessageAllClients)>,
+    db_request_receiver: UnboundedReceiver<(UserId, DatabaseRequest)>,
+    db_response_sender: UnboundedSender<(UserId, DatabaseResponse)>,
+    admin_sender: UnboundedSender<(AdminCommand, oneshot::Sender<AdminReply>)>,
+    /// Dropping this would tell the engine to shut down
+    _shutdown_sender: oneshot::Sender<()>,
+    /// Requests read while logging users in that were not theirs to answer
+    unanswered_requests: Vec<(UserId, DatabaseRequest)>,
+}
+
+impl HeadlessEngine {
+    pub(crate) fn new(builder: EngineBuilder) -> Self {
+        let (client_sender, client_receiver) = mpsc::unbounded_channel();
+        let (server_sender, output_receiver) = mpsc::channel(OUTPUT_CAPACITY);
+        let (db_sender, db_request_receiver) = mpsc::unbounded_channel();
+        let (db_response_sender, db_receiver) = mpsc::unbounded_channel();
+        let (admin_sender, admin_receiver) = mpsc::unbounded_channel();
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+
+        let mut app = builder.build_app(EngineChannels {
+            client_receiver,
+            server_sender,
+            db_sender,
+            db_receiver,
+            admin_receiver,
+            shutdown_receiver,
+        });
+        // Nothing runs the app on its own, it only moves in `advance`
+        app.add_plugin(CorePlugin::default());
+
+        Self {
+            app,
+            client_sender,
+            output_receiver,
+            single_client_output: Vec::new(),
+            all_clients_output: Vec::new(),
+            db_request_receiver,
+            db_response_sender,
+            admin_sender,
+            _shutdown_sender: shutdown_sender,
+            unanswered_requests: Vec::new(),
+        }
+    }
+
+    /// How much game time passes on each tick from now on, instead of the config's tick rate
+    pub fn set_tick_duration(&mut self, tick_duration: Duration) {
+        self.app.world.resource_mut::<GameClock>().tick_duration = tick_duration;
+    }
+
+    /// Queues a message as if `user_id` had sent it, it is handled on the next tick
+    pub fn inject(&self, user_id: UserId, message: ClientMessage) {
+        self.client_sender
+            .send((user_id, message))
+            .expect("The engine owns the receiver");
+    }
+
+    /// Runs `ticks` ticks straight away, the game clock still only moves by the tick
+    /// duration each time
+    pub fn advance(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.app.update();
+
+            while let Ok(output) = self.output_receiver.try_recv() {
+                for (user_id, messages) in output.batches {
+                    self.single_client_output
+                        .extend(messages.into_iter().map(|message| (user_id, message)));
+                }
+                self.all_clients_output.extend(
+                    output
+                        .broadcasts
+                        .into_iter()
+                        .map(|message| (output.tick, message)),
+                );
+            }
+        }
+    }
+
+    /// Every message sent to a single client since the last drain, in the order they were sent
+    pub fn drain_single_client(&mut self) -> Vec<(UserId, ServerMessageSingleClient)> {
+        std::mem::take(&mut self.single_client_output)
+    }
+
+    /// The messages sent to `user_id` since the last drain, anyone else's are thrown away
+    pub fn drain_user(&mut self, user_id: UserId) -> Vec<ServerMessageSingleClient> {
+        self.drain_single_client()
+            .into_iter()
+            .filter(|(recipient, _)| *recipient == user_id)
+            .map(|(_, message)| message)
+            .collect()
+    }
+
+    /// Every message sent to all clients since the last drain, with the tick it was sent on
+    pub fn drain_all_clients(&mut self) -> Vec<(u32, ServerMessageAllClients)> {
+        std::mem::take(&mut self.all_clients_output)
+    }
+
+    /// Every request the engine has made of the database since the last drain
+    pub fn drain_database_requests(&mut self) -> Vec<(UserId, DatabaseRequest)> {
+        let mut requests = std::mem::take(&mut self.unanswered_requests);
+        while let Ok(request) = self.db_request_receiver.try_recv() {
+            requests.push(request);
+        }
+        requests
+    }
+
+    /// Answers for the database, the engine reads it on the next tick
+    pub fn respond_from_database(&self, user_id: UserId, response: DatabaseResponse) {
+        self.db_response_sender
+            .send((user_id, response))
+            .expect("The engine owns the receiver");
+    }
+
+    /// Logs `user_id` in to an account called `name` with the same id as the user,
+    /// approving the login in place of the database
+    pub fn log_in(&mut self, user_id: UserId, name: &str) {
+        self.inject(
+            user_id,
+            ClientMessage::Login {
+                name: name.to_string(),
+                password: Password(String::new()),
+                protocol_version: None,
+            },
+        );
+        self.advance(1);
+
+        for (requester, request) in self.drain_database_requests() {
+            match request {
+                DatabaseRequest::Login { name, .. } if requester == user_id => {
+                    self.respond_from_database(
+                        user_id,
+                        DatabaseResponse::LoggedIn {
+                            account_id: user_id.0 as i64,
+                            name,
+                        },
+                    );
+                }
+                request => self.unanswered_requests.push((requester, request)),
+            }
+        }
+        self.advance(1);
+    }
+
+    /// Logs `user_id` in and spawns their player on the start map, returning once they
+    /// have been sent the map
+    pub fn join(&mut self, user_id: UserId, name: &str, sprite: SpriteTexture) {
+        self.log_in(user_id, name);
+        self.inject(
+            user_id,
+            ClientMessage::Initialize {
+                sprite,
+                protocol_version: None,
+            },
+        );
+        // The map goes out on the tick after the player is spawned
+        self.advance(2);
+    }
+
+    /// Sends an admin command and runs the tick that applies it
+    pub fn admin(&mut self, command: AdminCommand) -> AdminReply {
+        let (reply_sender, mut reply_receiver) = oneshot::channel();
+        self.admin_sender
+            .send((command, reply_sender))
+            .expect("The engine owns the receiver");
+        self.advance(1);
+        reply_receiver
+            .try_recv()
+            .expect("The engine answers admin commands every tick")
+    }
+
+    /// The engine's world, to look at components and resources directly
+    pub fn world(&self) -> &World {
+        &self.app.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.app.world
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ae_direction::BodyRelative;
+    use bevy::{
+        prelude::{Entity, With},
+        time::Time,
+    };
+    use core_api::{AdminResponse, LogMessage};
+
+    use super::*;
+    use std::{fs, path::Path};
+
+    use crate::{
+        components::{ai::Ai, session::Disconnected, MapPosition, User},
+        data::{
+            enemy_configs::EnemyConfigs, game_data::load_game_data, map_data::load_maps,
+            player_configs::SharedPlayerConfigs,
+        },
+        resources::{
+            config::EngineConfig,
+            world::{GameWorld, MapId},
+            CurrentUserMaps,
+        },
+    };
+
+    fn engine_with(config: EngineConfig) -> HeadlessEngine {
+        let data = load_game_data(&config.data_dir).unwrap();
+        let maps = load_maps(&config, &data.dialogue_contents).unwrap();
+
+        EngineBuilder::new(config, data)
+            .maps(maps)
+            .seed(7)
+            .headless()
+    }
+
+    fn engine() -> HeadlessEngine {
+        engine_with(EngineConfig {
+            maps_dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("../../maps"),
+            data_dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("../../data"),
+            ..Default::default()
+        })
+    }
+
+    fn camera(messages: &[ServerMessageSingleClient]) -> Option<ae_position::Position> {
+        messages.iter().rev().find_map(|message| match message {
+            ServerMessageSingleClient::CentreCamera(pos) => Some(pos.clone()),
+            ServerMessageSingleClient::UpdateFullGameMap { camera, .. } => Some(camera.clone()),
+            _ => None,
+        })
+    }
+
+    /// Where every enemy is and what it has decided to do, in a stable order
+    fn enemy_choices(engine: &mut HeadlessEngine) -> Vec<(u32, i32, i32, String)> {
+        let world = engine.world_mut();
+        let mut choices: Vec<_> = world
+            .query::<(Entity, &Ai, &MapPosition)>()
+            .iter(world)
+            .map(|(entity, ai, map_pos)| {
+                let action = format!("{:?}", ai.action);
+                (entity.index(), map_pos.pos.x, map_pos.pos.y, action)
+            })
+            .collect();
+        choices.sort();
+        choices
+    }
+
+    #[test]
+    fn enemies_make_the_same_choices_with_the_same_seed() {
+        let play = || {
+            // The generated maps are loaded before the engine is built, so they need it too
+            let mut engine = engine_with(EngineConfig {
+                maps_dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("../../maps"),
+                data_dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("../../data"),
+                rng_seed: Some(7),
+                ..Default::default()
+            });
+            engine.join(UserId(1), "alice", SpriteTexture::PcBoneyBoi);
+            engine
+                .admin(AdminCommand::Teleport {
+                    user_id: UserId(1),
+                    map_id: 2,
+                    pos: ae_position::Position { x: 12, y: 8 },
+                })
+                .unwrap();
+
+            let mut history = Vec::new();
+            for _ in 0..40 {
+                engine.advance(10);
+                history.push(enemy_choices(&mut engine));
+            }
+            history
+        };
+
+        let history = play();
+        assert!(
+            history
+                .iter()
+                .flatten()
+                .any(|(.., action)| !action.contains("Wander") && action != "None"),
+            "The enemies should have gone after alice"
+        );
+        assert_eq!(history, play());
+    }
+
+    #[test]
+    fn joining_sends_the_map_and_a_session_token() {
+        let mut engine = engine();
+        engine.join(UserId(1), "alice", SpriteTexture::PcBoneyBoi);
+
+        let messages = engine.drain_user(UserId(1));
+        assert!(messages
+            .iter()
+            .any(|message| matches!(message, ServerMessageSingleClient::LoggedIn { name } if name == "alice")));
+        assert!(messages
+            .iter()
+            .any(|message| matches!(message, ServerMessageSingleClient::SessionToken(_))));
+        assert!(camera(&messages).is_some());
+    }
+
+    #[test]
+    fn joining_without_logging_in_is_refused() {
+        let mut engine = engine();
+        engine.inject(
+            UserId(1),
+            ClientMessage::Initialize {
+                sprite: SpriteTexture::PcBoneyBoi,
+                protocol_version: None,
+            },
+        );
+        engine.advance(1);
+
+        assert!(engine
+            .drain_user(UserId(1))
+            .iter()
+            .any(|message| matches!(message, ServerMessageSingleClient::LoginFailed { .. })));
+    }
+
+    #[test]
+    fn every_tick_moves_the_clock_by_the_same_amount() {
+        let mut engine = engine();
+        engine.advance(3);
+
+        let tick_duration = EngineConfig::default().tick_duration();
+        let time = engine.world().resource::<Time>();
+        assert_eq!(time.delta(), tick_duration);
+        assert_eq!(time.elapsed(), tick_duration * 3);
+    }
+
+    #[test]
+    fn broadcasts_carry_the_tick_they_were_sent_on() {
+        let mut engine = engine();
+        engine.advance(4);
+        engine
+            .admin(AdminCommand::Broadcast("hello".to_string()))
+            .unwrap();
+
+        let logs: Vec<_> = engine
+            .drain_all_clients()
+            .into_iter()
+            .filter_map(|(tick, message)| match message {
+                ServerMessageAllClients::Log(LogMessage(log)) => Some((tick, log)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(logs, [(4, "hello".to_string())]);
+    }
+
+    #[test]
+    fn moving_recentres_the_camera() {
+        let mut engine = engine();
+        engine.join(UserId(1), "alice", SpriteTexture::PcBoneyBoi);
+        let start = camera(&engine.drain_user(UserId(1))).unwrap();
+
+        // One of the four directions is open unless the player spawned boxed in
+        let moved = [
+            BodyRelative::Up,
+            BodyRelative::Right,
+            BodyRelative::Down,
+            BodyRelative::Left,
+        ]
+        .into_iter()
+        .any(|key| {
+            engine.inject(UserId(1), ClientMessage::Keypress(key));
+            engine.advance(1);
+            camera(&engine.drain_user(UserId(1))).map_or(false, |pos| pos != start)
+        });
+
+        assert!(moved);
+    }
+
+    fn player_position(engine: &mut HeadlessEngine) -> (i32, i32, i32) {
+        let world = engine.world_mut();
+        let map_pos = world
+            .query_filtered::<&MapPosition, With<User>>()
+            .single(world);
+        (map_pos.map_id.0, map_pos.pos.x, map_pos.pos.y)
+    }
+
+    fn position_of(engine: &mut HeadlessEngine, user_id: UserId) -> (i32, i32, i32) {
+        let world = engine.world_mut();
+        let (_, map_pos) = world
+            .query::<(&User, &MapPosition)>()
+            .iter(world)
+            .find(|(user, _)| user.0 == user_id)
+            .unwrap();
+        (map_pos.map_id.0, map_pos.pos.x, map_pos.pos.y)
+    }
+
+    /// Sends every teleport before advancing, so they are all handled in the same tick
+    fn teleport_together(
+        engine: &mut HeadlessEngine,
+        teleports: &[(UserId, i32, i32, i32)],
+    ) -> Vec<AdminReply> {
+        let mut replies = Vec::new();
+        for (user_id, map_id, x, y) in teleports {
+            let (reply_sender, reply_receiver) = oneshot::channel();
+            let teleport = AdminCommand::Teleport {
+                user_id: *user_id,
+                map_id: *map_id,
+                pos: ae_position::Position { x: *x, y: *y },
+            };
+            engine.admin_sender.send((teleport, reply_sender)).unwrap();
+            replies.push(reply_receiver);
+        }
+        engine.advance(2);
+
+        replies
+            .into_iter()
+            .map(|mut reply| reply.try_recv().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn players_teleported_to_the_same_tile_in_one_tick_dont_share_it() {
+        let mut engine = engine();
+        engine.join(UserId(1), "alice", SpriteTexture::PcBoneyBoi);
+        engine.join(UserId(2), "bob", SpriteTexture::PcBoneyBoi);
+
+        let replies = teleport_together(&mut engine, &[(UserId(1), 1, 8, 3), (UserId(2), 1, 8, 3)]);
+        assert!(replies[0].is_ok());
+        assert_eq!(
+            replies[1].as_ref().unwrap_err(),
+            "Position { x: 8, y: 3 } is not an open tile on map 1"
+        );
+        assert_eq!(position_of(&mut engine, UserId(1)), (1, 8, 3));
+        assert_ne!(position_of(&mut engine, UserId(2)), (1, 8, 3));
+    }
+
+    #[test]
+    fn players_warping_to_the_same_tile_in_one_tick_dont_share_it() {
+        let mut engine = engine();
+        engine.join(UserId(1), "alice", SpriteTexture::PcBoneyBoi);
+        engine.join(UserId(2), "bob", SpriteTexture::PcBoneyBoi);
+
+        // One lands on the ladder down as the other is sent to the bottom of it
+        let replies =
+            teleport_together(&mut engine, &[(UserId(1), 1, 7, 3), (UserId(2), 2, 11, 1)]);
+        assert!(replies.iter().all(Result::is_ok));
+
+        // Only one of them fits at the bottom, the other waits on the ladder
+        let mut positions = [
+            position_of(&mut engine, UserId(1)),
+            position_of(&mut engine, UserId(2)),
+        ];
+        positions.sort();
+        assert_eq!(positions, [(1, 7, 3), (2, 11, 1)]);
+
+        // And the ladder up leads back
+        let below = if position_of(&mut engine, UserId(1)).0 == 2 {
+            UserId(1)
+        } else {
+            UserId(2)
+        };
+        engine.inject(below, ClientMessage::Keypress(BodyRelative::Left));
+        engine.advance(10);
+        assert_eq!(position_of(&mut engine, below), (1, 8, 3));
+    }
+
+    #[test]
+    fn hovering_describes_what_stands_on_the_tile() {
+        let mut engine = engine();
+        engine.join(UserId(1), "alice", SpriteTexture::PcBoneyBoi);
+        engine.drain_user(UserId(1));
+
+        let hover = |engine: &mut HeadlessEngine, x, y| {
+            let pos = ae_position::Position { x, y };
+            engine.inject(UserId(1), ClientMessage::TileHover(pos));
+            engine.advance(1);
+            engine
+                .drain_user(UserId(1))
+                .into_iter()
+                .find_map(|message| match message {
+                    ServerMessageSingleClient::TileHover(data) => Some(data),
+                    _ => None,
+                })
+                .expect("Every hover is answered")
+        };
+
+        // The real estate dick rather than the grass under him
+        let data = hover(&mut engine, 2, 1).unwrap();
+        assert_eq!(data.name, "Real Estate Dick");
+        assert!(data.description.is_some());
+
+        // Off the edge of the map there's nothing to describe
+        assert!(hover(&mut engine, -1, 500).is_none());
+        assert!(hover(&mut engine, 500, 1).is_none());
+    }
+
+    #[test]
+    fn warp_teevees_lead_between_maps() {
+        let mut engine = engine();
+        engine.join(UserId(1), "alice", SpriteTexture::PcBoneyBoi);
+        engine
+            .admin(AdminCommand::Teleport {
+                user_id: UserId(1),
+                map_id: 1,
+                pos: ae_position::Position { x: 1, y: 2 },
+            })
+            .unwrap();
+
+        // The teevee in the corner of each map leads to the other
+        engine.inject(UserId(1), ClientMessage::Keypress(BodyRelative::Up));
+        engine.advance(10);
+        assert_eq!(player_position(&mut engine), (2, 2, 1));
+
+        engine.inject(UserId(1), ClientMessage::Keypress(BodyRelative::Left));
+        engine.advance(10);
+        assert_eq!(player_position(&mut engine), (1, 1, 2));
+    }
+
+    fn entities_on_map(engine: &mut HeadlessEngine, map_id: i32) -> usize {
+        let world = engine.world_mut();
+        world
+            .query::<&MapPosition>()
+            .iter(world)
+            .filter(|map_pos| map_pos.map_id == MapId(map_id))
+            .count()
+    }
+
+    #[test]
+    fn instances_are_torn_down_once_the_last_player_leaves() {
+        let mut engine = engine();
+        engine.join(UserId(1), "alice", SpriteTexture::PcBoneyBoi);
+
+        let Ok(AdminResponse::MapCreated(instance)) =
+            engine.admin(AdminCommand::CreateInstance { template_map_id: 2 })
+        else {
+            panic!("The sewers should be copied");
+        };
+        engine.advance(1);
+        assert_eq!(
+            entities_on_map(&mut engine, instance),
+            entities_on_map(&mut engine, 2)
+        );
+
+        // Kept until someone has been in it
+        engine.advance(5);
+        engine
+            .admin(AdminCommand::Teleport {
+                user_id: UserId(1),
+                map_id: instance,
+                pos: ae_position::Position { x: 2, y: 1 },
+            })
+            .unwrap();
+        assert_eq!(player_position(&mut engine), (instance, 2, 1));
+
+        // The copy's teevee still leads home
+        engine.inject(UserId(1), ClientMessage::Keypress(BodyRelative::Left));
+        engine.advance(10);
+        assert_eq!(player_position(&mut engine), (1, 1, 2));
+        assert_eq!(entities_on_map(&mut engine, instance), 0);
+        assert!(!engine
+            .world()
+            .resource::<GameWorld>()
+            .game_maps
+            .contains_key(&MapId(instance)));
+    }
+
+    #[test]
+    fn instances_nobody_enters_are_closed_and_their_spectators_moved_home() {
+        let mut engine = engine_with(EngineConfig {
+            maps_dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("../../maps"),
+            data_dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("../../data"),
+            instance_entry_timeout_secs: 1.0,
+            ..Default::default()
+        });
+
+        let Ok(AdminResponse::MapCreated(instance)) =
+            engine.admin(AdminCommand::CreateInstance { template_map_id: 2 })
+        else {
+            panic!("The sewers should be copied");
+        };
+        engine.inject(UserId(2), ClientMessage::Spectate { map_id: instance });
+        engine.advance(2);
+        let watching = |engine: &HeadlessEngine| {
+            engine.world().resource::<CurrentUserMaps>().0[&UserId(2)].map_id
+        };
+        assert_eq!(watching(&engine), MapId(instance));
+        engine.drain_user(UserId(2));
+
+        // A second at the default 20 ticks a second
+        engine.advance(20);
+        assert!(!engine
+            .world()
+            .resource::<GameWorld>()
+            .game_maps
+            .contains_key(&MapId(instance)));
+        assert_eq!(entities_on_map(&mut engine, instance), 0);
+        assert_eq!(watching(&engine), MapId(1));
+        assert!(engine
+            .drain_user(UserId(2))
+            .iter()
+            .any(|message| matches!(message, ServerMessageSingleClient::UpdateFullGameMap { .. })));
+    }
+
+    fn waiting_players(engine: &mut HeadlessEngine) -> usize {
+        let world = engine.world_mut();
+        world.query::<&Disconnected>().iter(world).count()
+    }
+
+    #[test]
+    fn disconnected_players_are_removed_once_the_grace_period_passes() {
+        let mut engine = engine();
+        engine.join(UserId(1), "alice", SpriteTexture::PcBoneyBoi);
+        engine.inject(UserId(1), ClientMessage::Disconnect);
+        engine.advance(1);
+
+        let grace_period = EngineConfig::default().session_grace_period_secs as u32;
+        engine.set_tick_duration(Duration::from_secs(1));
+        engine.advance(grace_period - 1);
+        assert_eq!(waiting_players(&mut engine), 1);
+
+        engine.advance(2);
+        assert_eq!(waiting_players(&mut engine), 0);
+    }
+
+    #[test]
+    fn data_is_only_reloaded_when_every_file_is_fine() {
+        let shipped = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../data");
+        let data_dir = std::env::temp_dir().join(format!("reload-data-{}", std::process::id()));
+        fs::create_dir_all(&data_dir).unwrap();
+        for entry in fs::read_dir(&shipped).unwrap() {
+            let path = entry.unwrap().path();
+            fs::copy(&path, data_dir.join(path.file_name().unwrap())).unwrap();
+        }
+
+        let mut engine = engine_with(EngineConfig {
+            maps_dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("../../maps"),
+            data_dir: data_dir.clone(),
+            ..Default::default()
+        });
+        let rat_hp = |engine: &HeadlessEngine| engine.world().resource::<EnemyConfigs>().rat.hp.max;
+        assert_eq!(rat_hp(&engine), 10);
+
+        let enemy_configs = data_dir.join("enemy_configs.ron");
+        let text = fs::read_to_string(&enemy_configs).unwrap();
+        fs::write(&enemy_configs, text.replace("max: 10,", "max: 10,,")).unwrap();
+        let error = engine.admin(AdminCommand::ReloadData).unwrap_err();
+        assert!(error.contains("enemy_configs.ron"), "{}", error);
+        assert_eq!(rat_hp(&engine), 10);
+
+        fs::write(&enemy_configs, text.replace("max: 10,", "max: 12,")).unwrap();
+        let player_configs = data_dir.join("player_configs.ron");
+        let text = fs::read_to_string(&player_configs).unwrap();
+        fs::write(
+            &player_configs,
+            text.replacen("visibility: 10,", "visibility: 11,", 1),
+        )
+        .unwrap();
+        let shared_player_configs = engine.world().resource::<SharedPlayerConfigs>().clone();
+        assert!(engine.admin(AdminCommand::ReloadData).is_ok());
+        assert_eq!(rat_hp(&engine), 12);
+        // What `api/player-stats` serves follows the reload
+        assert_eq!(shared_player_configs.read().ghost_boy.visibility, 11);
+
+        fs::remove_dir_all(&data_dir).ok();
+    }
+}
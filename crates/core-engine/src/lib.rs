@@ -1,109 +1,298 @@
 pub mod components;
 pub mod data;
 pub mod events;
+pub mod generation;
+pub mod headless;
 pub mod resources;
 pub mod systems;
 
 use bevy::{
-    prelude::{App, IntoSystemDescriptor},
-    time::Time,
+    app::{PluginGroup, ScheduleRunnerSettings},
+    log::info,
+    prelude::{App, CoreStage, IntoSystemDescriptor},
+    time::{Time, TimePlugin},
     MinimalPlugins,
 };
 
 use components::cooldown;
 use core_api::{
-    ClientMessage, DatabaseRequest, DatabaseResponse, ServerMessageAllClients,
-    ServerMessageSingleClient, UserId,
+    AdminCommand, AdminReply, ClientMessage, DatabaseRequest, DatabaseResponse,
+    ServerMessageAllClients, ServerMessageSingleClient, SharedMetrics, TickOutput, UserId,
 };
 use data::{
-    dialogue_contents::DialogueContents, dialogue_contents_str, enemy_configs::EnemyConfigs,
-    enemy_configs_str, player_configs::PlayerConfigs, player_configs_str,
+    game_data::{last_modified, GameData},
+    map_layout::MapLayout,
+    player_configs::SharedPlayerConfigs,
+    tile_properties::TileProperties,
+    TILE_PROPERTIES,
 };
-use resources::{DatabaseReceiver, DatabaseSender, SpawnStopWatch, SpawnableEnemyBuffer};
+use headless::HeadlessEngine;
+use resources::{
+    config::EngineConfig, rng::GameRng, AdminReceiver, AuthenticatedUsers, DataWatch,
+    DatabaseReceiver, DatabaseSender, GameClock, GenerateMapBuffer, InstanceBuffer, KickBuffer,
+    LoginBuffer, MapInstances, MapLayouts, Metrics, OutboundAllClientsReceiver,
+    OutboundReceiver, PendingLogins, ReloadDataBuffer, ResumeBuffer, ShutdownReceiver,
+    SpawnStopWatch, SpawnableEnemyBuffer, SpectateBuffer, Spectators, TeleportBuffer, Tick,
+    TickSender, TickTimer,
+};
+use std::{sync::Arc, time::Instant};
 use systems::{
+    admin::{admin_system, kick_system, teleport_system},
     ai::ai_system,
+    clock::clock_system,
     cooldown::cooldown_system,
     death::death_system,
     debug::debug_system,
+    instances::{close_instances_system, create_instance_system},
+    login::login_system,
+    metrics::{metrics_system, tick_start_system},
+    outbound::outbound_flush_system,
     persistence::{database_receiver_system, database_sender_system},
+    reload_data::reload_data_system,
     resolve_consume::resolve_consume_system,
     resolve_melee_attack::resolve_melee_attack_system,
     resolve_move::resolve_move_system,
     resolve_speak::resolve_speak_system,
+    resume_game::resume_game_system,
+    shutdown::shutdown_system,
     spawn_enemy::spawn_enemy_system,
+    spectate::spectate_system,
+};
+use tokio::sync::{
+    mpsc::{self, Sender, UnboundedReceiver, UnboundedSender},
+    oneshot,
 };
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
 use crate::{
-    events::{ShouldSendFullMapUpdateToClient, ShouldUpdateMap},
+    events::{ShouldSendFullMapUpdateToClient, ShouldSendFullMapUpdateToUser, ShouldUpdateMap},
     resources::{
         world::GameWorld, ConnectBuffer, CurrentUserMaps, DebugStopwatch, DisconnectBuffer,
         KeypressBuffer, MessageReceiver, MessageSenderAllClients, MessageSenderSingleClient,
         MouseClickBuffer, MouseHoverBuffer,
     },
     systems::{
-        build_maps::build_maps_system, change_map::change_map_system, join_game::join_game_system,
-        leave_game::leave_game_system, message::message_system, mouse_click::mouse_click_system,
-        mouse_hover::mouse_hover_system, movement_keys::movement_keys_system,
-        update_client::update_client_system, update_map::update_map_system,
+        build_maps::{build_maps_system, generate_map_system},
+        change_map::change_map_system,
+        join_game::join_game_system,
+        leave_game::{expire_sessions_system, leave_game_system},
+        message::message_system,
+        mouse_click::mouse_click_system,
+        mouse_hover::mouse_hover_system,
+        movement_keys::movement_keys_system,
+        update_client::update_client_system,
+        update_map::update_map_system,
     },
 };
 
-pub fn start_game_engine(
-    client_receiver: UnboundedReceiver<(UserId, ClientMessage)>,
-    server_sender_single_client: UnboundedSender<(UserId, ServerMessageSingleClient)>,
-    server_sender_all_clients: UnboundedSender<ServerMessageAllClients>,
-    db_sender: UnboundedSender<(UserId, DatabaseRequest)>,
-    db_receiver: UnboundedReceiver<(UserId, DatabaseResponse)>,
-) {
-    App::new()
-        .insert_resource(MessageReceiver(client_receiver))
-        .insert_resource(DatabaseSender(db_sender))
-        .insert_resource(DatabaseReceiver(db_receiver))
-        .insert_resource(MessageSenderSingleClient(server_sender_single_client))
-        .insert_resource(MessageSenderAllClients(server_sender_all_clients))
-        .insert_resource(GameWorld::default())
-        .insert_resource(KeypressBuffer::default())
-        .insert_resource(DisconnectBuffer::default())
-        .insert_resource(ConnectBuffer::default())
-        .insert_resource(MouseHoverBuffer::default())
-        .insert_resource(MouseClickBuffer::default())
-        .insert_resource(SpawnableEnemyBuffer::default())
-        .insert_resource(DebugStopwatch::new())
-        .insert_resource(SpawnStopWatch::new())
-        .insert_resource(Time::default())
-        .insert_resource(CurrentUserMaps::default())
-        .insert_resource(ron::from_str::<PlayerConfigs>(player_configs_str).unwrap())
-        .insert_resource(ron::from_str::<EnemyConfigs>(enemy_configs_str).unwrap())
-        .insert_resource(ron::from_str::<DialogueContents>(dialogue_contents_str).unwrap())
-        .add_event::<ShouldUpdateMap>()
-        .add_event::<ShouldSendFullMapUpdateToClient>()
-        .add_startup_system(build_maps_system)
-        .add_system(update_client_system.before(message_system))
-        .add_system(message_system)
-        .add_system(cooldown_system)
-        .add_system(join_game_system.after(message_system))
-        .add_system(spawn_enemy_system.after(message_system))
-        .add_system(movement_keys_system.after(message_system))
-        .add_system(ai_system.after(movement_keys_system))
-        .add_system(resolve_move_system.after(message_system))
-        // .add_system(pathing_system.after(message_system))
-        // THIS SYSTEM WILL PANIC
-        // .add_system(mouse_hover_system.after(message_system))
-        .add_system(mouse_click_system.after(message_system))
-        .add_system(leave_game_system.after(message_system))
-        .add_system(change_map_system.after(message_system))
-        // Don't run the map updater until after entities have moved
-        .add_system(
-            update_map_system.after(movement_keys_system), // .after(combat_system), // .after(pathing_system),
-        )
-        .add_system(resolve_melee_attack_system.after(update_map_system))
-        .add_system(death_system.after(resolve_melee_attack_system))
-        .add_system(resolve_speak_system.after(update_map_system))
-        .add_system(resolve_consume_system.after(update_map_system))
-        .add_system(database_sender_system.after(update_map_system))
-        .add_system(database_receiver_system.after(update_map_system))
-        .add_system(debug_system.after(database_receiver_system))
-        .add_plugins(MinimalPlugins)
-        .run();
+/// The ends of the channels the engine shares with the server and the database
+pub struct EngineChannels {
+    pub client_receiver: UnboundedReceiver<(UserId, ClientMessage)>,
+    /// Everything sent during a tick, bounded so the engine waits for a server that can't
+    /// keep up rather than piling up messages without limit
+    pub server_sender: Sender<TickOutput>,
+    pub db_sender: UnboundedSender<(UserId, DatabaseRequest)>,
+    pub db_receiver: UnboundedReceiver<(UserId, DatabaseResponse)>,
+    pub admin_receiver: UnboundedReceiver<(AdminCommand, oneshot::Sender<AdminReply>)>,
+    pub shutdown_receiver: oneshot::Receiver<()>,
+}
+
+/// Sets up a game engine, either to run forever behind the server or to be stepped
+/// through one tick at a time as a [`HeadlessEngine`]
+pub struct EngineBuilder {
+    config: EngineConfig,
+    maps: Vec<MapLayout>,
+    data: GameData,
+    shared_player_configs: SharedPlayerConfigs,
+    metrics: Arc<SharedMetrics>,
+}
+
+impl EngineBuilder {
+    /// An engine playing with the given player, enemy and dialogue data, usually from
+    /// [`load_game_data`](data::game_data::load_game_data)
+    pub fn new(config: EngineConfig, data: GameData) -> Self {
+        Self {
+            config,
+            maps: Vec::new(),
+            shared_player_configs: SharedPlayerConfigs::new(data.player_configs.clone()),
+            data,
+            metrics: Arc::new(SharedMetrics::default()),
+        }
+    }
+
+    /// The maps to play on, usually from [`load_maps`](data::map_data::load_maps)
+    pub fn maps(mut self, maps: Vec<MapLayout>) -> Self {
+        self.maps = maps;
+        self
+    }
+
+    /// Where figures for the metrics endpoint are published, by default nobody reads them
+    pub fn metrics(mut self, metrics: Arc<SharedMetrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// The player configs the engine is playing with, updated whenever the data is reloaded
+    pub fn shared_player_configs(&self) -> SharedPlayerConfigs {
+        self.shared_player_configs.clone()
+    }
+
+    /// Replaces `rng_seed` from the config so every run plays out the same way
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.rng_seed = Some(seed);
+        self
+    }
+
+    /// Runs the game on the current thread at the configured tick rate until
+    /// `shutdown_receiver` fires or its sender is dropped
+    pub fn run(self, channels: EngineChannels) {
+        let tick_duration = self.config.tick_duration();
+
+        self.build_app(channels)
+            .insert_resource(ScheduleRunnerSettings::run_loop(tick_duration))
+            // `clock_system` keeps time instead
+            .add_plugins(MinimalPlugins.build().disable::<TimePlugin>())
+            .run();
+    }
+
+    /// An engine with in-memory channels that only moves when told to
+    pub fn headless(self) -> HeadlessEngine {
+        HeadlessEngine::new(self)
+    }
+
+    /// Everything but the plugins, which decide how time passes and how often the game updates
+    fn build_app(self, channels: EngineChannels) -> App {
+        let EngineBuilder {
+            config,
+            maps,
+            data,
+            shared_player_configs,
+            metrics,
+        } = self;
+        let data_watch = DataWatch::new(last_modified(&config.data_dir));
+
+        // Single client messages are collected here and sent to the server in one batch per tick
+        let (outbound_sender, outbound_receiver) =
+            mpsc::unbounded_channel::<(UserId, ServerMessageSingleClient)>();
+
+        // Messages for all clients wait for the end of the tick too, to be given its number
+        let (all_clients_sender, all_clients_receiver) =
+            mpsc::unbounded_channel::<ServerMessageAllClients>();
+
+        // Starts one tick behind so the first tick has a full tick's worth of time
+        let now = Instant::now();
+        let mut time = Time::new(now);
+        time.update_with_instant(now);
+        let clock = GameClock {
+            tick_duration: config.tick_duration(),
+            now,
+        };
+
+        let rng = GameRng::from_seed_or_random(config.rng_seed);
+        info!("Game engine random seed is {}", rng.seed());
+
+        let mut app = App::new();
+        app.insert_resource(MessageReceiver(channels.client_receiver))
+            .insert_resource(DatabaseSender::new(channels.db_sender, metrics.clone()))
+            .insert_resource(DatabaseReceiver(channels.db_receiver))
+            .insert_resource(AdminReceiver(channels.admin_receiver))
+            .insert_resource(ShutdownReceiver(channels.shutdown_receiver))
+            .insert_resource(KickBuffer::default())
+            .insert_resource(TeleportBuffer::default())
+            .insert_resource(GenerateMapBuffer::default())
+            .insert_resource(InstanceBuffer::default())
+            .insert_resource(ReloadDataBuffer::default())
+            .insert_resource(data_watch)
+            .insert_resource(MapInstances::default())
+            .insert_resource(MessageSenderSingleClient(outbound_sender))
+            .insert_resource(OutboundReceiver(outbound_receiver))
+            .insert_resource(TickSender(channels.server_sender))
+            .insert_resource(Tick::default())
+            .insert_resource(Metrics(metrics))
+            .insert_resource(TickTimer::default())
+            .insert_resource(MessageSenderAllClients(all_clients_sender))
+            .insert_resource(OutboundAllClientsReceiver(all_clients_receiver))
+            .insert_resource(GameWorld::new(&maps))
+            .insert_resource(MapLayouts(maps))
+            .insert_resource(KeypressBuffer::default())
+            .insert_resource(DisconnectBuffer::default())
+            .insert_resource(ConnectBuffer::default())
+            .insert_resource(LoginBuffer::default())
+            .insert_resource(PendingLogins::default())
+            .insert_resource(AuthenticatedUsers::default())
+            .insert_resource(ResumeBuffer::default())
+            .insert_resource(SpectateBuffer::default())
+            .insert_resource(Spectators::default())
+            .insert_resource(config)
+            .insert_resource(rng)
+            .insert_resource(MouseHoverBuffer::default())
+            .insert_resource(MouseClickBuffer::default())
+            .insert_resource(SpawnableEnemyBuffer::default())
+            .insert_resource(DebugStopwatch::new())
+            .insert_resource(SpawnStopWatch::new())
+            .insert_resource(time)
+            .insert_resource(clock)
+            .insert_resource(CurrentUserMaps::default())
+            .insert_resource(data.player_configs)
+            .insert_resource(shared_player_configs)
+            .insert_resource(data.enemy_configs)
+            .insert_resource(data.dialogue_contents)
+            .insert_resource(ron::from_str::<TileProperties>(TILE_PROPERTIES).unwrap())
+            .add_event::<ShouldUpdateMap>()
+            .add_event::<ShouldSendFullMapUpdateToClient>()
+            .add_event::<ShouldSendFullMapUpdateToUser>()
+            .add_startup_system(build_maps_system)
+            .add_system(update_client_system.before(message_system))
+            .add_system(message_system)
+            .add_system(shutdown_system)
+            .add_system(admin_system)
+            .add_system(cooldown_system)
+            .add_system(login_system.after(message_system))
+            .add_system(join_game_system.after(message_system))
+            .add_system(spawn_enemy_system.after(message_system))
+            .add_system(movement_keys_system.after(message_system))
+            .add_system(ai_system.after(movement_keys_system))
+            .add_system(resolve_move_system.after(message_system))
+            // .add_system(pathing_system.after(message_system))
+            .add_system(mouse_hover_system.after(message_system))
+            .add_system(mouse_click_system.after(message_system))
+            .add_system(leave_game_system.after(message_system))
+            .add_system(resume_game_system.after(message_system))
+            .add_system(spectate_system.after(message_system))
+            .add_system(kick_system.after(admin_system))
+            .add_system(teleport_system.after(admin_system))
+            .add_system(generate_map_system.after(admin_system))
+            .add_system(create_instance_system.after(generate_map_system))
+            .add_system(reload_data_system.after(admin_system))
+            .add_system(expire_sessions_system.after(leave_game_system))
+            // Players teleported onto a portal go through it in the same tick
+            .add_system(
+                change_map_system
+                    .after(resolve_move_system)
+                    .after(teleport_system),
+            )
+            .add_system(
+                close_instances_system
+                    .after(change_map_system)
+                    .after(teleport_system)
+                    .after(kick_system)
+                    .after(expire_sessions_system),
+            )
+            // Don't run the map updater until after entities have moved
+            .add_system(
+                update_map_system.after(movement_keys_system), // .after(combat_system), // .after(pathing_system),
+            )
+            .add_system(resolve_melee_attack_system.after(update_map_system))
+            .add_system(death_system.after(resolve_melee_attack_system))
+            .add_system(resolve_speak_system.after(update_map_system))
+            .add_system(resolve_consume_system.after(update_map_system))
+            .add_system(database_sender_system.after(update_map_system))
+            .add_system(database_receiver_system.after(update_map_system))
+            .add_system(debug_system.after(database_receiver_system))
+            // Runs after every other system so each batch holds a whole tick
+            .add_system_to_stage(CoreStage::Last, outbound_flush_system)
+            .add_system_to_stage(CoreStage::First, clock_system)
+            .add_system_to_stage(CoreStage::First, tick_start_system)
+            .add_system_to_stage(CoreStage::Last, metrics_system.after(outbound_flush_system));
+        app
+    }
 }
@@ -0,0 +1,60 @@
+use std::{path::PathBuf, time::Duration};
+
+use bevy::prelude::Resource;
+use serde::Deserialize;
+
+use super::map::{BAD_GUY_MAP_ID, PEACEFUL_MAP_ID};
+
+/// Tuning values for the game engine, read from the `engine` section of the server config
+#[derive(Resource, Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    /// How long (in seconds) a disconnected player's entity is kept around waiting to be resumed
+    pub session_grace_period_secs: f32,
+    /// How long (in seconds) a map instance nobody has entered yet is kept before it is closed
+    pub instance_entry_timeout_secs: f32,
+    /// How often (in seconds) an enemy may be spawned on the enemy map
+    pub spawn_interval_secs: f32,
+    /// How often (in seconds) debug data is sent to every client
+    pub debug_interval_secs: f32,
+    /// Every `.ron`, `.tmj` and `.tmx` file in here is loaded as a map when the server starts
+    pub maps_dir: PathBuf,
+    /// Holds `player_configs.ron`, `enemy_configs.ron` and `dialogue_contents.ron`
+    pub data_dir: PathBuf,
+    /// Reloads the data files whenever one of them is saved, they are otherwise only
+    /// reloaded when the admin API asks
+    pub watch_data: bool,
+    /// The map new players are placed on
+    pub start_map_id: i32,
+    /// The map enemies are spawned on
+    pub enemy_map_id: i32,
+    /// Ticks the game runs per second
+    pub tick_rate: u32,
+    /// Seeds every random roll in the game, a random seed is picked (and logged) when unset
+    pub rng_seed: Option<u64>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            session_grace_period_secs: 60.0,
+            instance_entry_timeout_secs: 300.0,
+            spawn_interval_secs: 5.0,
+            debug_interval_secs: 0.5,
+            maps_dir: PathBuf::from("maps"),
+            data_dir: PathBuf::from("data"),
+            watch_data: false,
+            start_map_id: PEACEFUL_MAP_ID,
+            enemy_map_id: BAD_GUY_MAP_ID,
+            tick_rate: 20,
+            rng_seed: None,
+        }
+    }
+}
+
+impl EngineConfig {
+    /// How much game time passes each tick, a tick rate of 0 is treated as 1
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs(1) / self.tick_rate.max(1)
+    }
+}
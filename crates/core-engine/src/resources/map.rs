@@ -1,8 +1,6 @@
-use std::sync::atomic::{AtomicI32, Ordering};
-
 use ae_position::{Dimensions2d, Position};
 use bevy::prelude::Resource;
-use rand::seq::SliceRandom;
+use rand::{seq::SliceRandom, Rng};
 use simple_astar::astar;
 use tv_shadowcasting::get_visible_idxs;
 
@@ -15,8 +13,6 @@ pub const PEACEFUL_MAP_ID: i32 = 1;
 
 pub const BAD_GUY_MAP_ID: i32 = 2;
 
-pub static MAP_ID_COUNTER: AtomicI32 = AtomicI32::new(PEACEFUL_MAP_ID);
-
 type IndexGrid = Vec<u8>;
 
 #[derive(Debug)]
@@ -34,15 +30,11 @@ impl VisibilityGrid {
     }
 
     pub fn position_visible(&self, pos: &Position) -> bool {
-        // [TODO] This has panicked before
-        let grid_pos = self.grid.get(pos.to_idx(self.width));
-        match grid_pos {
-            Some(grid_pos) => *grid_pos == 1,
-            None => {
-                panic!("position_visible panicked on {:?}", pos)
-            },
+        // Off the grid nothing is visible, and an x past the edge would wrap onto the next row
+        if pos.x < 0 || pos.y < 0 || pos.x as usize >= self.width {
+            return false;
         }
-        // self.grid[pos.to_idx(self.width)] == 1
+        self.grid.get(pos.to_idx(self.width)) == Some(&1)
     }
 
     // #[allow(dead_code)]
@@ -113,7 +105,7 @@ pub struct GameMap {
 }
 
 impl GameMap {
-    fn inside_map_bounds(&self, pos: &Position) -> bool {
+    pub fn inside_map_bounds(&self, pos: &Position) -> bool {
         pos.x >= 0 && pos.x < self.dimensions.width && pos.y >= 0 && pos.y < self.dimensions.height
     }
 
@@ -127,9 +119,9 @@ impl GameMap {
         self.map_id
     }
 
-    pub fn new(dimensions: Dimensions2d) -> Self {
+    pub fn new(map_id: MapId, dimensions: Dimensions2d) -> Self {
         Self {
-            map_id: MapId(MAP_ID_COUNTER.fetch_add(1, Ordering::Relaxed)),
+            map_id,
             dimensions: dimensions.clone(),
             light_blocking_grid: LightBlockingGrid::new(dimensions.width, dimensions.height),
             movement_blocking_grid: MovementBlockingGrid::new(dimensions.width, dimensions.height),
@@ -248,20 +240,21 @@ impl GameMap {
         println!();
     }
 
-    /// Returns a random position on the map that doesn't block movement
-    pub fn random_movement_unblocked_tile(&self) -> Position {
+    /// Whether anything more can be placed on the map
+    pub fn has_movement_unblocked_tile(&self) -> bool {
+        self.movement_blocking_grid.0.contains(&0)
+    }
+
+    /// Returns a random position on the map that doesn't block movement, or `None` when
+    /// every tile is taken
+    pub fn random_movement_unblocked_tile(&self, rng: &mut impl Rng) -> Option<Position> {
         let map_width = self.width() as usize;
         let unblocked_positions =
             index_grid_to_positions(&self.movement_blocking_grid.0, map_width, false);
 
         // self.pretty_print_idx_map(&self.movement_blocking_grid.0);
 
-        let position = unblocked_positions
-            .choose(&mut rand::thread_rng())
-            .expect("Attempted to find a random unblocked tile, but apparently there are none")
-            .clone();
-
-        position
+        unblocked_positions.choose(rng).cloned()
     }
 }
 
@@ -275,6 +268,7 @@ impl GameMap {
 mod tests {
 
     use super::*;
+    use rand::SeedableRng;
 
     #[test]
     fn perimeter_index_grid_works() {
@@ -288,4 +282,26 @@ mod tests {
         assert_eq!(grid[row_len], 1);
         assert_eq!(grid[row_len + 1], 0);
     }
+
+    #[test]
+    fn a_full_map_has_no_random_tile() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(1);
+        let mut map = GameMap::new(
+            MapId(1),
+            Dimensions2d {
+                width: 3,
+                height: 3,
+            },
+        );
+
+        // Only the middle is inside the walls
+        assert_eq!(
+            map.random_movement_unblocked_tile(&mut rng),
+            Some(Position { x: 1, y: 1 })
+        );
+
+        map.set_blocks_movement(&Position { x: 1, y: 1 });
+        assert!(!map.has_movement_unblocked_tile());
+        assert_eq!(map.random_movement_unblocked_tile(&mut rng), None);
+    }
 }
@@ -1,32 +1,157 @@
+pub mod config;
 pub mod map;
+pub mod rng;
 pub mod user_id_resource;
 pub mod world;
 
-use std::collections::{HashMap, VecDeque};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant, SystemTime},
+};
 
 use ae_direction::BodyRelative;
 use ae_position::Position;
 use bevy::{prelude::Resource, time::Stopwatch};
 use core_api::{
-    ClientMessage, DatabaseRequest, DatabaseResponse, ServerMessageAllClients,
-    ServerMessageSingleClient, SpawnableEnemy, SpriteTexture, UserId,
+    AdminCommand, AdminReply, ClientMessage, DatabaseRequest, DatabaseResponse,
+    MapGeneratorSettings, ServerMessageAllClients, ServerMessageSingleClient, SharedMetrics,
+    SpawnableEnemy, SpriteTexture, TickOutput, UserId,
+};
+use tokio::sync::{
+    mpsc::{Sender, UnboundedReceiver, UnboundedSender},
+    oneshot,
 };
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
-use crate::components::MapPosition;
+use crate::{
+    components::{account::Account, MapPosition},
+    data::map_layout::MapLayout,
+};
+
+use self::world::MapId;
 
 #[derive(Resource)]
 pub struct MessageReceiver(pub UnboundedReceiver<(UserId, ClientMessage)>);
 
+/// Systems send single client messages here, they are held until the end of the tick
 #[derive(Resource)]
 pub struct MessageSenderSingleClient(pub UnboundedSender<(UserId, ServerMessageSingleClient)>);
 
+/// Everything sent with `MessageSenderSingleClient` during the current tick
+#[derive(Resource)]
+pub struct OutboundReceiver(pub UnboundedReceiver<(UserId, ServerMessageSingleClient)>);
+
+/// Sends everything from one tick to the server at once, waiting for room when the
+/// server falls behind
+#[derive(Resource)]
+pub struct TickSender(pub Sender<TickOutput>);
+
+/// Messages for every client sent during the current tick, see `MessageSenderAllClients`
+#[derive(Resource)]
+pub struct OutboundAllClientsReceiver(pub UnboundedReceiver<ServerMessageAllClients>);
+
+/// Number of the tick currently being run
+#[derive(Resource, Default)]
+pub struct Tick(pub u32);
+
+/// The game clock, which moves forward by exactly `tick_duration` every tick however long
+/// the tick really took, so nothing timed in the game depends on how fast the server is
+#[derive(Resource)]
+pub struct GameClock {
+    pub tick_duration: Duration,
+    pub now: Instant,
+}
+
+/// The maps loaded at startup, which `build_maps_system` builds the game maps from, along
+/// with any generated since. Instances are copies of these.
+#[derive(Resource)]
+pub struct MapLayouts(pub Vec<MapLayout>);
+
+/// A private copy of a map, torn down once the last player has left it
+pub struct MapInstance {
+    pub template: MapId,
+    /// Whether a player has been on it yet, it's kept until one has or it times out
+    pub entered: bool,
+    /// Seconds left for a player to enter it before it is closed anyway
+    pub entry_time_remaining: f32,
+}
+
+#[derive(Resource, Default)]
+pub struct MapInstances(pub HashMap<MapId, MapInstance>);
+
+/// Fires when the server is shutting down and the app should exit
+#[derive(Resource)]
+pub struct ShutdownReceiver(pub oneshot::Receiver<()>);
+
+#[derive(Resource)]
+pub struct AdminReceiver(pub UnboundedReceiver<(AdminCommand, oneshot::Sender<AdminReply>)>);
+
+#[derive(Resource, Default)]
+pub struct KickBuffer(pub VecDeque<(UserId, oneshot::Sender<AdminReply>)>);
+
+#[derive(Resource, Default)]
+pub struct TeleportBuffer(pub VecDeque<(UserId, MapPosition, oneshot::Sender<AdminReply>)>);
+
+/// Maps to make private copies of
+#[derive(Resource, Default)]
+pub struct InstanceBuffer(pub VecDeque<(MapId, oneshot::Sender<AdminReply>)>);
+
+/// Maps to generate and add to the game, with their names
+#[derive(Resource, Default)]
+pub struct GenerateMapBuffer(
+    pub VecDeque<(String, MapGeneratorSettings, oneshot::Sender<AdminReply>)>,
+);
+
+/// Admins waiting for the data files to be reloaded
+#[derive(Resource, Default)]
+pub struct ReloadDataBuffer(pub VecDeque<oneshot::Sender<AdminReply>>);
+
+/// Notices the data files being saved, when `watch_data` is on
 #[derive(Resource)]
-pub struct DatabaseSender(pub UnboundedSender<(UserId, DatabaseRequest)>);
+pub struct DataWatch {
+    pub last_modified: Option<SystemTime>,
+    /// Time since the files were last looked at
+    pub stopwatch: Stopwatch,
+}
+
+impl DataWatch {
+    pub fn new(last_modified: Option<SystemTime>) -> Self {
+        Self {
+            last_modified,
+            stopwatch: Stopwatch::new(),
+        }
+    }
+}
+
+/// Passes requests to the database, they count as pending until the database is done
+#[derive(Resource)]
+pub struct DatabaseSender {
+    sender: UnboundedSender<(UserId, DatabaseRequest)>,
+    metrics: Arc<SharedMetrics>,
+}
+
+impl DatabaseSender {
+    pub fn new(
+        sender: UnboundedSender<(UserId, DatabaseRequest)>,
+        metrics: Arc<SharedMetrics>,
+    ) -> Self {
+        Self { sender, metrics }
+    }
+
+    pub fn send(&self, user_id: UserId, request: DatabaseRequest) {
+        // Counted first so the database can never finish a request before it is counted
+        let pending = &self.metrics.pending_database_requests;
+        pending.fetch_add(1, Ordering::Relaxed);
+        if self.sender.send((user_id, request)).is_err() {
+            pending.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
 
 #[derive(Resource)]
 pub struct DatabaseReceiver(pub UnboundedReceiver<(UserId, DatabaseResponse)>);
 
+/// Systems send messages for every client here, they are stamped with the tick at the end of it
 #[derive(Resource)]
 pub struct MessageSenderAllClients(pub UnboundedSender<ServerMessageAllClients>);
 
@@ -37,7 +162,29 @@ pub struct KeypressBuffer(pub VecDeque<(UserId, BodyRelative)>);
 pub struct DisconnectBuffer(pub VecDeque<UserId>);
 
 #[derive(Resource, Default)]
-pub struct ConnectBuffer(pub VecDeque<(UserId, String, SpriteTexture)>);
+pub struct ConnectBuffer(pub VecDeque<(UserId, SpriteTexture)>);
+
+/// `Register` and `Login` requests waiting to be passed to the database
+#[derive(Resource, Default)]
+pub struct LoginBuffer(pub VecDeque<(UserId, DatabaseRequest)>);
+
+/// Users whose login is being checked by the database
+#[derive(Resource, Default)]
+pub struct PendingLogins(pub HashSet<UserId>);
+
+/// Users that have logged in, only they can join the game
+#[derive(Resource, Default)]
+pub struct AuthenticatedUsers(pub HashMap<UserId, Account>);
+
+#[derive(Resource, Default)]
+pub struct ResumeBuffer(pub VecDeque<(UserId, String)>);
+
+#[derive(Resource, Default)]
+pub struct SpectateBuffer(pub VecDeque<(UserId, MapId)>);
+
+/// Users watching a map without a player entity, they only receive map and sprite updates
+#[derive(Resource, Default)]
+pub struct Spectators(pub HashSet<UserId>);
 
 #[derive(Resource, Default)]
 pub struct MouseHoverBuffer(pub VecDeque<(UserId, Position)>);
@@ -45,8 +192,9 @@ pub struct MouseHoverBuffer(pub VecDeque<(UserId, Position)>);
 #[derive(Resource, Default)]
 pub struct MouseClickBuffer(pub VecDeque<(UserId, Position)>);
 
+/// Enemies waiting to be spawned, on the given tile or wherever the map has room
 #[derive(Resource, Default)]
-pub struct SpawnableEnemyBuffer(pub VecDeque<(UserId, SpawnableEnemy)>);
+pub struct SpawnableEnemyBuffer(pub VecDeque<(MapId, SpawnableEnemy, Option<Position>)>);
 
 #[derive(Resource)]
 pub struct DebugStopwatch(pub Stopwatch);
@@ -57,6 +205,33 @@ impl DebugStopwatch {
     }
 }
 
+/// Where the engine publishes its figures for the metrics endpoint
+#[derive(Resource)]
+pub struct Metrics(pub Arc<SharedMetrics>);
+
+/// Times every tick, the totals are published with the rest of the metrics
+#[derive(Resource)]
+pub struct TickTimer {
+    pub tick_started: Instant,
+    pub ticks: u64,
+    pub tick_seconds_total: f64,
+    /// The slowest tick since the last publish
+    pub tick_seconds_max: f64,
+    pub publish_stopwatch: Stopwatch,
+}
+
+impl Default for TickTimer {
+    fn default() -> Self {
+        Self {
+            tick_started: Instant::now(),
+            ticks: 0,
+            tick_seconds_total: 0.0,
+            tick_seconds_max: 0.0,
+            publish_stopwatch: Stopwatch::new(),
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct SpawnStopWatch(pub Stopwatch);
 
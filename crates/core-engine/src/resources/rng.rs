@@ -0,0 +1,100 @@
+use bevy::prelude::Resource;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// What a random number is for. Each has its own stream, so an extra roll in one part
+/// of the engine doesn't shift the numbers every other part gets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RngStream {
+    /// Damage rolls
+    Combat,
+    /// Which enemy appears, and when
+    Spawning,
+    /// What enemies decide to do, and where they wander to
+    Ai,
+    /// Where new players and enemies are put on a map
+    Placement,
+}
+
+const STREAM_COUNT: usize = 4;
+
+/// The only source of randomness for game logic. With the same seed and the same inputs
+/// the engine plays out the same way. Session tokens are not game logic and keep using
+/// the OS generator, they must not be predictable.
+#[derive(Resource, Debug)]
+pub struct GameRng {
+    seed: u64,
+    streams: [ChaCha8Rng; STREAM_COUNT],
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        let stream = |number: u64| {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            rng.set_stream(number);
+            rng
+        };
+
+        Self {
+            seed,
+            streams: [stream(0), stream(1), stream(2), stream(3)],
+        }
+    }
+
+    /// Uses `seed` when there is one, otherwise picks one at random
+    pub fn from_seed_or_random(seed: Option<u64>) -> Self {
+        Self::new(seed.unwrap_or_else(|| rand::thread_rng().gen()))
+    }
+
+    /// The seed to put in a bug report, or in the config to play the same game again
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn stream(&mut self, stream: RngStream) -> &mut ChaCha8Rng {
+        &mut self.streams[stream as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rolls(rng: &mut GameRng, stream: RngStream) -> Vec<u32> {
+        (0..8)
+            .map(|_| rng.stream(stream).gen_range(0..20))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_rolls() {
+        let mut first = GameRng::new(42);
+        let mut second = GameRng::new(42);
+
+        assert_eq!(
+            rolls(&mut first, RngStream::Combat),
+            rolls(&mut second, RngStream::Combat)
+        );
+        assert_ne!(
+            rolls(&mut GameRng::new(42), RngStream::Combat),
+            rolls(&mut GameRng::new(43), RngStream::Combat)
+        );
+    }
+
+    #[test]
+    fn streams_do_not_affect_each_other() {
+        let mut busy = GameRng::new(7);
+        let mut quiet = GameRng::new(7);
+
+        rolls(&mut busy, RngStream::Combat);
+
+        assert_eq!(
+            rolls(&mut busy, RngStream::Spawning),
+            rolls(&mut quiet, RngStream::Spawning)
+        );
+        assert_ne!(
+            rolls(&mut quiet, RngStream::Ai),
+            rolls(&mut quiet, RngStream::Placement)
+        );
+    }
+}
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use bevy::prelude::*;
 
-use crate::data::map_data::{str_map_dimensions, EXAMPLE_MAP_1, EXAMPLE_MAP_2};
+use crate::data::map_layout::MapLayout;
 
 use super::map::GameMap;
 
@@ -14,23 +14,33 @@ type GameMaps = HashMap<MapId, GameMap>;
 #[derive(Resource)]
 pub struct GameWorld {
     pub game_maps: GameMaps,
+    next_map_id: i32,
 }
 
-impl Default for GameWorld {
-    fn default() -> Self {
-        let dimensions = str_map_dimensions(EXAMPLE_MAP_1);
-        
-        let default_map = GameMap::new(dimensions);
-
-        let mut game_maps: GameMaps = HashMap::new();
-
-        game_maps.insert(default_map.id(), default_map);
-
-        let dimensions = str_map_dimensions(EXAMPLE_MAP_2);
-        let second_map = GameMap::new(dimensions);
-
-        game_maps.insert(second_map.id(), second_map);
+impl GameWorld {
+    /// An empty game map for each loaded map, `build_maps_system` fills them in
+    pub fn new(maps: &[MapLayout]) -> Self {
+        let game_maps = maps
+            .iter()
+            .map(|map| {
+                (
+                    MapId(map.id),
+                    GameMap::new(MapId(map.id), map.dimensions.clone()),
+                )
+            })
+            .collect();
+        let next_map_id = maps.iter().map(|map| map.id + 1).max().unwrap_or(1);
+
+        Self {
+            game_maps,
+            next_map_id,
+        }
+    }
 
-        Self { game_maps }
+    /// An id no map has had yet, for maps made while the game is running
+    pub fn allocate_map_id(&mut self) -> MapId {
+        let map_id = MapId(self.next_map_id);
+        self.next_map_id += 1;
+        map_id
     }
 }
@@ -0,0 +1,250 @@
+use bevy::prelude::*;
+use core_api::{
+    AdminCommand, AdminResponse, ConnectedUser, EntityIndex, LogMessage, ServerMessageAllClients,
+    ServerMessageSingleClient,
+};
+
+use crate::{
+    components::{eyes::Eyes, BlocksMovement, MapPosition, User},
+    events::{ShouldSendFullMapUpdateToClient, ShouldUpdateMap},
+    resources::{
+        world::{GameWorld, MapId},
+        AdminReceiver, CurrentUserMaps, GenerateMapBuffer, InstanceBuffer, KickBuffer,
+        MessageSenderAllClients, MessageSenderSingleClient, ReloadDataBuffer, SpawnableEnemyBuffer,
+        Spectators, TeleportBuffer,
+    },
+};
+
+/// A player an admin can move, with everything a teleport changes
+type TeleportedPlayer<'a> = (
+    Entity,
+    &'a User,
+    &'a mut MapPosition,
+    Option<&'a mut Eyes>,
+    Option<&'a BlocksMovement>,
+);
+
+/// Tells every user on a map that an entity's sprite is gone
+fn remove_sprite_from_map(
+    sender_single_client: &MessageSenderSingleClient,
+    current_user_maps: &CurrentUserMaps,
+    entity: Entity,
+    map_id: MapId,
+) {
+    current_user_maps
+        .0
+        .iter()
+        .for_each(|(user_id, user_map_pos)| {
+            if user_map_pos.map_id == map_id {
+                sender_single_client
+                    .0
+                    .send((
+                        *user_id,
+                        ServerMessageSingleClient::RemoveSprite(EntityIndex {
+                            idx: entity.index(),
+                        }),
+                    ))
+                    .ok();
+            }
+        });
+}
+
+/// Handles commands from the admin API, answering the simple ones straight away and
+/// placing the rest into buffers for the systems that apply them
+// Every admin command is handed to the buffer of the system that carries it out
+#[allow(clippy::too_many_arguments)]
+pub fn admin_system(
+    mut admin_receiver: ResMut<AdminReceiver>,
+    mut kick_buffer: ResMut<KickBuffer>,
+    mut teleport_buffer: ResMut<TeleportBuffer>,
+    mut spawnable_enemy_buffer: ResMut<SpawnableEnemyBuffer>,
+    mut generate_map_buffer: ResMut<GenerateMapBuffer>,
+    mut instance_buffer: ResMut<InstanceBuffer>,
+    mut reload_data_buffer: ResMut<ReloadDataBuffer>,
+    sender_all_clients: Res<MessageSenderAllClients>,
+    game_world: Res<GameWorld>,
+    current_user_maps: Res<CurrentUserMaps>,
+    spectators: Res<Spectators>,
+    query: Query<(&User, &Name)>,
+) {
+    while let Ok((command, reply)) = admin_receiver.0.try_recv() {
+        info!("Admin command: {:?}", command);
+
+        match command {
+            AdminCommand::ListUsers => {
+                let users = current_user_maps
+                    .0
+                    .iter()
+                    .map(|(user_id, user_map_pos)| ConnectedUser {
+                        user_id: user_id.0,
+                        name: query
+                            .iter()
+                            .find(|(user, _)| user.0 == *user_id)
+                            .map(|(_, name)| name.to_string()),
+                        map_id: user_map_pos.map_id.0,
+                        pos: user_map_pos.pos.clone(),
+                        spectator: spectators.0.contains(user_id),
+                    })
+                    .collect();
+
+                reply.send(Ok(AdminResponse::Users(users))).ok();
+            }
+            AdminCommand::Kick(user_id) => {
+                kick_buffer.0.push_back((user_id, reply));
+            }
+            AdminCommand::Broadcast(message) => {
+                sender_all_clients
+                    .0
+                    .send(ServerMessageAllClients::Log(LogMessage(message)))
+                    .ok();
+
+                reply.send(Ok(AdminResponse::Done)).ok();
+            }
+            AdminCommand::Spawn { map_id, enemy } => {
+                let map_id = MapId(map_id);
+                match game_world.game_maps.get(&map_id) {
+                    Some(map) if map.has_movement_unblocked_tile() => {
+                        spawnable_enemy_buffer.0.push_back((map_id, enemy, None));
+                        reply.send(Ok(AdminResponse::Done)).ok();
+                    }
+                    Some(_) => {
+                        reply
+                            .send(Err(format!("No free tile on map {}", map_id.0)))
+                            .ok();
+                    }
+                    None => {
+                        reply.send(Err(format!("No map with id {}", map_id.0))).ok();
+                    }
+                }
+            }
+            AdminCommand::Teleport {
+                user_id,
+                map_id,
+                pos,
+            } => {
+                let destination = MapPosition {
+                    pos,
+                    map_id: MapId(map_id),
+                };
+                teleport_buffer.0.push_back((user_id, destination, reply));
+            }
+            AdminCommand::GenerateMap { name, settings } => {
+                generate_map_buffer.0.push_back((name, settings, reply));
+            }
+            AdminCommand::CreateInstance { template_map_id } => {
+                instance_buffer.0.push_back((MapId(template_map_id), reply));
+            }
+            AdminCommand::ReloadData => {
+                reload_data_buffer.0.push_back(reply);
+            }
+        }
+    }
+}
+
+/// Removes a kicked user's player from the game without waiting for their session to expire
+pub fn kick_system(
+    sender_single_client: Res<MessageSenderSingleClient>,
+    mut commands: Commands,
+    mut kick_buffer: ResMut<KickBuffer>,
+    mut current_user_maps: ResMut<CurrentUserMaps>,
+    mut spectators: ResMut<Spectators>,
+    query: Query<(Entity, &User, &MapPosition, &Name)>,
+) {
+    while let Some((kicked_user_id, reply)) = kick_buffer.0.pop_front() {
+        let was_spectating = spectators.0.remove(&kicked_user_id);
+        current_user_maps.0.remove(&kicked_user_id);
+
+        let player = query
+            .iter()
+            .find(|(_, user, _, _)| user.0 == kicked_user_id);
+
+        if let Some((entity, _, map_pos, name)) = player {
+            info!("Kicking {}", name);
+            remove_sprite_from_map(
+                &sender_single_client,
+                &current_user_maps,
+                entity,
+                map_pos.map_id,
+            );
+            commands.entity(entity).despawn();
+        }
+
+        if player.is_some() || was_spectating {
+            reply.send(Ok(AdminResponse::Done)).ok();
+        } else {
+            reply
+                .send(Err(format!("No user with id {}", kicked_user_id.0)))
+                .ok();
+        }
+    }
+}
+
+/// Moves a player to any open tile, on their current map or another one
+pub fn teleport_system(
+    sender_single_client: Res<MessageSenderSingleClient>,
+    mut game_world: ResMut<GameWorld>,
+    mut teleport_buffer: ResMut<TeleportBuffer>,
+    mut current_user_maps: ResMut<CurrentUserMaps>,
+    mut ev_update_map: EventWriter<ShouldUpdateMap>,
+    mut ev_update_client: EventWriter<ShouldSendFullMapUpdateToClient>,
+    mut query: Query<TeleportedPlayer>,
+) {
+    while let Some((user_id, destination, reply)) = teleport_buffer.0.pop_front() {
+        let Some(map) = game_world.game_maps.get_mut(&destination.map_id) else {
+            reply
+                .send(Err(format!("No map with id {}", destination.map_id.0)))
+                .ok();
+            continue;
+        };
+
+        if !map.inside_map_bounds(&destination.pos) || map.movement_blocked(&destination.pos) {
+            reply
+                .send(Err(format!(
+                    "{:?} is not an open tile on map {}",
+                    destination.pos, destination.map_id.0
+                )))
+                .ok();
+            continue;
+        }
+
+        let Some((entity, _, mut map_pos, eyes, blocks_movement)) = query
+            .iter_mut()
+            .find(|(_, user, _, _, _)| user.0 == user_id)
+        else {
+            reply
+                .send(Err(format!("No player for user {}", user_id.0)))
+                .ok();
+            continue;
+        };
+
+        // Like a warp, the tile stays taken for anyone else arriving there this tick
+        if blocks_movement.is_some() {
+            map.set_blocks_movement(&destination.pos);
+        }
+
+        let previous_map_id = map_pos.map_id;
+        if previous_map_id != destination.map_id {
+            remove_sprite_from_map(
+                &sender_single_client,
+                &current_user_maps,
+                entity,
+                previous_map_id,
+            );
+        }
+
+        *map_pos = destination.clone();
+        if let Some(mut eyes) = eyes {
+            // The old grid is sized for the map the player came from
+            *eyes = Eyes::new(map, eyes.visible_distance);
+            eyes.set_visibility(&destination.pos, map);
+        }
+
+        current_user_maps.0.insert(user_id, destination.clone());
+
+        ev_update_map.send(ShouldUpdateMap(previous_map_id));
+        ev_update_map.send(ShouldUpdateMap(destination.map_id));
+        ev_update_client.send(ShouldSendFullMapUpdateToClient(destination.map_id));
+
+        reply.send(Ok(AdminResponse::Done)).ok();
+    }
+}
@@ -8,12 +8,22 @@ use crate::{
         intend_move::IntendMove,
         MapPosition, User,
     },
-    resources::world::GameWorld,
+    resources::{
+        rng::{GameRng, RngStream},
+        world::GameWorld,
+    },
 };
 use ae_position::Position;
 use bevy::prelude::*;
-use rand::seq::SliceRandom;
-use tv_utility_ai::{choose::choose_action_fuzzy, curve, WeightedAction};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    seq::SliceRandom,
+    Rng,
+};
+use tv_utility_ai::{curve, WeightedAction};
+
+/// Actions scoring under this share of the best one are never picked
+const MIN_SHARE_OF_BEST: f32 = 0.6;
 
 fn distance_between_positions(pos: &Position, other: &Position) -> u32 {
     ((pos.x.max(other.x) - pos.x.min(other.x)) + (pos.y.max(other.y) - pos.y.min(other.y))) as u32
@@ -36,6 +46,27 @@ fn get_visible_floor_positions(
     })
 }
 
+/// Picks one of the actions that score close to the best, at random in proportion to their
+/// weights, so enemies facing a close call don't all make the same choice
+fn choose_action_fuzzy<A>(actions: Vec<WeightedAction<A>>, rng: &mut impl Rng) -> Option<A> {
+    let best = actions
+        .iter()
+        .map(|action| action.weight)
+        .fold(0.0, f32::max);
+    let mut candidates: Vec<WeightedAction<A>> = actions
+        .into_iter()
+        .filter(|action| action.weight >= best * MIN_SHARE_OF_BEST)
+        .collect();
+
+    let index = match WeightedIndex::new(candidates.iter().map(|action| action.weight)) {
+        Ok(weights) => weights.sample(rng),
+        // Every weight is 0, so there is nothing to tell them apart by
+        Err(_) if !candidates.is_empty() => rng.gen_range(0..candidates.len()),
+        Err(_) => return None,
+    };
+    Some(candidates.swap_remove(index).action)
+}
+
 fn make_attack_action(entity: Entity, enemy_hp: &Hp, offset: f32) -> WeightedAction<AiAction> {
     WeightedAction {
         action: AiAction::Attack(entity),
@@ -74,6 +105,7 @@ pub fn ai_system(
     chase_target_query: Query<(&MapPosition)>,
     mut commands: Commands,
     game_world: Res<GameWorld>,
+    mut rng: ResMut<GameRng>,
 ) {
     for (ent, mut ai, mut cooldown, map_pos, eyes) in query.iter_mut() {
         if cooldown.time_remaining > 0.0 {
@@ -85,7 +117,6 @@ pub fn ai_system(
                 map.visibility_grid_from_position(&map_pos.pos, eyes.visible_distance);
             for (user_ent, _user, user_hp, user_pos) in visible_user_query.iter() {
                 if user_pos.map_id == map_pos.map_id
-                // [TODO] possible panic on `position_visible`
                     && visibility_grid.position_visible(&user_pos.pos)
                 {
                     if is_adjacent(&user_pos.pos, &map_pos.pos) {
@@ -121,15 +152,14 @@ pub fn ai_system(
                     &map.get_unblocked_idxs(),
                     visibility_grid.width,
                 )
-                .choose(&mut rand::thread_rng())
+                .choose(rng.stream(RngStream::Ai))
                 .cloned(),
             };
             if let Some(position) = position {
                 weighted_actions.push(make_wander_action(&position));
             }
             // we hold the ai action to give the next chosen action some additional weight
-            // choice offset should use random number here
-            ai.action = choose_action_fuzzy(weighted_actions, 0.1, 0.6);
+            ai.action = choose_action_fuzzy(weighted_actions, rng.stream(RngStream::Ai));
             match &ai.action {
                 Some(AiAction::Attack(target_ent)) => {
                     commands.entity(ent).insert(IntendMeleeAttack {
@@ -1,215 +1,227 @@
-use ae_position::Position;
 use bevy::prelude::*;
-use core_api::{DialogueMap, SpriteTexture};
+use core_api::{AdminResponse, SpriteTexture};
+use rand::Rng;
 
 use crate::{
-    components::{speaks::Speaks, BlocksLight, BlocksMovement, MapPosition, Renderable},
-    data::map_data::{
-        example_map_1_legend, example_map_2_legend, DEFAULT_FLOOR_MAP_1, DEFAULT_FLOOR_MAP_2,
-        EXAMPLE_MAP_1, EXAMPLE_MAP_2,
+    components::{
+        speaks::Speaks, BlocksLight, BlocksMovement, Description, Floor, MapPosition, Portal,
+        Renderable, SpawnPoint,
+    },
+    data::{
+        dialogue_contents::DialogueContents,
+        map_layout::{MapLayout, MapObject},
+        tile_properties::{Interaction, TileProperties},
     },
     events::ShouldUpdateMap,
+    generation,
     resources::{
+        config::EngineConfig,
         map::GameMap,
+        rng::{GameRng, RngStream},
         world::{GameWorld, MapId},
+        GenerateMapBuffer, MapLayouts, SpawnableEnemyBuffer,
     },
 };
 
-fn str_map_to_game_map(
-    string_map: &str,
-    legend: &dyn Fn(char) -> (SpriteTexture, Option<DialogueMap>),
+fn spawn_map_tiles(
+    map_layout: &MapLayout,
+    tile_properties: &TileProperties,
     commands: &mut Commands,
+    spawnable_enemy_buffer: &mut SpawnableEnemyBuffer,
     map: &GameMap,
-    floor: SpriteTexture,
 ) {
-    let lines = string_map.lines().filter_map(|line| {
-        let trimmed_line = line.trim();
-
-        (!trimmed_line.is_empty()).then_some(trimmed_line)
-    });
-
-    for (y, line) in lines.enumerate() {
-        let trimmed_line = line.trim();
-
-        for (x, character) in trimmed_line.chars().enumerate() {
-            let pos = Position {
-                x: x as i32,
-                y: y as i32,
-            };
-
-            // Every tile needs a floor at minimum
-            commands
-                .spawn(Name::new("Floor"))
-                .insert(MapPosition {
-                    pos: pos.clone(),
-                    map_id: map.id(),
-                })
-                .insert(Renderable { texture: floor });
-            // .id()
-            // .index();
-
-            // Check if there is stuff on top of the floor
-            let (sprite, maybe_dialogue_map) = legend(character);
-
-            if sprite != SpriteTexture::Empty {
-                let mut sprite_command = commands.spawn(Renderable { texture: sprite });
-
-                if let Some(maybe_dialogue_map) = maybe_dialogue_map {
-                    sprite_command.insert(Speaks(maybe_dialogue_map));
-                }
-
-                let blocks_movement_and_light = match sprite {
-                    SpriteTexture::WallBrick => true,
-                    SpriteTexture::WallStone => true,
-
-                    SpriteTexture::PcBoneyBoi => true,
-                    SpriteTexture::PcKidZilla => true,
-                    SpriteTexture::ObjectRedSoda => true,
-                    SpriteTexture::ObjectSewerGrate => false,
-                    SpriteTexture::ObjectWindow => true,
-                    SpriteTexture::ObjectLadderUp => false,
-                    SpriteTexture::ObjectLadderDown => false,
-                    SpriteTexture::ObjectWater => true,
-                    SpriteTexture::FloorGrass => false,
-                    SpriteTexture::FloorConcrete => false,
-                    SpriteTexture::FloorSlime => false,
-                    SpriteTexture::NpcFatherNeilFrames6 => true,
-
-                    SpriteTexture::NpcKingRatFrames4 => true,
-                    SpriteTexture::PcSewerKidFrames6 => true,
-                    SpriteTexture::NpcSlime => true,
-                    SpriteTexture::Empty => false,
-
-                    SpriteTexture::NpcFootballFrames4 => true,
-                    SpriteTexture::NpcGoon1Frames4 => true,
-                    SpriteTexture::NpcGoon2Frames4 => true,
-                    SpriteTexture::NpcGoon3Frames4 => true,
-                    SpriteTexture::NpcGoon4Frames4 => true,
-                    SpriteTexture::NpcGraceJonesFrames6 => true,
-                    SpriteTexture::NpcMallChick1Frames6 => true,
-                    SpriteTexture::NpcMallChick2Frames6 => true,
-                    SpriteTexture::NpcPersonFrames2 => true,
-                    SpriteTexture::NpcRatFrames4 => true,
-                    SpriteTexture::NpcSmallRatFrames6 => true,
-
-                    SpriteTexture::ObjectWarpTeeveeFrames3 => false,
-
-                    SpriteTexture::PcAntBoi => true,
-                    SpriteTexture::PcAntBoiFrames4 => true,
-                    SpriteTexture::PcBoneyBoiFrames4 => true,
-                    SpriteTexture::PcGhostBoyFrames8 => true,
-                    SpriteTexture::ObjectNewspaper => true,
-                    SpriteTexture::NpcRealEstateDickFrames21 => true,
-                    SpriteTexture::ObjectShoreFrames4 => false,
-                    SpriteTexture::ObjectWaterFrames4 => true,
-                    SpriteTexture::ObjectSand => false,
-                    SpriteTexture::ObjectBone => false,
-
-                    SpriteTexture::WallFenceCornerIn => true,
-                    SpriteTexture::WallFenceCornerOut => true,
-                    SpriteTexture::WallFenceHorizontal => true,
-                    SpriteTexture::WallFenceVertical => true,
-
-                    SpriteTexture::ObjectWood => false,
-                };
-
-                let name = match sprite {
-                    SpriteTexture::WallBrick => "Brick Wall".to_string(),
-                    SpriteTexture::WallStone => "Stone Wall".to_string(),
-
-                    SpriteTexture::PcBoneyBoi => "Boney Boi".to_string(),
-                    SpriteTexture::PcKidZilla => "Kidzilla".to_string(),
-                    SpriteTexture::ObjectRedSoda => "Soda".to_string(),
-                    SpriteTexture::ObjectSewerGrate => "Sewer Grate".to_string(),
-                    SpriteTexture::ObjectWindow => "Window".to_string(),
-                    SpriteTexture::ObjectLadderUp => "Ladder (Up)".to_string(),
-                    SpriteTexture::ObjectLadderDown => "Ladder (Down)".to_string(),
-                    SpriteTexture::ObjectWater => "Water".to_string(),
-                    SpriteTexture::FloorGrass => "Grass".to_string(),
-                    SpriteTexture::FloorConcrete => "Concrete".to_string(),
-                    SpriteTexture::FloorSlime => "Slime Floor".to_string(),
-                    SpriteTexture::NpcFatherNeilFrames6 => "Father Neil".to_string(),
-                    SpriteTexture::NpcKingRatFrames4 => "King Rat".to_string(),
-                    SpriteTexture::PcSewerKidFrames6 => "Sewer Kid".to_string(),
-                    SpriteTexture::NpcSlime => "Slime".to_string(),
-                    SpriteTexture::Empty => "XXX EMPTY XXX".to_string(),
-
-                    SpriteTexture::NpcFootballFrames4 => "Football".to_string(),
-                    SpriteTexture::NpcGoon1Frames4 => "Goon".to_string(),
-                    SpriteTexture::NpcGoon2Frames4 => "Goon".to_string(),
-                    SpriteTexture::NpcGoon3Frames4 => "Goon".to_string(),
-                    SpriteTexture::NpcGoon4Frames4 => "Goon".to_string(),
-                    SpriteTexture::NpcGraceJonesFrames6 => "Grace Jones".to_string(),
-                    SpriteTexture::NpcMallChick1Frames6 => "Mall Chick".to_string(),
-                    SpriteTexture::NpcMallChick2Frames6 => "Mall Chick".to_string(),
-                    SpriteTexture::NpcPersonFrames2 => "Person".to_string(),
-                    SpriteTexture::NpcRatFrames4 => "Rat".to_string(),
-                    SpriteTexture::NpcSmallRatFrames6 => "Small Rat".to_string(),
-
-                    SpriteTexture::ObjectWarpTeeveeFrames3 => "Warp Teevee".to_string(),
-                    SpriteTexture::PcAntBoi => "Ant Boi".to_string(),
-                    SpriteTexture::PcAntBoiFrames4 => "Ant Boi".to_string(),
-                    SpriteTexture::PcBoneyBoiFrames4 => "Boney Boi".to_string(),
-                    SpriteTexture::PcGhostBoyFrames8 => "Ghost Boy".to_string(),
-                    SpriteTexture::ObjectNewspaper => "Newspaper".to_string(),
-                    SpriteTexture::NpcRealEstateDickFrames21 => "Real Estate Dick".to_string(),
-                    SpriteTexture::ObjectShoreFrames4 => "Shore".to_string(),
-                    SpriteTexture::ObjectWaterFrames4 => "Moving Water".to_string(),
-                    SpriteTexture::ObjectSand => "Sand".to_string(),
-
-                    SpriteTexture::ObjectBone => "Bones".to_string(),
-
-                    SpriteTexture::WallFenceCornerIn => "Fence".to_string(),
-                    SpriteTexture::WallFenceCornerOut => "Fence".to_string(),
-                    SpriteTexture::WallFenceHorizontal => "Fence".to_string(),
-                    SpriteTexture::WallFenceVertical => "Fence".to_string(),
-
-                    SpriteTexture::ObjectWood => "Wood Floor".to_string(),
-                };
-
-                sprite_command.insert(Name::new(name));
-
-                if blocks_movement_and_light {
-                    sprite_command.insert(BlocksMovement);
-                    sprite_command.insert(BlocksLight);
-                };
-
-                sprite_command
-                    .insert(MapPosition {
-                        pos: pos.clone(),
-                        map_id: map.id(),
-                    })
-                    .id()
-                    .index();
+    for (pos, object) in map_layout.objects.iter() {
+        let map_pos = MapPosition {
+            pos: pos.clone(),
+            map_id: map.id(),
+        };
+
+        match object {
+            MapObject::Floor(texture) => {
+                // Floors were checked against the tile properties too, but are never in the way
+                let property = &tile_properties.0[texture];
+                commands
+                    .spawn(Name::new(property.name.clone()))
+                    .insert(Description(property.description.clone()))
+                    .insert(Floor)
+                    .insert(map_pos)
+                    .insert(Renderable { texture: *texture });
+            }
+            MapObject::SpawnPoint => {
+                commands.spawn(SpawnPoint).insert(map_pos);
+            }
+            MapObject::Enemy(enemy) => {
+                spawnable_enemy_buffer
+                    .0
+                    .push_back((map.id(), *enemy, Some(pos.clone())));
+            }
+            MapObject::Portal {
+                map_id,
+                pos: destination,
+            } => {
+                commands.spawn(map_pos).insert(Portal {
+                    destination: MapPosition {
+                        pos: destination.clone(),
+                        map_id: MapId(*map_id),
+                    },
+                });
             }
+            MapObject::Sprite { sprite, dialogue } => spawn_sprite(
+                *sprite,
+                dialogue.as_deref(),
+                tile_properties,
+                commands,
+                map_pos,
+            ),
         }
     }
 }
 
+fn spawn_sprite(
+    sprite: SpriteTexture,
+    dialogue: Option<&str>,
+    tile_properties: &TileProperties,
+    commands: &mut Commands,
+    map_pos: MapPosition,
+) {
+    // Sprites were checked when the map was loaded
+    let property = &tile_properties.0[&sprite];
+    let mut sprite_command = commands.spawn(Renderable { texture: sprite });
+    sprite_command
+        .insert(Name::new(property.name.clone()))
+        .insert(Description(property.description.clone()))
+        .insert(map_pos);
+
+    if property.interaction != Interaction::None {
+        sprite_command.insert(property.interaction);
+    }
+
+    if let Some(name) = dialogue {
+        sprite_command.insert(Speaks(name.to_string()));
+    }
+
+    if property.blocks_movement {
+        sprite_command.insert(BlocksMovement);
+    }
+
+    if property.blocks_light {
+        sprite_command.insert(BlocksLight);
+    }
+}
+
+/// Adds a map to the running game. Its id must not be in use, the caller sends the
+/// `ShouldUpdateMap` once the tiles are in place.
+pub(crate) fn add_map(
+    map_layout: &MapLayout,
+    game_world: &mut GameWorld,
+    tile_properties: &TileProperties,
+    commands: &mut Commands,
+    spawnable_enemy_buffer: &mut SpawnableEnemyBuffer,
+) {
+    let map_id = MapId(map_layout.id);
+    let map = GameMap::new(map_id, map_layout.dimensions.clone());
+    spawn_map_tiles(
+        map_layout,
+        tile_properties,
+        commands,
+        spawnable_enemy_buffer,
+        &map,
+    );
+    game_world.game_maps.insert(map_id, map);
+}
+
 /// Adds the all tiles to the maps on initial load
 pub fn build_maps_system(
     game_world: Res<GameWorld>,
+    config: Res<EngineConfig>,
+    map_layouts: Res<MapLayouts>,
+    tile_properties: Res<TileProperties>,
     mut commands: Commands,
+    mut spawnable_enemy_buffer: ResMut<SpawnableEnemyBuffer>,
     mut ev_update_map: EventWriter<ShouldUpdateMap>,
 ) {
-    for map in game_world.game_maps.values() {
-        if map.id() == (MapId(1)) {
-            str_map_to_game_map(
-                EXAMPLE_MAP_1,
-                &example_map_1_legend,
-                &mut commands,
-                map,
-                DEFAULT_FLOOR_MAP_1,
-            );
-        } else {
-            str_map_to_game_map(
-                EXAMPLE_MAP_2,
-                &example_map_2_legend,
-                &mut commands,
-                map,
-                DEFAULT_FLOOR_MAP_2,
-            );
-        }
+    // Catch a bad config at startup rather than when the first player joins
+    for map_id in [config.start_map_id, config.enemy_map_id] {
+        assert!(
+            game_world.game_maps.contains_key(&MapId(map_id)),
+            "The engine config refers to map {} which does not exist",
+            map_id
+        );
+    }
+
+    for map_layout in map_layouts.0.iter() {
+        let map = game_world
+            .game_maps
+            .get(&MapId(map_layout.id))
+            .expect("Every loaded map has a game map");
+
+        spawn_map_tiles(
+            map_layout,
+            &tile_properties,
+            &mut commands,
+            &mut spawnable_enemy_buffer,
+            map,
+        );
         ev_update_map.send(ShouldUpdateMap(map.id()));
     }
 }
+
+/// Generates the maps asked for through the admin API and adds them to the running game
+// Generating a map builds it like build_maps_system and also records its layout
+#[allow(clippy::too_many_arguments)]
+pub fn generate_map_system(
+    mut generate_map_buffer: ResMut<GenerateMapBuffer>,
+    mut game_world: ResMut<GameWorld>,
+    mut map_layouts: ResMut<MapLayouts>,
+    dialogue_contents: Res<DialogueContents>,
+    tile_properties: Res<TileProperties>,
+    mut rng: ResMut<GameRng>,
+    mut commands: Commands,
+    mut spawnable_enemy_buffer: ResMut<SpawnableEnemyBuffer>,
+    mut ev_update_map: EventWriter<ShouldUpdateMap>,
+) {
+    while let Some((name, settings, reply)) = generate_map_buffer.0.pop_front() {
+        if let Err(error) = generation::check_settings(&settings) {
+            reply.send(Err(error)).ok();
+            continue;
+        }
+
+        // Drawn from the game's generator so a replay with the same seed builds the same map
+        let seed = settings
+            .seed
+            .unwrap_or_else(|| rng.stream(RngStream::Placement).gen());
+        let map_id = game_world.allocate_map_id();
+
+        let map_layout =
+            generation::generate(map_id.0, &name, &settings, seed).and_then(|map_layout| {
+                map_layout
+                    .validate(&dialogue_contents, &tile_properties)
+                    .map(|_| map_layout)
+            });
+        let map_layout = match map_layout {
+            Ok(map_layout) => map_layout,
+            Err(error) => {
+                reply.send(Err(error)).ok();
+                continue;
+            }
+        };
+
+        info!(
+            "Generated map {} \"{}\" with {:?} from seed {}",
+            map_id.0, name, settings.algorithm, seed
+        );
+
+        add_map(
+            &map_layout,
+            &mut game_world,
+            &tile_properties,
+            &mut commands,
+            &mut spawnable_enemy_buffer,
+        );
+        ev_update_map.send(ShouldUpdateMap(map_id));
+        // Kept so it can be copied for instances
+        map_layouts.0.push(map_layout);
+
+        reply.send(Ok(AdminResponse::MapCreated(map_id.0))).ok();
+    }
+}
@@ -1,35 +1,71 @@
-use ae_position::Position;
 use bevy::prelude::*;
 use core_api::{EntityIndex, ServerMessageSingleClient};
 
 use crate::{
-    components::{MapPosition, User},
-    events::ShouldSendFullMapUpdateToClient,
-    resources::{world::MapId, CurrentUserMaps, MessageSenderSingleClient, map::{BAD_GUY_MAP_ID, PEACEFUL_MAP_ID}},
+    components::{eyes::Eyes, BlocksMovement, MapPosition, Portal, User},
+    data::tile_properties::Interaction,
+    events::{ShouldSendFullMapUpdateToClient, ShouldUpdateMap},
+    resources::{world::GameWorld, CurrentUserMaps, MessageSenderSingleClient},
 };
 
-/// [TODO] Turn this prototype into something more permanent
-/// Prototype system that creates a magic warp point to transition between maps
-/// Used during development of adding support for more than one game map
+/// A player that may have stepped onto a portal, with everything a warp changes
+type MovedPlayer<'a> = (
+    Entity,
+    &'a mut MapPosition,
+    &'a User,
+    Option<&'a mut Eyes>,
+    Option<&'a BlocksMovement>,
+);
+
+/// Sends players that step onto a tile with the `Warp` interaction to wherever the portal
+/// on it leads. Arriving on a portal doesn't send them on again, the system never sees its
+/// own changes.
+// A warp reads the tile being stepped on and updates the map, the player and their client
+#[allow(clippy::too_many_arguments)]
 pub fn change_map_system(
     sender_single_client: Res<MessageSenderSingleClient>,
+    mut game_world: ResMut<GameWorld>,
+    portals: Query<(&MapPosition, &Portal), Without<User>>,
+    interactions: Query<(&MapPosition, &Interaction), Without<User>>,
     // Change detection
     // https://bevy-cheatbook.github.io/programming/change-detection.html
-    mut query: Query<(Entity, &mut MapPosition, &User), Changed<MapPosition>>,
+    mut query: Query<MovedPlayer, Changed<MapPosition>>,
+    mut ev_update_map: EventWriter<ShouldUpdateMap>,
     mut ev_update_client: EventWriter<ShouldSendFullMapUpdateToClient>,
     mut current_user_maps: ResMut<CurrentUserMaps>,
 ) {
-    for (entity, mut map_pos, user) in query.iter_mut() {
-        // [TODO]
-        let arbitrary_position = Position { x: 1, y: 1 };
-        if map_pos.pos == arbitrary_position {
-            let previous_map_id = map_pos.map_id;
-            let new_map_id = if map_pos.map_id == (MapId(1)) {
-                MapId(BAD_GUY_MAP_ID)
-            } else {
-                MapId(PEACEFUL_MAP_ID)
-            };
+    for (entity, mut map_pos, user, eyes, blocks_movement) in query.iter_mut() {
+        let warps = interactions
+            .iter()
+            .any(|(pos, interaction)| *pos == *map_pos && *interaction == Interaction::Warp);
+        if !warps {
+            continue;
+        }
+        let Some((_, portal)) = portals
+            .iter()
+            .find(|(portal_pos, _)| **portal_pos == *map_pos)
+        else {
+            continue;
+        };
+
+        let destination = portal.destination.clone();
+        let Some(map) = game_world.game_maps.get_mut(&destination.map_id) else {
+            warn!("A portal leads to missing map {}", destination.map_id.0);
+            continue;
+        };
 
+        // Someone is standing on the other side, the player stays put until it's clear
+        if map.movement_blocked(&destination.pos) {
+            continue;
+        }
+        // Blocked tiles are only worked out again once the map update is handled, until then
+        // anyone else warping there this tick has to see the tile as taken
+        if blocks_movement.is_some() {
+            map.set_blocks_movement(&destination.pos);
+        }
+
+        let previous_map_id = map_pos.map_id;
+        if previous_map_id != destination.map_id {
             current_user_maps
                 .0
                 .iter()
@@ -47,19 +83,19 @@ pub fn change_map_system(
                             .ok();
                     }
                 });
+        }
 
-            map_pos.map_id = new_map_id;
+        *map_pos = destination.clone();
+        if let Some(mut eyes) = eyes {
+            // The old grid is sized for the map the player came from
+            *eyes = Eyes::new(map, eyes.visible_distance);
+            eyes.set_visibility(&destination.pos, map);
+        }
 
-            // Track that this user is not viewing a new map
-            current_user_maps.0.insert(
-                user.0,
-                MapPosition {
-                    pos: arbitrary_position,
-                    map_id: new_map_id,
-                },
-            );
+        current_user_maps.0.insert(user.0, destination.clone());
 
-            ev_update_client.send(ShouldSendFullMapUpdateToClient(new_map_id));
-        }
+        ev_update_map.send(ShouldUpdateMap(previous_map_id));
+        ev_update_map.send(ShouldUpdateMap(destination.map_id));
+        ev_update_client.send(ShouldSendFullMapUpdateToClient(destination.map_id));
     }
 }
@@ -0,0 +1,11 @@
+use bevy::prelude::*;
+
+use crate::resources::GameClock;
+
+/// Runs first on every tick and moves the game clock on by one tick, `Time` is only ever
+/// updated here
+pub fn clock_system(mut clock: ResMut<GameClock>, mut time: ResMut<Time>) {
+    let tick_duration = clock.tick_duration;
+    clock.now += tick_duration;
+    time.update_with_instant(clock.now);
+}
@@ -6,16 +6,17 @@ use core_api::{DebugData, ServerMessageAllClients};
 
 use crate::{
     components::{combat_stats::CombatStats, hp::Hp, MapPosition, User},
-    resources::{DebugStopwatch, MessageSenderAllClients},
+    resources::{config::EngineConfig, DebugStopwatch, MessageSenderAllClients},
 };
 
 pub fn debug_system(
     time: Res<Time>,
+    config: Res<EngineConfig>,
     mut debug_stopwatch: ResMut<DebugStopwatch>,
     sender_all_clients: Res<MessageSenderAllClients>,
     target_query: Query<(&CombatStats, &mut Hp, &Name, &MapPosition), Without<User>>,
 ) {
-    if debug_stopwatch.0.elapsed_secs() < 0.5 {
+    if debug_stopwatch.0.elapsed_secs() < config.debug_interval_secs {
         debug_stopwatch.0.tick(time.delta());
     } else {
         debug_stopwatch.0.reset();
@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+use core_api::AdminResponse;
+
+use crate::{
+    components::{MapPosition, User},
+    data::tile_properties::TileProperties,
+    events::{ShouldSendFullMapUpdateToUser, ShouldUpdateMap},
+    resources::{
+        config::EngineConfig,
+        world::{GameWorld, MapId},
+        CurrentUserMaps, InstanceBuffer, MapInstance, MapInstances, MapLayouts,
+        SpawnableEnemyBuffer, Spectators,
+    },
+    systems::{build_maps::add_map, spectate::spectator_position},
+};
+
+/// Builds the private copies of maps asked for through the admin API
+// An instance is built like any other map, so this needs what build_maps_system needs
+#[allow(clippy::too_many_arguments)]
+pub fn create_instance_system(
+    mut instance_buffer: ResMut<InstanceBuffer>,
+    mut game_world: ResMut<GameWorld>,
+    mut map_instances: ResMut<MapInstances>,
+    map_layouts: Res<MapLayouts>,
+    tile_properties: Res<TileProperties>,
+    mut commands: Commands,
+    mut spawnable_enemy_buffer: ResMut<SpawnableEnemyBuffer>,
+    mut ev_update_map: EventWriter<ShouldUpdateMap>,
+    config: Res<EngineConfig>,
+) {
+    while let Some((template, reply)) = instance_buffer.0.pop_front() {
+        let Some(template_layout) = map_layouts.0.iter().find(|map| map.id == template.0) else {
+            reply
+                .send(Err(format!("No map with id {} to copy", template.0)))
+                .ok();
+            continue;
+        };
+
+        let map_id = game_world.allocate_map_id();
+        info!(
+            "Creating map {} as a copy of \"{}\"",
+            map_id.0, template_layout.name
+        );
+
+        add_map(
+            &template_layout.instance(map_id.0),
+            &mut game_world,
+            &tile_properties,
+            &mut commands,
+            &mut spawnable_enemy_buffer,
+        );
+        map_instances.0.insert(
+            map_id,
+            MapInstance {
+                template,
+                entered: false,
+                entry_time_remaining: config.instance_entry_timeout_secs,
+            },
+        );
+        ev_update_map.send(ShouldUpdateMap(map_id));
+
+        reply.send(Ok(AdminResponse::MapCreated(map_id.0))).ok();
+    }
+}
+
+/// Tears down instances once the last player has left, along with everything on them, or
+/// once nobody has entered them for `instance_entry_timeout_secs`. Disconnected players
+/// still count until their grace period runs out, so they can come back to where they were.
+/// Anyone spectating a closed instance is moved to the start map.
+// Closing an instance touches every resource that can refer to its map
+#[allow(clippy::too_many_arguments)]
+pub fn close_instances_system(
+    time: Res<Time>,
+    config: Res<EngineConfig>,
+    mut game_world: ResMut<GameWorld>,
+    mut map_instances: ResMut<MapInstances>,
+    mut current_user_maps: ResMut<CurrentUserMaps>,
+    spectators: Res<Spectators>,
+    mut ev_update_user: EventWriter<ShouldSendFullMapUpdateToUser>,
+    players: Query<&MapPosition, With<User>>,
+    entities: Query<(Entity, &MapPosition)>,
+    mut commands: Commands,
+) {
+    let mut closing = Vec::new();
+    for (map_id, instance) in map_instances.0.iter_mut() {
+        let occupied = players.iter().any(|map_pos| map_pos.map_id == *map_id);
+        if occupied {
+            instance.entered = true;
+        } else if instance.entered {
+            closing.push(*map_id);
+        } else {
+            instance.entry_time_remaining -= time.delta().as_secs_f32();
+            if instance.entry_time_remaining <= 0.0 {
+                closing.push(*map_id);
+            }
+        }
+    }
+
+    for map_id in closing {
+        if let Some(instance) = map_instances.0.remove(&map_id) {
+            info!(
+                "Closing map {}, a copy of map {}",
+                map_id.0, instance.template.0
+            );
+        }
+
+        for (entity, map_pos) in entities.iter() {
+            if map_pos.map_id == map_id {
+                commands.entity(entity).despawn();
+            }
+        }
+
+        game_world.game_maps.remove(&map_id);
+
+        // Only spectators can be left watching it, they go back to watching the start map
+        let start_map_id = MapId(config.start_map_id);
+        let Some(start_map) = game_world.game_maps.get(&start_map_id) else {
+            continue;
+        };
+        for (user_id, user_map_pos) in current_user_maps.0.iter_mut() {
+            if user_map_pos.map_id == map_id && spectators.0.contains(user_id) {
+                *user_map_pos = spectator_position(start_map_id, start_map);
+                ev_update_user.send(ShouldSendFullMapUpdateToUser(*user_id));
+            }
+        }
+    }
+}
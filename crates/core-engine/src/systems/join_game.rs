@@ -1,34 +1,97 @@
 use crate::{
-    components::{cooldown::Cooldown, eyes::Eyes, BlocksMovement, MapPosition, Renderable, User},
+    components::{
+        account::Account,
+        cooldown::Cooldown,
+        eyes::Eyes,
+        session::{Disconnected, Session},
+        BlocksMovement, MapPosition, Renderable, User,
+    },
     data::{player_config::PlayerConfig, player_configs::PlayerConfigs},
     events::ShouldSendFullMapUpdateToClient,
     resources::{
-        map::PEACEFUL_MAP_ID,
+        config::EngineConfig,
+        rng::{GameRng, RngStream},
         world::{GameWorld, MapId},
-        ConnectBuffer, CurrentUserMaps,
+        AuthenticatedUsers, ConnectBuffer, CurrentUserMaps, MessageSenderSingleClient,
+        ResumeBuffer,
     },
 };
 use bevy::prelude::*;
+use core_api::{ServerMessageSingleClient, UserId};
+
+fn refuse_join(sender_single_client: &MessageSenderSingleClient, user_id: UserId, reason: &str) {
+    sender_single_client
+        .0
+        .send((
+            user_id,
+            ServerMessageSingleClient::LoginFailed {
+                reason: reason.to_string(),
+            },
+        ))
+        .ok();
+}
 
-/// Adds an entity to the game when the user connects
+/// Adds an entity to the game for a logged in user. An account only ever has one player,
+/// if it is still waiting out its grace period the user takes it back instead.
+// Joining reads the player's account, session and config before placing them on a map
+#[allow(clippy::too_many_arguments)]
 pub fn join_game_system(
     game_world: Res<GameWorld>,
+    config: Res<EngineConfig>,
     mut commands: Commands,
     mut connect_buffer: ResMut<ConnectBuffer>,
     mut ev_update_client: EventWriter<ShouldSendFullMapUpdateToClient>,
     mut current_user_maps: ResMut<CurrentUserMaps>,
     player_configs: Res<PlayerConfigs>,
+    sender_single_client: Res<MessageSenderSingleClient>,
+    authenticated_users: Res<AuthenticatedUsers>,
+    mut resume_buffer: ResMut<ResumeBuffer>,
+    players: Query<(&Account, &Session, Option<&Disconnected>)>,
+    mut rng: ResMut<GameRng>,
     // enemy_configs: Res<EnemyConfigs>,
 ) {
     let map = game_world
         .game_maps
-        .get(&MapId(PEACEFUL_MAP_ID))
-        .expect("Somehow the primary map does not exist");
+        .get(&MapId(config.start_map_id))
+        .expect("Somehow the start map does not exist");
+
+    if let Some((player_user_id, player_sprite)) = connect_buffer.0.pop_front() {
+        let Some(account) = authenticated_users.0.get(&player_user_id) else {
+            refuse_join(
+                &sender_single_client,
+                player_user_id,
+                "Log in before joining the game",
+            );
+            return;
+        };
+
+        let existing_player = players
+            .iter()
+            .find(|(player_account, _, _)| player_account.id == account.id);
 
-    if let Some((player_user_id, player_name, player_sprite)) = connect_buffer.0.pop_front() {
-        let player_name = format!("{}", player_name);
+        if let Some((_, session, disconnected)) = existing_player {
+            if disconnected.is_some() {
+                resume_buffer
+                    .0
+                    .push_back((player_user_id, session.token.clone()));
+            } else {
+                refuse_join(&sender_single_client, player_user_id, "Already in the game");
+            }
+            return;
+        }
+
+        let Some(pos) = map.random_movement_unblocked_tile(rng.stream(RngStream::Placement)) else {
+            refuse_join(
+                &sender_single_client,
+                player_user_id,
+                "There is no room on the map, try again later",
+            );
+            return;
+        };
+
+        let player_name = account.name.clone();
         let player_map_position = MapPosition {
-            pos: map.random_movement_unblocked_tile(),
+            pos,
             map_id: map.id(),
         };
         let mut player_commands = commands.spawn(User(player_user_id));
@@ -42,6 +105,7 @@ pub fn join_game_system(
         }
         player_commands
             .insert(Name::new(player_name))
+            .insert(account.clone())
             .insert(player_map_position.clone())
             .insert(Renderable {
                 texture: player_sprite,
@@ -54,6 +118,17 @@ pub fn join_game_system(
                 attack_time: player_config.attack_time,
             });
 
+        // Give the client a way to reclaim this player if its socket drops
+        let session = Session::generate();
+        sender_single_client
+            .0
+            .send((
+                player_user_id,
+                ServerMessageSingleClient::SessionToken(session.token.clone()),
+            ))
+            .ok();
+        player_commands.insert(session);
+
         // Track the current map the new user is on
         current_user_maps
             .0
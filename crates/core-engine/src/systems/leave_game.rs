@@ -2,42 +2,82 @@ use bevy::prelude::*;
 use core_api::{EntityIndex, ServerMessageSingleClient};
 
 use crate::{
-    components::{MapPosition, User},
-    resources::{CurrentUserMaps, DisconnectBuffer, MessageSenderSingleClient},
+    components::{session::Disconnected, MapPosition, User},
+    resources::{
+        config::EngineConfig, AuthenticatedUsers, CurrentUserMaps, DisconnectBuffer,
+        MessageSenderSingleClient, PendingLogins, Spectators,
+    },
 };
 
-/// Removes a user's player entity from the game when the user disconnects
+/// Holds on to a user's player entity when the user disconnects so the client can resume it
+// A leaving user is removed from every resource that tracks connections
+#[allow(clippy::too_many_arguments)]
 pub fn leave_game_system(
-    sender_single_client: Res<MessageSenderSingleClient>,
-    current_user_maps: Res<CurrentUserMaps>,
+    config: Res<EngineConfig>,
+    mut current_user_maps: ResMut<CurrentUserMaps>,
+    mut spectators: ResMut<Spectators>,
+    mut authenticated_users: ResMut<AuthenticatedUsers>,
+    mut pending_logins: ResMut<PendingLogins>,
     mut commands: Commands,
     mut disconnect_buffer: ResMut<DisconnectBuffer>,
-    query: Query<(Entity, &User, &Name, &MapPosition)>,
+    query: Query<(Entity, &User, &Name)>,
 ) {
     if let Some(disconnected_user_id) = disconnect_buffer.0.pop_front() {
-        for (entity, user, name, leaving_entity_map_pos) in query.iter() {
-            if user.0 == disconnected_user_id {
-                current_user_maps
-                    .0
-                    .iter()
-                    .for_each(|(user_id, user_map_pos)| {
-                        // Communicate to any users on the old map that the sprite should be removed
-                        if user_map_pos.map_id == leaving_entity_map_pos.map_id {
-                            sender_single_client
-                                .0
-                                .send((
-                                    *user_id,
-                                    ServerMessageSingleClient::RemoveSprite(EntityIndex {
-                                        idx: entity.index(),
-                                    }),
-                                ))
-                                .ok();
-                        }
-                    });
+        // There is no longer a socket to send this user's map updates to
+        current_user_maps.0.remove(&disconnected_user_id);
+        spectators.0.remove(&disconnected_user_id);
+        // The account can log in again from a new connection and take its player back
+        authenticated_users.0.remove(&disconnected_user_id);
+        pending_logins.0.remove(&disconnected_user_id);
 
-                info!("Removing {}", name);
-                commands.entity(entity).despawn();
+        for (entity, user, name) in query.iter() {
+            if user.0 == disconnected_user_id {
+                info!(
+                    "Holding {} for {} seconds",
+                    name, config.session_grace_period_secs
+                );
+                commands.entity(entity).insert(Disconnected {
+                    time_remaining: config.session_grace_period_secs,
+                });
             }
         }
     }
 }
+
+/// Removes a disconnected player entity from the game once its grace period has run out
+pub fn expire_sessions_system(
+    time: Res<Time>,
+    sender_single_client: Res<MessageSenderSingleClient>,
+    current_user_maps: Res<CurrentUserMaps>,
+    mut commands: Commands,
+    mut query: Query<(Entity, &Name, &MapPosition, &mut Disconnected)>,
+) {
+    for (entity, name, leaving_entity_map_pos, mut disconnected) in query.iter_mut() {
+        disconnected.time_remaining -= time.delta().as_secs_f32();
+
+        if disconnected.time_remaining > 0.0 {
+            continue;
+        }
+
+        current_user_maps
+            .0
+            .iter()
+            .for_each(|(user_id, user_map_pos)| {
+                // Communicate to any users on the old map that the sprite should be removed
+                if user_map_pos.map_id == leaving_entity_map_pos.map_id {
+                    sender_single_client
+                        .0
+                        .send((
+                            *user_id,
+                            ServerMessageSingleClient::RemoveSprite(EntityIndex {
+                                idx: entity.index(),
+                            }),
+                        ))
+                        .ok();
+                }
+            });
+
+        info!("Removing {}", name);
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,42 @@
+use bevy::prelude::*;
+use core_api::ServerMessageSingleClient;
+
+use crate::resources::{
+    AuthenticatedUsers, DatabaseSender, LoginBuffer, MessageSenderSingleClient, PendingLogins,
+};
+
+/// Passes `Register` and `Login` requests to the database to be checked, the answer is
+/// handled by `database_receiver_system`
+pub fn login_system(
+    db_sender: Res<DatabaseSender>,
+    sender_single_client: Res<MessageSenderSingleClient>,
+    authenticated_users: Res<AuthenticatedUsers>,
+    mut login_buffer: ResMut<LoginBuffer>,
+    mut pending_logins: ResMut<PendingLogins>,
+) {
+    while let Some((user_id, request)) = login_buffer.0.pop_front() {
+        let refusal = if authenticated_users.0.contains_key(&user_id) {
+            Some("Already logged in")
+        } else if pending_logins.0.contains(&user_id) {
+            Some("Still checking the last login")
+        } else {
+            None
+        };
+
+        if let Some(reason) = refusal {
+            sender_single_client
+                .0
+                .send((
+                    user_id,
+                    ServerMessageSingleClient::LoginFailed {
+                        reason: reason.to_string(),
+                    },
+                ))
+                .ok();
+            continue;
+        }
+
+        pending_logins.0.insert(user_id);
+        db_sender.send(user_id, request);
+    }
+}
@@ -1,26 +1,57 @@
 use bevy::prelude::*;
-use core_api::ClientMessage;
+use core_api::{ClientMessage, DatabaseRequest};
 
 use crate::resources::{
-    ConnectBuffer, DisconnectBuffer, KeypressBuffer, MessageReceiver, MouseClickBuffer,
-    MouseHoverBuffer, SpawnableEnemyBuffer,
+    config::EngineConfig, world::MapId, ConnectBuffer, DisconnectBuffer, KeypressBuffer,
+    LoginBuffer, MessageReceiver, MouseClickBuffer, MouseHoverBuffer, ResumeBuffer,
+    SpawnableEnemyBuffer, SpectateBuffer, Spectators,
 };
 
 /// Handles all messages received from the client and places them into separate resource
 /// buffers so they can be handled by separate systems independently
+// Sorts each client message into the buffer of the system that handles it
+#[allow(clippy::too_many_arguments)]
 pub fn message_system(
     mut receiver: ResMut<MessageReceiver>,
     mut keypress_buffer: ResMut<KeypressBuffer>,
     mut disconnect_buffer: ResMut<DisconnectBuffer>,
     mut connect_buffer: ResMut<ConnectBuffer>,
+    mut login_buffer: ResMut<LoginBuffer>,
+    mut resume_buffer: ResMut<ResumeBuffer>,
     mut mouse_hover_buffer: ResMut<MouseHoverBuffer>,
     mut mouse_click_buffer: ResMut<MouseClickBuffer>,
     mut spawnable_enemy_buffer: ResMut<SpawnableEnemyBuffer>,
+    mut spectate_buffer: ResMut<SpectateBuffer>,
+    spectators: Res<Spectators>,
+    config: Res<EngineConfig>,
 ) {
     while let Ok((id, message)) = receiver.0.try_recv() {
+        // Spectators can watch but never play
+        if spectators.0.contains(&id)
+            && !matches!(
+                message,
+                ClientMessage::Disconnect | ClientMessage::KeepAlive
+            )
+        {
+            continue;
+        }
+
         match message {
-            ClientMessage::Initialize { name, sprite } => {
-                connect_buffer.0.push_back((id, name, sprite));
+            ClientMessage::Register { name, password, .. } => {
+                login_buffer
+                    .0
+                    .push_back((id, DatabaseRequest::Register { name, password }));
+            }
+            ClientMessage::Login { name, password, .. } => {
+                login_buffer
+                    .0
+                    .push_back((id, DatabaseRequest::Login { name, password }));
+            }
+            ClientMessage::Initialize { sprite, .. } => {
+                connect_buffer.0.push_back((id, sprite));
+            }
+            ClientMessage::Resume { token, .. } => {
+                resume_buffer.0.push_back((id, token));
             }
             ClientMessage::Keypress(k) => {
                 keypress_buffer.0.push_back((id, k));
@@ -35,11 +66,16 @@ pub fn message_system(
                 mouse_click_buffer.0.push_back((id, pos));
             }
             ClientMessage::Spawn(enemy) => {
-                spawnable_enemy_buffer.0.push_back((id, enemy));
+                spawnable_enemy_buffer
+                    .0
+                    .push_back((MapId(config.enemy_map_id), enemy, None));
             }
             ClientMessage::KeepAlive => {
                 // No action
             }
+            ClientMessage::Spectate { map_id } => {
+                spectate_buffer.0.push_back((id, MapId(map_id)));
+            }
         }
     }
 }
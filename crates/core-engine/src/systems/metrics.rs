@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use core_api::EngineMetrics;
+
+use crate::{
+    components::{Enemy, MapPosition},
+    resources::{Metrics, TickTimer},
+};
+
+/// How often (in seconds) the engine's figures are handed to the metrics endpoint
+const PUBLISH_INTERVAL_SECS: f32 = 1.0;
+
+/// Runs first on every tick so `metrics_system` can tell how long the tick took
+pub fn tick_start_system(mut tick_timer: ResMut<TickTimer>) {
+    tick_timer.tick_started = std::time::Instant::now();
+}
+
+/// Runs last on every tick, and every so often publishes the engine's figures without
+/// ever waiting on the metrics endpoint
+pub fn metrics_system(
+    time: Res<Time>,
+    metrics: Res<Metrics>,
+    mut tick_timer: ResMut<TickTimer>,
+    position_query: Query<&MapPosition>,
+    enemy_query: Query<&Name, With<Enemy>>,
+) {
+    let tick_seconds = tick_timer.tick_started.elapsed().as_secs_f64();
+    tick_timer.ticks += 1;
+    tick_timer.tick_seconds_total += tick_seconds;
+    tick_timer.tick_seconds_max = tick_timer.tick_seconds_max.max(tick_seconds);
+
+    if tick_timer.publish_stopwatch.elapsed_secs() < PUBLISH_INTERVAL_SECS {
+        tick_timer.publish_stopwatch.tick(time.delta());
+        return;
+    }
+
+    // Someone is reading the last figures, try again next tick rather than wait for them
+    let Ok(mut published) = metrics.0.engine.try_write() else {
+        return;
+    };
+
+    let mut entities_per_map = BTreeMap::new();
+    for map_position in position_query.iter() {
+        *entities_per_map.entry(map_position.map_id.0).or_default() += 1;
+    }
+
+    let mut enemies_by_type = BTreeMap::new();
+    for name in enemy_query.iter() {
+        *enemies_by_type.entry(name.to_string()).or_default() += 1;
+    }
+
+    *published = EngineMetrics {
+        ticks: tick_timer.ticks,
+        tick_seconds_total: tick_timer.tick_seconds_total,
+        tick_seconds_max: tick_timer.tick_seconds_max,
+        entities_per_map,
+        enemies_by_type,
+    };
+
+    tick_timer.tick_seconds_max = 0.0;
+    tick_timer.publish_stopwatch.reset();
+}
@@ -1,21 +1,31 @@
+pub mod admin;
 pub mod ai;
 pub mod build_maps;
 pub mod change_map;
+pub mod clock;
 pub mod cooldown;
 pub mod death;
 pub mod debug;
+pub mod instances;
 pub mod join_game;
 pub mod leave_game;
+pub mod login;
 pub mod message;
+pub mod metrics;
 pub mod mouse_click;
 pub mod mouse_hover;
 pub mod movement_keys;
+pub mod outbound;
 pub mod pathing;
 pub mod persistence;
+pub mod reload_data;
 pub mod resolve_consume;
 pub mod resolve_melee_attack;
 pub mod resolve_move;
 pub mod resolve_speak;
+pub mod resume_game;
+pub mod shutdown;
 pub mod spawn_enemy;
+pub mod spectate;
 pub mod update_client;
 pub mod update_map;
@@ -2,41 +2,57 @@ use bevy::prelude::*;
 use core_api::{EntityData, ServerMessageSingleClient};
 
 use crate::{
-    components::{eyes::Eyes, BlocksLight, MapPosition, User},
-    resources::{MessageSenderSingleClient, MouseHoverBuffer},
+    components::{eyes::Eyes, BlocksLight, Description, Floor, MapPosition, User},
+    resources::{world::GameWorld, CurrentUserMaps, MessageSenderSingleClient, MouseHoverBuffer},
 };
 
-/// Looks for an entity at a tile position being hovered
+/// Looks for an entity at a tile position being hovered, on the map the user is looking at.
+/// Whatever stands on the tile is described rather than the floor under it.
 pub fn mouse_hover_system(
     sender_single_client: Res<MessageSenderSingleClient>,
     mut mouse_hover_buffer: ResMut<MouseHoverBuffer>,
-    mut set: ParamSet<(
-        Query<(&MapPosition, &Name, Option<&BlocksLight>)>,
-        Query<(&User, &Eyes)>,
+    game_world: Res<GameWorld>,
+    current_user_maps: Res<CurrentUserMaps>,
+    tiles: Query<(
+        &MapPosition,
+        &Name,
+        Option<&BlocksLight>,
+        Option<&Description>,
+        Option<&Floor>,
     )>,
+    users: Query<(&User, &Eyes)>,
 ) {
-    if let Some((id, hover_pos)) = mouse_hover_buffer.0.pop_front() {
+    while let Some((id, hover_pos)) = mouse_hover_buffer.0.pop_front() {
+        // Nothing to describe for users who aren't on a map, or tiles off the edge of it
+        let Some(user_map_pos) = current_user_maps.0.get(&id) else {
+            continue;
+        };
+        let on_map = game_world
+            .game_maps
+            .get(&user_map_pos.map_id)
+            .map_or(false, |map| map.inside_map_bounds(&hover_pos));
+
         let mut hover_entity_info: Option<EntityData> = None;
-        for (ent_map_pos, name, blocks_light) in set.p0().iter() {
-            if hover_pos == ent_map_pos.pos {
-                hover_entity_info = Some(EntityData {
-                    name: name.into(),
-                    blocks_light: blocks_light.is_some(),
-                    visible_to_player: false,
-                });
-                break;
-            }
+        if on_map {
+            let hovered = tiles
+                .iter()
+                .filter(|(map_pos, ..)| {
+                    map_pos.map_id == user_map_pos.map_id && map_pos.pos == hover_pos
+                })
+                .min_by_key(|(.., floor)| floor.is_some());
+
+            hover_entity_info = hovered.map(|(_, name, blocks_light, description, _)| EntityData {
+                name: name.into(),
+                blocks_light: blocks_light.is_some(),
+                visible_to_player: false,
+                description: description.map(|description| description.0.clone()),
+            });
         }
 
         if let Some(hover_entity_info) = &mut hover_entity_info {
-            for (user, eyes) in set.p1().iter() {
-                if user.0 == id {
-                    if eyes.position_visible(&hover_pos) {
-                        hover_entity_info.visible_to_player = true;
-                    }
-
-                    break;
-                }
+            // Spectators have no eyes of their own, so nothing is visible to them as a player
+            if let Some((_, eyes)) = users.iter().find(|(user, _)| user.0 == id) {
+                hover_entity_info.visible_to_player = eyes.position_visible(&hover_pos);
             }
         }
 
@@ -6,8 +6,9 @@ use crate::{
     components::{
         combat_stats::CombatStats, cooldown::Cooldown, hp::Hp, intend_consume::IntendConsume,
         intend_melee_attack::IntendMeleeAttack, intend_move::IntendMove, intend_speak::IntendSpeak,
-        speaks::Speaks, Bones, MapPosition, User,
+        Bones, MapPosition, User,
     },
+    data::tile_properties::Interaction,
     resources::{world::GameWorld, KeypressBuffer},
 };
 
@@ -20,7 +21,7 @@ pub fn movement_keys_system(
         Entity,
         &MapPosition,
         Option<&CombatStats>,
-        Option<&Speaks>,
+        Option<&Interaction>,
         Option<&Bones>,
     )>,
     mut commands: Commands,
@@ -82,11 +83,11 @@ pub fn movement_keys_system(
                     });
                     cooldown.time_remaining = cooldown.move_time;
                 } else {
-                    for (other_ent, other_map_pos, other_combat_stats, other_speaks, _) in
+                    for (other_ent, other_map_pos, other_combat_stats, other_interaction, _) in
                         blocker_query.iter()
                     {
                         if other_map_pos.map_id == map_pos.map_id && other_map_pos.pos == new_pos {
-                            if other_speaks.is_some() {
+                            if other_interaction == Some(&Interaction::Talk) {
                                 commands
                                     .entity(entity)
                                     .insert(IntendSpeak { target: other_ent });
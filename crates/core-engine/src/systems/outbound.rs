@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use core_api::{ServerMessageSingleClient, TickOutput, UserId};
+
+use crate::resources::{
+    OutboundAllClientsReceiver, OutboundReceiver, Spectators, Tick, TickSender,
+};
+
+/// Spectators only need enough to draw the map they are watching
+fn visible_to_spectators(message: &ServerMessageSingleClient) -> bool {
+    matches!(
+        message,
+        ServerMessageSingleClient::UpdateFullGameMap { .. }
+            | ServerMessageSingleClient::AddSprite(_)
+            | ServerMessageSingleClient::RemoveSprite(_)
+            | ServerMessageSingleClient::EntityPositionChange(_)
+    )
+}
+
+/// Sends everything each user was sent during this tick, and every message for all clients,
+/// to the server in one piece with the tick's number, then moves on to the next tick
+pub fn outbound_flush_system(
+    mut outbound_receiver: ResMut<OutboundReceiver>,
+    mut outbound_all_clients_receiver: ResMut<OutboundAllClientsReceiver>,
+    tick_sender: Res<TickSender>,
+    spectators: Res<Spectators>,
+    mut tick: ResMut<Tick>,
+) {
+    let mut batches: HashMap<UserId, Vec<ServerMessageSingleClient>> = HashMap::new();
+
+    while let Ok((user_id, message)) = outbound_receiver.0.try_recv() {
+        if spectators.0.contains(&user_id) && !visible_to_spectators(&message) {
+            continue;
+        }
+
+        batches.entry(user_id).or_default().push(message);
+    }
+
+    let mut broadcasts = Vec::new();
+    while let Ok(message) = outbound_all_clients_receiver.0.try_recv() {
+        broadcasts.push(message);
+    }
+
+    if !batches.is_empty() || !broadcasts.is_empty() {
+        // Blocks while the server's channel is full, which holds the game back until it
+        // catches up
+        tick_sender
+            .0
+            .blocking_send(TickOutput {
+                tick: tick.0,
+                batches: batches.into_iter().collect(),
+                broadcasts,
+            })
+            .ok();
+    }
+
+    tick.0 = tick.0.wrapping_add(1);
+}
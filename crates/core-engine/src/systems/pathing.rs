@@ -6,13 +6,19 @@ use core_api::{EntityIndex, ServerMessageSingleClient, SpriteUpdate};
 use crate::{
     components::{paths::Paths, BlocksLight, BlocksMovement, MapPosition, Renderable, User},
     events::ShouldUpdateMap,
-    resources::{world::GameWorld, CurrentUserMaps, MessageSenderSingleClient, DebugStopwatch},
+    resources::{
+        rng::{GameRng, RngStream},
+        world::GameWorld,
+        CurrentUserMaps, DebugStopwatch, MessageSenderSingleClient,
+    },
 };
 
 /// THIS SYSTEM IS NO LONGER USED CAN PROBABLY DELETE
 /// THIS SYSTEM IS NO LONGER USED CAN PROBABLY DELETE
 /// 
 /// Move randomly
+// Moving an entity updates the map, the mover's client and the users watching
+#[allow(clippy::too_many_arguments)]
 pub fn pathing_system(
     mut move_stopwatch: ResMut<DebugStopwatch>,
     // sender: Res<MessageSenderAllClients>,
@@ -31,6 +37,7 @@ pub fn pathing_system(
     )>,
     mut current_user_maps: ResMut<CurrentUserMaps>,
     sender_single_client: Res<MessageSenderSingleClient>,
+    mut rng: ResMut<GameRng>,
 ) {
     if move_stopwatch.0.elapsed_secs() < 0.5 {
         move_stopwatch.0.tick(time.delta());
@@ -61,7 +68,11 @@ pub fn pathing_system(
                     // [TODO] This is still pretty janky, right now the entity will still pop from
                     // their path even if they try to move to a blocked tile and probably teleport to the
                     // next one on their turn after
-                    let unlocked_position = map.random_movement_unblocked_tile();
+                    let Some(unlocked_position) =
+                        map.random_movement_unblocked_tile(rng.stream(RngStream::Placement))
+                    else {
+                        continue;
+                    };
 
                     // let new_path = Paths::generate_direct_to_position(&pos, &unlocked_position);
                     let new_path = Paths::generate_astar(&map_pos.pos, &unlocked_position, &map);
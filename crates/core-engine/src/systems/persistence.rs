@@ -1,9 +1,14 @@
 use bevy::prelude::*;
-use core_api::{DatabaseRequest, ServerMessageAllClients, UserId};
+use core_api::{
+    DatabaseRequest, DatabaseResponse, ServerMessageAllClients, ServerMessageSingleClient, UserId,
+};
 
 use crate::{
-    components::{MapPosition, User},
-    resources::{DatabaseReceiver, DatabaseSender, MessageSenderAllClients},
+    components::{account::Account, MapPosition, User},
+    resources::{
+        AuthenticatedUsers, DatabaseReceiver, DatabaseSender, MessageSenderAllClients,
+        MessageSenderSingleClient, PendingLogins,
+    },
 };
 
 /// Send any request for data, or send data to save in the SQLite database
@@ -13,29 +18,66 @@ pub fn database_sender_system(
 ) {
     for _ in query.iter() {
         // Send a message to the DB any time any entity changes position for any reason
-        db_sender
-            .0
-            .send((
-                UserId(-1), // No specific user, but there could be
-                DatabaseRequest::Placeholder,
-            ))
-            .ok();
+        db_sender.send(
+            UserId(-1), // No specific user, but there could be
+            DatabaseRequest::Placeholder,
+        );
     }
 }
 
 /// Receive a response from the SQLite database message system
 pub fn database_receiver_system(
     mut db_receiver: ResMut<DatabaseReceiver>,
+    mut pending_logins: ResMut<PendingLogins>,
+    mut authenticated_users: ResMut<AuthenticatedUsers>,
     sender_all_clients: Res<MessageSenderAllClients>,
+    sender_single_client: Res<MessageSenderSingleClient>,
 ) {
-    while let Ok((_user_id, db_response)) = db_receiver.0.try_recv() {
+    while let Ok((user_id, db_response)) = db_receiver.0.try_recv() {
         match db_response {
-            core_api::DatabaseResponse::MoveCount(move_count) => {
+            DatabaseResponse::MoveCount(move_count) => {
                 sender_all_clients
                     .0
                     .send(ServerMessageAllClients::MoveCount(move_count))
                     .ok();
             }
+            DatabaseResponse::LoggedIn { account_id, name } => {
+                // The user disconnected while their login was being checked
+                if !pending_logins.0.remove(&user_id) {
+                    continue;
+                }
+
+                // Only one connection per account, otherwise it could have two players
+                let reply = if authenticated_users
+                    .0
+                    .values()
+                    .any(|account| account.id == account_id)
+                {
+                    ServerMessageSingleClient::LoginFailed {
+                        reason: format!("{} is already logged in", name),
+                    }
+                } else {
+                    info!("User {} logged in as {}", user_id.0, name);
+                    authenticated_users.0.insert(
+                        user_id,
+                        Account {
+                            id: account_id,
+                            name: name.clone(),
+                        },
+                    );
+                    ServerMessageSingleClient::LoggedIn { name }
+                };
+
+                sender_single_client.0.send((user_id, reply)).ok();
+            }
+            DatabaseResponse::LoginFailed(reason) => {
+                if pending_logins.0.remove(&user_id) {
+                    sender_single_client
+                        .0
+                        .send((user_id, ServerMessageSingleClient::LoginFailed { reason }))
+                        .ok();
+                }
+            }
         }
     }
 }
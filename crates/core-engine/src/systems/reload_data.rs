@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+use core_api::AdminResponse;
+
+use crate::{
+    data::{
+        dialogue_contents::DialogueContents,
+        enemy_configs::EnemyConfigs,
+        game_data::{last_modified, load_game_data, GameData, DIALOGUE_CONTENTS_FILE},
+        player_configs::{PlayerConfigs, SharedPlayerConfigs},
+        tile_properties::TileProperties,
+    },
+    resources::{config::EngineConfig, DataWatch, MapLayouts, ReloadDataBuffer},
+};
+
+/// How often (in seconds) the data files are looked at when `watch_data` is on
+const WATCH_INTERVAL_SECS: f32 = 1.0;
+
+/// Whether the data files were saved since they were last looked at
+fn data_saved(config: &EngineConfig, data_watch: &mut DataWatch, time: &Time) -> bool {
+    if !config.watch_data {
+        return false;
+    }
+
+    data_watch.stopwatch.tick(time.delta());
+    if data_watch.stopwatch.elapsed_secs() < WATCH_INTERVAL_SECS {
+        return false;
+    }
+    data_watch.stopwatch.reset();
+
+    let modified = last_modified(&config.data_dir);
+    if modified == data_watch.last_modified {
+        return false;
+    }
+    data_watch.last_modified = modified;
+    true
+}
+
+/// Reads the data files again, they must still fit every map in the game
+fn reload(
+    config: &EngineConfig,
+    map_layouts: &MapLayouts,
+    tile_properties: &TileProperties,
+) -> Result<GameData, String> {
+    let data = load_game_data(&config.data_dir).map_err(|error| error.to_string())?;
+
+    for map_layout in &map_layouts.0 {
+        map_layout
+            .validate(&data.dialogue_contents, tile_properties)
+            .map_err(|reason| {
+                format!(
+                    "{} no longer fits map {} \"{}\": {}",
+                    DIALOGUE_CONTENTS_FILE, map_layout.id, map_layout.name, reason
+                )
+            })?;
+    }
+
+    Ok(data)
+}
+
+/// Swaps in new player, enemy and dialogue data when an admin asks or, with `watch_data`,
+/// when a data file is saved. Nothing changes unless every file is fine, so a mistake
+/// is reported and the game carries on with what it had. New stats apply to players and
+/// enemies spawned from then on, new dialogue to the next conversation.
+// Each data file being watched is swapped into its own resource
+#[allow(clippy::too_many_arguments)]
+pub fn reload_data_system(
+    config: Res<EngineConfig>,
+    time: Res<Time>,
+    map_layouts: Res<MapLayouts>,
+    tile_properties: Res<TileProperties>,
+    mut reload_data_buffer: ResMut<ReloadDataBuffer>,
+    mut data_watch: ResMut<DataWatch>,
+    mut player_configs: ResMut<PlayerConfigs>,
+    shared_player_configs: Res<SharedPlayerConfigs>,
+    mut enemy_configs: ResMut<EnemyConfigs>,
+    mut dialogue_contents: ResMut<DialogueContents>,
+) {
+    let saved = data_saved(&config, &mut data_watch, &time);
+    if !saved && reload_data_buffer.0.is_empty() {
+        return;
+    }
+
+    let result = reload(&config, &map_layouts, &tile_properties);
+    match &result {
+        Ok(_) => info!("Reloaded the game data from {}", config.data_dir.display()),
+        Err(error) => error!("Kept the current game data, {}", error),
+    }
+
+    for reply in reload_data_buffer.0.drain(..) {
+        let reply_result = match &result {
+            Ok(_) => Ok(AdminResponse::Done),
+            Err(error) => Err(error.clone()),
+        };
+        reply.send(reply_result).ok();
+    }
+
+    if let Ok(data) = result {
+        shared_player_configs.replace(data.player_configs.clone());
+        *player_configs = data.player_configs;
+        *enemy_configs = data.enemy_configs;
+        *dialogue_contents = data.dialogue_contents;
+    }
+}
@@ -6,7 +6,10 @@ use crate::{
         intend_melee_attack::IntendMeleeAttack,
         MapPosition, User,
     },
-    resources::{CurrentUserMaps, MessageSenderAllClients, MessageSenderSingleClient},
+    resources::{
+        rng::{GameRng, RngStream},
+        CurrentUserMaps, MessageSenderAllClients, MessageSenderSingleClient,
+    },
 };
 use bevy::prelude::*;
 use core_api::{
@@ -36,6 +39,7 @@ pub fn resolve_melee_attack_system(
     sender_all_clients: Res<MessageSenderAllClients>,
     sender_single_client: Res<MessageSenderSingleClient>,
     current_user_maps: Res<CurrentUserMaps>,
+    mut rng: ResMut<GameRng>,
 ) {
     for (ent, attacker_combat_stats, intend_melee_attack, name, attacker_user, cooldown) in
         attacker_query.iter()
@@ -49,9 +53,8 @@ pub fn resolve_melee_attack_system(
             target_user,
         )) = target_query.get_mut(intend_melee_attack.target)
         {
-            let mut rng = rand::thread_rng();
             // Damage minimum is a random number between 0 and 2
-            let minimum = rng.gen_range(0..3);
+            let minimum = rng.stream(RngStream::Combat).gen_range(0..3);
 
             let damage =
                 (attacker_combat_stats.attack - target_combat_stats.defense).max(0) + minimum;
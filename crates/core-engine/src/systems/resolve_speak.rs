@@ -1,5 +1,6 @@
 use crate::{
     components::{intend_speak::IntendSpeak, speaks::Speaks, User},
+    data::{dialogue_contents::DialogueContents, tile_properties::Interaction},
     resources::MessageSenderSingleClient,
 };
 use bevy::prelude::*;
@@ -7,19 +8,28 @@ use core_api::ServerMessageSingleClient;
 
 pub fn resolve_speak_system(
     speaker_query: Query<(Entity, &IntendSpeak, &User)>,
-    target_query: Query<(&Name, &Speaks)>,
+    target_query: Query<(&Name, &Interaction, Option<&Speaks>)>,
+    dialogue_contents: Res<DialogueContents>,
     sender_single_client: Res<MessageSenderSingleClient>,
     mut commands: Commands,
 ) {
     for (ent, intend_speak, user) in speaker_query.iter() {
-        if let Ok((name, speaks)) = target_query.get(intend_speak.target) {
+        // Only tiles that can be talked to answer, and only those the map gave dialogue
+        if let Ok((name, Interaction::Talk, Some(speaks))) = target_query.get(intend_speak.target) {
+            // Maps are checked against the dialogue whenever either is loaded
+            let Some(dialogue_map) = dialogue_contents.0.get(&speaks.0) else {
+                warn!("{} has the missing dialogue \"{}\"", name, speaks.0);
+                commands.entity(ent).remove::<IntendSpeak>();
+                continue;
+            };
+
             sender_single_client
                 .0
                 .send((
                     user.0,
                     ServerMessageSingleClient::ShowDialogue {
                         entity_name: name.to_string(),
-                        dialogue_map: speaks.0.clone(),
+                        dialogue_map: dialogue_map.clone(),
                     },
                 ))
                 .ok();
@@ -0,0 +1,57 @@
+use bevy::prelude::*;
+use core_api::ServerMessageSingleClient;
+
+use crate::{
+    components::{
+        account::Account,
+        session::{Disconnected, Session},
+        MapPosition, User,
+    },
+    events::ShouldSendFullMapUpdateToUser,
+    resources::{AuthenticatedUsers, CurrentUserMaps, MessageSenderSingleClient, ResumeBuffer},
+};
+
+/// Hands an existing player entity to a reconnecting user that presents its session token
+pub fn resume_game_system(
+    sender_single_client: Res<MessageSenderSingleClient>,
+    mut commands: Commands,
+    mut resume_buffer: ResMut<ResumeBuffer>,
+    mut current_user_maps: ResMut<CurrentUserMaps>,
+    mut authenticated_users: ResMut<AuthenticatedUsers>,
+    mut ev_update_user: EventWriter<ShouldSendFullMapUpdateToUser>,
+    query: Query<(Entity, &User, &Session, &MapPosition, &Name, &Account)>,
+) {
+    if let Some((resuming_user_id, token)) = resume_buffer.0.pop_front() {
+        let session_entity = query
+            .iter()
+            .find(|(_, _, session, _, _, _)| session.token == token);
+
+        if let Some((entity, previous_user, _, map_pos, name, account)) = session_entity {
+            info!("{} resumed as user {}", name, resuming_user_id.0);
+
+            // The previous socket may not have been noticed as closed yet
+            current_user_maps.0.remove(&previous_user.0);
+            authenticated_users.0.remove(&previous_user.0);
+            authenticated_users
+                .0
+                .insert(resuming_user_id, account.clone());
+            current_user_maps
+                .0
+                .insert(resuming_user_id, map_pos.clone());
+
+            commands
+                .entity(entity)
+                .insert(User(resuming_user_id))
+                .remove::<Disconnected>();
+
+            // Everyone else on the map can already see this player, only the resuming
+            // client needs to be told about the whole map
+            ev_update_user.send(ShouldSendFullMapUpdateToUser(resuming_user_id));
+        } else {
+            sender_single_client
+                .0
+                .send((resuming_user_id, ServerMessageSingleClient::ResumeFailed))
+                .ok();
+        }
+    }
+}
@@ -0,0 +1,15 @@
+use bevy::{app::AppExit, prelude::*};
+use tokio::sync::oneshot::error::TryRecvError;
+
+use crate::resources::ShutdownReceiver;
+
+/// Stops the app when the server asks it to, or when the server has gone away
+pub fn shutdown_system(
+    mut shutdown_receiver: ResMut<ShutdownReceiver>,
+    mut ev_app_exit: EventWriter<AppExit>,
+) {
+    if !matches!(shutdown_receiver.0.try_recv(), Err(TryRecvError::Empty)) {
+        info!("Stopping the game engine");
+        ev_app_exit.send(AppExit);
+    }
+}
@@ -1,32 +1,59 @@
+use ae_position::Position;
 use bevy::prelude::*;
 use core_api::{
     EntityIndex, LogMessage, ServerMessageAllClients, ServerMessageSingleClient, SpawnableEnemy,
     SpriteTexture, SpriteUpdate,
 };
-use rand::Rng;
+use rand::{seq::SliceRandom, Rng};
 
 use crate::{
     components::{
         ai::Ai, cooldown::Cooldown, eyes::Eyes, BlocksMovement, Bones, Enemy, MapPosition,
-        Renderable,
+        Renderable, SpawnPoint,
     },
     data::enemy_configs::EnemyConfigs,
     resources::{
-        map::{GameMap, BAD_GUY_MAP_ID},
+        config::EngineConfig,
+        map::GameMap,
+        rng::{GameRng, RngStream},
         world::{GameWorld, MapId},
         CurrentUserMaps, MessageSenderAllClients, MessageSenderSingleClient, SpawnStopWatch,
         SpawnableEnemyBuffer,
     },
 };
 
+/// One of the map's spawn points that nothing is standing on, or anywhere free if there are
+/// none. `None` when the map is full.
+fn spawn_position(
+    map: &GameMap,
+    spawn_points: &Query<&MapPosition, With<SpawnPoint>>,
+    rng: &mut impl Rng,
+) -> Option<Position> {
+    let free_spawn_points: Vec<&Position> = spawn_points
+        .iter()
+        .filter(|spawn_point| {
+            spawn_point.map_id == map.id() && !map.movement_blocked(&spawn_point.pos)
+        })
+        .map(|spawn_point| &spawn_point.pos)
+        .collect();
+
+    match free_spawn_points.choose(rng) {
+        Some(pos) => Some((*pos).clone()),
+        None => map.random_movement_unblocked_tile(rng),
+    }
+}
+
 fn spawn_enemy_and_communicate(
-    bad_guy_map: &GameMap,
+    map: &GameMap,
+    spawn_points: &Query<&MapPosition, With<SpawnPoint>>,
     current_user_maps: &Res<CurrentUserMaps>,
     enemy: &SpawnableEnemy,
+    placed_at: Option<Position>,
     enemy_configs: &Res<EnemyConfigs>,
     commands: &mut Commands,
     sender_single_client: &Res<MessageSenderSingleClient>,
     sender_all_clients: &Res<MessageSenderAllClients>,
+    rng: &mut GameRng,
 ) {
     let enemy_config = match enemy {
         core_api::SpawnableEnemy::Slime => &enemy_configs.slime,
@@ -34,14 +61,28 @@ fn spawn_enemy_and_communicate(
         core_api::SpawnableEnemy::Rat => &enemy_configs.rat,
     };
 
-    let mut enemy_commands = commands.spawn(Name::new(enemy_config.name.clone()));
+    let placed = placed_at.is_some();
+    let new_entity_pos = match placed_at {
+        Some(pos) => pos,
+        None => match spawn_position(map, spawn_points, rng.stream(RngStream::Placement)) {
+            Some(pos) => pos,
+            None => {
+                warn!(
+                    "Cannot spawn {:?} on map {}, there is no free tile",
+                    enemy,
+                    map.id().0
+                );
+                return;
+            }
+        },
+    };
 
-    let new_entity_pos = bad_guy_map.random_movement_unblocked_tile();
+    let mut enemy_commands = commands.spawn(Name::new(enemy_config.name.clone()));
     let new_entity_texture = enemy_config.texture;
     enemy_commands
         .insert(MapPosition {
             pos: new_entity_pos.clone(),
-            map_id: bad_guy_map.id(),
+            map_id: map.id(),
         })
         .insert(Renderable {
             texture: new_entity_texture,
@@ -56,7 +97,7 @@ fn spawn_enemy_and_communicate(
             attack_time: enemy_config.attack_time,
             move_time: enemy_config.move_time,
         })
-        .insert(Eyes::new(bad_guy_map, enemy_config.visibility));
+        .insert(Eyes::new(map, enemy_config.visibility));
 
     let new_entity_idx = enemy_commands.id().index();
 
@@ -64,7 +105,7 @@ fn spawn_enemy_and_communicate(
         .0
         .iter()
         .for_each(|(user_id, user_map_pos)| {
-            if user_map_pos.map_id == bad_guy_map.id() {
+            if user_map_pos.map_id == map.id() {
                 sender_single_client
                     .0
                     .send((
@@ -81,6 +122,11 @@ fn spawn_enemy_and_communicate(
             }
         });
 
+    // Enemies that come with a map were there all along
+    if placed {
+        return;
+    }
+
     let log_message = LogMessage(format!(
         "{} has spawned!",
         String::from(enemy_config.name.clone())
@@ -92,24 +138,29 @@ fn spawn_enemy_and_communicate(
 }
 
 /// Allows enemies to be spawned dynamically
+// Spawning checks timers, spawn points, enemy configs and who is watching each map
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_enemy_system(
     mut spawnable_enemy_buffer: ResMut<SpawnableEnemyBuffer>,
     sender_single_client: Res<MessageSenderSingleClient>,
     mut commands: Commands,
     current_user_maps: Res<CurrentUserMaps>,
     game_world: Res<GameWorld>,
+    config: Res<EngineConfig>,
     enemy_configs: Res<EnemyConfigs>,
     mut spawn_stopwatch: ResMut<SpawnStopWatch>,
     time: Res<Time>,
     enemy_query: Query<(Entity, &Renderable), With<Enemy>>,
+    spawn_points: Query<&MapPosition, With<SpawnPoint>>,
     sender_all_clients: Res<MessageSenderAllClients>,
+    mut rng: ResMut<GameRng>,
 ) {
     let bad_guy_map = game_world
         .game_maps
-        .get(&MapId(BAD_GUY_MAP_ID))
-        .expect("Somehow the primary map does not exist");
+        .get(&MapId(config.enemy_map_id))
+        .expect("Somehow the enemy map does not exist");
 
-    if spawn_stopwatch.0.elapsed_secs() < 5.0 {
+    if spawn_stopwatch.0.elapsed_secs() < config.spawn_interval_secs {
         spawn_stopwatch.0.tick(time.delta());
     } else {
         spawn_stopwatch.0.reset();
@@ -129,37 +180,44 @@ pub fn spawn_enemy_system(
             }
         }
 
-        let mut rng = rand::thread_rng();
-
-        let d20 = rng.gen_range(0..20) + 1;
+        let d20 = rng.stream(RngStream::Spawning).gen_range(0..20) + 1;
 
         match d20 {
             1..=15 if rats < 10 && rat_kings == 1 => spawn_enemy_and_communicate(
                 &bad_guy_map,
+                &spawn_points,
                 &current_user_maps,
                 &SpawnableEnemy::Rat,
+                None,
                 &enemy_configs,
                 &mut commands,
                 &sender_single_client,
                 &sender_all_clients,
+                &mut rng,
             ),
             16..=18 if slimes < 4 => spawn_enemy_and_communicate(
                 &bad_guy_map,
+                &spawn_points,
                 &current_user_maps,
                 &SpawnableEnemy::Slime,
+                None,
                 &enemy_configs,
                 &mut commands,
                 &sender_single_client,
                 &sender_all_clients,
+                &mut rng,
             ),
             19..=20 if rat_kings < 1 => spawn_enemy_and_communicate(
                 &bad_guy_map,
+                &spawn_points,
                 &current_user_maps,
                 &SpawnableEnemy::Slime,
+                None,
                 &enemy_configs,
                 &mut commands,
                 &sender_single_client,
                 &sender_all_clients,
+                &mut rng,
             ),
             _ => {
                 // Nada
@@ -167,16 +225,24 @@ pub fn spawn_enemy_system(
         }
     }
 
-    while let Some((_user_id, enemy)) = spawnable_enemy_buffer.0.pop_front() {
+    while let Some((map_id, enemy, placed_at)) = spawnable_enemy_buffer.0.pop_front() {
+        let Some(map) = game_world.game_maps.get(&map_id) else {
+            warn!("Cannot spawn {:?} on missing map {}", enemy, map_id.0);
+            continue;
+        };
+
         // [TODO] Right now it's slime only but in the future it could be others
         spawn_enemy_and_communicate(
-            &bad_guy_map,
+            map,
+            &spawn_points,
             &current_user_maps,
             &enemy,
+            placed_at,
             &enemy_configs,
             &mut commands,
             &sender_single_client,
             &sender_all_clients,
+            &mut rng,
         )
     }
 }
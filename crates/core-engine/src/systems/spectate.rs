@@ -0,0 +1,60 @@
+use ae_position::Position;
+use bevy::prelude::*;
+
+use crate::{
+    components::MapPosition,
+    events::ShouldSendFullMapUpdateToUser,
+    resources::{
+        map::GameMap,
+        world::{GameWorld, MapId},
+        CurrentUserMaps, SpectateBuffer, Spectators,
+    },
+};
+
+/// Spectators watch from the middle of the map, where the camera is centred for them
+pub fn spectator_position(map_id: MapId, map: &GameMap) -> MapPosition {
+    MapPosition {
+        pos: Position {
+            x: map.width() / 2,
+            y: map.height() / 2,
+        },
+        map_id,
+    }
+}
+
+/// Lets a user watch a map without spawning a player for them
+pub fn spectate_system(
+    game_world: Res<GameWorld>,
+    mut spectate_buffer: ResMut<SpectateBuffer>,
+    mut spectators: ResMut<Spectators>,
+    mut current_user_maps: ResMut<CurrentUserMaps>,
+    mut ev_update_user: EventWriter<ShouldSendFullMapUpdateToUser>,
+) {
+    if let Some((spectator_user_id, map_id)) = spectate_buffer.0.pop_front() {
+        // Someone already playing can't also be a spectator
+        if current_user_maps.0.contains_key(&spectator_user_id) {
+            return;
+        }
+
+        let Some(map) = game_world.game_maps.get(&map_id) else {
+            warn!(
+                "User {} tried to spectate missing map {}",
+                spectator_user_id.0, map_id.0
+            );
+            return;
+        };
+
+        info!(
+            "User {} is spectating map {}",
+            spectator_user_id.0, map_id.0
+        );
+
+        // Spectators get map updates like anyone else on the map
+        current_user_maps
+            .0
+            .insert(spectator_user_id, spectator_position(map_id, map));
+        spectators.0.insert(spectator_user_id);
+
+        ev_update_user.send(ShouldSendFullMapUpdateToUser(spectator_user_id));
+    }
+}
@@ -3,17 +3,58 @@ use core_api::{EntityIndex, ServerMessageSingleClient, SpriteUpdate};
 
 use crate::{
     components::{MapPosition, Renderable, User},
-    events::ShouldSendFullMapUpdateToClient,
+    events::{ShouldSendFullMapUpdateToClient, ShouldSendFullMapUpdateToUser},
     resources::{world::MapId, CurrentUserMaps, MessageSenderSingleClient},
 };
 
+/// Every sprite that should be rendered on a map
+fn sprites_on_map(
+    query: &Query<(Entity, &MapPosition, Option<&User>, &Renderable)>,
+    map_id: MapId,
+) -> Vec<SpriteUpdate> {
+    query
+        .iter()
+        .filter_map(|(entity, map_pos, _, sprite)| {
+            if map_pos.map_id != map_id {
+                None
+            } else {
+                Some(SpriteUpdate {
+                    entity: EntityIndex {
+                        idx: entity.index(),
+                    },
+                    pos: map_pos.pos.clone(),
+                    sprite: sprite.texture,
+                })
+            }
+        })
+        .collect::<Vec<_>>()
+}
+
 /// Communicate any relevant sprite change information from the game engine to the client
 pub fn update_client_system(
     sender_single_client: Res<MessageSenderSingleClient>,
     mut ev_update_client: EventReader<ShouldSendFullMapUpdateToClient>,
+    mut ev_update_user: EventReader<ShouldSendFullMapUpdateToUser>,
     current_user_maps: Res<CurrentUserMaps>,
     query: Query<(Entity, &MapPosition, Option<&User>, &Renderable)>,
 ) {
+    // Users that need the full map without everyone else on that map getting it too
+    ev_update_user.iter().for_each(|event| {
+        let user_id = event.0;
+        if let Some(user_map_pos) = current_user_maps.0.get(&user_id) {
+            sender_single_client
+                .0
+                .send((
+                    user_id,
+                    ServerMessageSingleClient::UpdateFullGameMap {
+                        camera: user_map_pos.pos.clone(),
+                        entities: sprites_on_map(&query, user_map_pos.map_id),
+                    },
+                ))
+                .ok();
+        }
+    });
+
     if ev_update_client.is_empty() {
         return;
     }
@@ -25,22 +66,7 @@ pub fn update_client_system(
         if !updated_maps.contains(&update_map_id) {
             updated_maps.push(update_map_id);
 
-            let entities_on_this_map = query
-                .iter()
-                .filter_map(|(entity, map_pos, _, sprite)| {
-                    if map_pos.map_id != update_map_id {
-                        None
-                    } else {
-                        Some(SpriteUpdate {
-                            entity: EntityIndex {
-                                idx: entity.index(),
-                            },
-                            pos: map_pos.pos.clone(),
-                            sprite: sprite.texture,
-                        })
-                    }
-                })
-                .collect::<Vec<_>>();
+            let entities_on_this_map = sprites_on_map(&query, update_map_id);
 
             current_user_maps
                 .0
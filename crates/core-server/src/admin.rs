@@ -0,0 +1,315 @@
+use ae_position::Position;
+use core_api::{
+    AdminCommand, AdminReply, AdminResponse, MapGeneratorSettings, SpawnableEnemy, UserId,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::{mpsc::UnboundedSender, oneshot};
+use warp::{
+    http::StatusCode,
+    reject::Reject,
+    reply::{self, Response},
+    Filter, Rejection, Reply,
+};
+
+use crate::connections::ConnectionsLock;
+
+/// Carries admin commands into the game engine along with a way to answer them
+pub type AdminSender = UnboundedSender<(AdminCommand, oneshot::Sender<AdminReply>)>;
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl Reject for Unauthorized {}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+#[derive(Deserialize)]
+struct BroadcastBody {
+    message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SpawnBody {
+    map_id: i32,
+    enemy: SpawnableEnemy,
+}
+
+#[derive(Deserialize)]
+struct GenerateMapBody {
+    name: String,
+    #[serde(flatten)]
+    settings: MapGeneratorSettings,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TeleportBody {
+    map_id: i32,
+    x: i32,
+    y: i32,
+}
+
+/// Compares every byte so the time taken doesn't reveal how much of the token was right
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Only lets requests through that carry `Authorization: Bearer <token>`. Without a
+/// configured token every request is turned away.
+fn authorized(token: Option<Arc<str>>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let token = token.clone();
+            async move {
+                let given = header
+                    .as_deref()
+                    .and_then(|header| header.strip_prefix("Bearer "));
+
+                match (given, token) {
+                    (Some(given), Some(token)) if tokens_match(given, &token) => Ok(()),
+                    _ => Err(warp::reject::custom(Unauthorized)),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+async fn handle_unauthorized(rejection: Rejection) -> Result<Response, Rejection> {
+    if rejection.find::<Unauthorized>().is_some() {
+        Ok(error_response(
+            StatusCode::UNAUTHORIZED,
+            "Missing or invalid admin token".to_string(),
+        ))
+    } else {
+        Err(rejection)
+    }
+}
+
+fn error_response(status: StatusCode, error: String) -> Response {
+    reply::with_status(reply::json(&ErrorBody { error }), status).into_response()
+}
+
+/// Hands a command to the game engine and waits for it to be applied on the next tick
+async fn run_command(
+    sender: &AdminSender,
+    command: AdminCommand,
+) -> Result<AdminResponse, Response> {
+    let engine_stopped = || {
+        error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The game engine is not running".to_string(),
+        )
+    };
+
+    let (reply_sender, reply_receiver) = oneshot::channel();
+    sender
+        .send((command, reply_sender))
+        .map_err(|_| engine_stopped())?;
+
+    match reply_receiver.await {
+        Ok(Ok(response)) => Ok(response),
+        Ok(Err(error)) => Err(error_response(StatusCode::BAD_REQUEST, error)),
+        Err(_) => Err(engine_stopped()),
+    }
+}
+
+fn command_response(result: Result<AdminResponse, Response>) -> Response {
+    match result {
+        Ok(response) => reply::json(&response).into_response(),
+        Err(response) => response,
+    }
+}
+
+async fn list_users(sender: AdminSender) -> Response {
+    command_response(run_command(&sender, AdminCommand::ListUsers).await)
+}
+
+async fn kick_user(user_id: i32, sender: AdminSender, connections: ConnectionsLock) -> Response {
+    let result = run_command(&sender, AdminCommand::Kick(UserId(user_id))).await;
+
+    if result.is_ok() {
+        if let Some(connection) = connections.read().await.0.get(&user_id) {
+            // 1008 is the close code for a policy violation
+            connection.queue.hang_up(1008, "Kicked by an admin");
+        }
+    }
+
+    command_response(result)
+}
+
+async fn teleport_user(user_id: i32, body: TeleportBody, sender: AdminSender) -> Response {
+    let command = AdminCommand::Teleport {
+        user_id: UserId(user_id),
+        map_id: body.map_id,
+        pos: Position {
+            x: body.x,
+            y: body.y,
+        },
+    };
+
+    command_response(run_command(&sender, command).await)
+}
+
+async fn broadcast(body: BroadcastBody, sender: AdminSender) -> Response {
+    command_response(run_command(&sender, AdminCommand::Broadcast(body.message)).await)
+}
+
+async fn spawn_enemy(body: SpawnBody, sender: AdminSender) -> Response {
+    let command = AdminCommand::Spawn {
+        map_id: body.map_id,
+        enemy: body.enemy,
+    };
+
+    command_response(run_command(&sender, command).await)
+}
+
+async fn generate_map(body: GenerateMapBody, sender: AdminSender) -> Response {
+    let command = AdminCommand::GenerateMap {
+        name: body.name,
+        settings: body.settings,
+    };
+
+    command_response(run_command(&sender, command).await)
+}
+
+async fn create_instance(template_map_id: i32, sender: AdminSender) -> Response {
+    command_response(run_command(&sender, AdminCommand::CreateInstance { template_map_id }).await)
+}
+
+async fn reload_data(sender: AdminSender) -> Response {
+    command_response(run_command(&sender, AdminCommand::ReloadData).await)
+}
+
+/// Routes under `api/admin` for operating the live server, every one of them needs the
+/// admin token
+pub fn admin_routes(
+    token: Option<String>,
+    sender: AdminSender,
+    connections: ConnectionsLock,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    let token: Option<Arc<str>> = token.map(Into::into);
+    let sender = warp::any().map(move || sender.clone());
+    let connections = warp::any().map(move || connections.clone());
+
+    // GET /admin/users -> everyone with a socket open, where they are and what they are called
+    let users = warp::path!("users")
+        .and(warp::get())
+        .and(sender.clone())
+        .then(list_users);
+
+    // POST /admin/users/:id/kick
+    let kick = warp::path!("users" / i32 / "kick")
+        .and(warp::post())
+        .and(sender.clone())
+        .and(connections)
+        .then(kick_user);
+
+    // POST /admin/users/:id/teleport {"mapId": 1, "x": 5, "y": 5}
+    let teleport = warp::path!("users" / i32 / "teleport")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(sender.clone())
+        .then(teleport_user);
+
+    // POST /admin/broadcast {"message": "Server restarting soon"}
+    let broadcast = warp::path!("broadcast")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(sender.clone())
+        .then(broadcast);
+
+    // POST /admin/spawn {"mapId": 2, "enemy": "slime"}
+    let spawn = warp::path!("spawn")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(sender.clone())
+        .then(spawn_enemy);
+
+    // POST /admin/maps {"name": "The Sewers", "algorithm": "caves", "width": 40, ...}
+    let generate = warp::path!("maps")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(sender.clone())
+        .then(generate_map);
+
+    // POST /admin/maps/:id/instances -> the id of a new private copy of the map
+    let instance = warp::path!("maps" / i32 / "instances")
+        .and(warp::post())
+        .and(sender.clone())
+        .then(create_instance);
+
+    // POST /admin/reload-data -> re-reads the player, enemy and dialogue data files
+    let reload = warp::path!("reload-data")
+        .and(warp::post())
+        .and(sender)
+        .then(reload_data);
+
+    warp::path("api")
+        .and(warp::path("admin"))
+        .and(authorized(token))
+        .and(
+            users
+                .or(kick)
+                .unify()
+                .or(teleport)
+                .unify()
+                .or(broadcast)
+                .unify()
+                .or(spawn)
+                .unify()
+                .or(generate)
+                .unify()
+                .or(instance)
+                .unify()
+                .or(reload)
+                .unify(),
+        )
+        .recover(handle_unauthorized)
+        .unify()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_must_match_exactly() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secreT", "secret"));
+        assert!(!tokens_match("secret2", "secret"));
+        assert!(!tokens_match("", "secret"));
+    }
+
+    #[tokio::test]
+    async fn requests_without_the_token_are_rejected() {
+        let (sender, _receiver) = tokio::sync::mpsc::unbounded_channel();
+        let routes = admin_routes(
+            Some("secret".to_string()),
+            sender,
+            ConnectionsLock::default(),
+        );
+
+        let response = warp::test::request()
+            .path("/api/admin/users")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = warp::test::request()
+            .path("/api/admin/users")
+            .header("authorization", "Bearer wrong")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,300 @@
+use core_api::{ServerMessageAllClients, ServerMessageSingleClient};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{fmt, sync::Arc};
+use warp::ws::Message;
+
+/// `Sec-WebSocket-Protocol` value a client offers to receive JSON text frames
+pub const JSON_PROTOCOL: &str = "goblin.json";
+
+/// `Sec-WebSocket-Protocol` value a client offers to receive MessagePack binary frames
+pub const MESSAGE_PACK_PROTOCOL: &str = "goblin.msgpack";
+
+/// The wire format used to encode messages for one websocket connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    Json(serde_json::Error),
+    MessagePack(rmp_serde::decode::Error),
+    /// Ping, pong and close frames carry no game message
+    NotAMessage,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Json(e) => write!(f, "{}", e),
+            DecodeError::MessagePack(e) => write!(f, "{}", e),
+            DecodeError::NotAMessage => write!(f, "frame is not a text or binary message"),
+        }
+    }
+}
+
+/// Just enough of any tagged message to report what it claimed to be
+#[derive(Deserialize)]
+struct MessageType {
+    #[serde(rename = "type")]
+    message_type: String,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 2] = [Codec::Json, Codec::MessagePack];
+
+    /// Picks the first codec we support from the comma separated protocols offered by
+    /// the client in the handshake. `None` means the client did not offer anything we know.
+    pub fn negotiate(offered_protocols: &str) -> Option<Codec> {
+        offered_protocols
+            .split(',')
+            .map(str::trim)
+            .find_map(|protocol| match protocol {
+                JSON_PROTOCOL => Some(Codec::Json),
+                MESSAGE_PACK_PROTOCOL => Some(Codec::MessagePack),
+                _ => None,
+            })
+    }
+
+    /// The value to echo back in the `Sec-WebSocket-Protocol` response header
+    pub fn protocol(&self) -> &'static str {
+        match self {
+            Codec::Json => JSON_PROTOCOL,
+            Codec::MessagePack => MESSAGE_PACK_PROTOCOL,
+        }
+    }
+
+    /// Position of this codec in [`Codec::ALL`], handy for caching one encoding per codec
+    pub fn index(&self) -> usize {
+        match self {
+            Codec::Json => 0,
+            Codec::MessagePack => 1,
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Message {
+        match self {
+            Codec::Json => {
+                Message::text(serde_json::to_string(value).expect("Serialize should work"))
+            }
+            // Structs are written as maps with their field names, which is what the client's
+            // decoder turns back into the same objects JSON gives it
+            Codec::MessagePack => {
+                Message::binary(rmp_serde::to_vec_named(value).expect("Serialize should work"))
+            }
+        }
+    }
+
+    /// Encodes one client's batch for a tick around broadcasts that were already encoded for
+    /// every client, giving the same frame as encoding a `ServerMessageSingleClient::Batch`
+    pub fn encode_batch(
+        &self,
+        tick: u32,
+        messages: &[ServerMessageSingleClient],
+        broadcasts: &[Arc<EncodedBroadcast>],
+    ) -> Message {
+        let broadcasts = broadcasts.iter().map(|broadcast| broadcast.encoded(*self));
+
+        match self {
+            Codec::Json => {
+                let mut frame = format!(
+                    r#"{{"type":"batch","content":{{"tick":{},"messages":{},"broadcasts":["#,
+                    tick,
+                    serde_json::to_string(messages).expect("Serialize should work")
+                );
+                for (index, broadcast) in broadcasts.enumerate() {
+                    if index > 0 {
+                        frame.push(',');
+                    }
+                    frame.push_str(std::str::from_utf8(broadcast).expect("JSON is UTF-8"));
+                }
+                frame.push_str("]}}");
+                Message::text(frame)
+            }
+            Codec::MessagePack => {
+                let frame = message_pack_batch(tick, messages, broadcasts)
+                    .expect("Writing to a Vec should work");
+                Message::binary(frame)
+            }
+        }
+    }
+
+    /// Decodes a frame based on its type rather than the negotiated codec, text frames
+    /// are always JSON and binary frames are always MessagePack
+    pub fn decode<T: DeserializeOwned>(msg: &Message) -> Result<T, DecodeError> {
+        if let Ok(text) = msg.to_str() {
+            serde_json::from_str(text).map_err(DecodeError::Json)
+        } else if msg.is_binary() {
+            rmp_serde::from_slice(msg.as_bytes()).map_err(DecodeError::MessagePack)
+        } else {
+            Err(DecodeError::NotAMessage)
+        }
+    }
+
+    /// The `type` tag of a frame, even if the rest of the frame is not a valid message
+    pub fn message_type(msg: &Message) -> Option<String> {
+        let message_type = if let Ok(text) = msg.to_str() {
+            serde_json::from_str::<MessageType>(text).ok()
+        } else {
+            rmp_serde::from_slice::<MessageType>(msg.as_bytes()).ok()
+        };
+
+        message_type.map(|m| m.message_type)
+    }
+}
+
+/// The MessagePack version of `{ type: "batch", content: { tick, messages, broadcasts } }`
+fn message_pack_batch<'a>(
+    tick: u32,
+    messages: &[ServerMessageSingleClient],
+    broadcasts: impl ExactSizeIterator<Item = &'a [u8]>,
+) -> Result<Vec<u8>, rmp::encode::ValueWriteError> {
+    let mut frame = Vec::new();
+    rmp::encode::write_map_len(&mut frame, 2)?;
+    rmp::encode::write_str(&mut frame, "type")?;
+    rmp::encode::write_str(&mut frame, "batch")?;
+    rmp::encode::write_str(&mut frame, "content")?;
+
+    rmp::encode::write_map_len(&mut frame, 3)?;
+    rmp::encode::write_str(&mut frame, "tick")?;
+    rmp::encode::write_uint(&mut frame, u64::from(tick))?;
+    rmp::encode::write_str(&mut frame, "messages")?;
+    frame.extend(rmp_serde::to_vec_named(messages).expect("Serialize should work"));
+    rmp::encode::write_str(&mut frame, "broadcasts")?;
+    rmp::encode::write_array_len(&mut frame, broadcasts.len() as u32)?;
+    for broadcast in broadcasts {
+        frame.extend_from_slice(broadcast);
+    }
+
+    Ok(frame)
+}
+
+/// A message for every client, encoded up front with each codec so the same bytes go into
+/// every client's batch rather than being encoded again per connection
+#[derive(Debug)]
+pub struct EncodedBroadcast {
+    message: ServerMessageAllClients,
+    encoded: [Vec<u8>; 2],
+}
+
+impl EncodedBroadcast {
+    pub fn new(message: ServerMessageAllClients) -> Self {
+        let encoded = Codec::ALL.map(|codec| codec.encode(&message).into_bytes());
+        Self { message, encoded }
+    }
+
+    pub fn message(&self) -> &ServerMessageAllClients {
+        &self.message
+    }
+
+    pub fn encoded(&self, codec: Codec) -> &[u8] {
+        &self.encoded[codec.index()]
+    }
+}
+
+/// Encodes a message lazily, at most once for each codec no matter how many
+/// connections it is sent to
+pub struct EncodedMessage<'a, T: Serialize> {
+    value: &'a T,
+    encoded: [Option<Message>; 2],
+}
+
+impl<'a, T: Serialize> EncodedMessage<'a, T> {
+    pub fn new(value: &'a T) -> Self {
+        Self {
+            value,
+            encoded: [None, None],
+        }
+    }
+
+    pub fn get(&mut self, codec: Codec) -> Message {
+        let value = self.value;
+        self.encoded[codec.index()]
+            .get_or_insert_with(|| codec.encode(value))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ae_position::Position;
+    use core_api::ClientMessage;
+
+    #[test]
+    fn negotiate_picks_first_supported_protocol() {
+        assert_eq!(
+            Codec::negotiate("chat, goblin.msgpack, goblin.json"),
+            Some(Codec::MessagePack)
+        );
+        assert_eq!(Codec::negotiate("goblin.json"), Some(Codec::Json));
+        assert_eq!(Codec::negotiate("chat"), None);
+    }
+
+    #[test]
+    fn decodes_both_frame_types() {
+        let json = Message::text(r#"{"type":"keepAlive"}"#);
+        assert!(matches!(
+            Codec::decode::<ClientMessage>(&json),
+            Ok(ClientMessage::KeepAlive)
+        ));
+
+        // What a JavaScript MessagePack encoder produces for `{ type: "keepAlive" }`
+        let keep_alive = std::collections::HashMap::from([("type", "keepAlive")]);
+        let binary = Message::binary(rmp_serde::to_vec_named(&keep_alive).unwrap());
+        assert!(matches!(
+            Codec::decode::<ClientMessage>(&binary),
+            Ok(ClientMessage::KeepAlive)
+        ));
+    }
+
+    #[test]
+    fn message_pack_keeps_field_names() {
+        let camera = ServerMessageSingleClient::CentreCamera(Position { x: 1, y: 2 });
+        let message = Codec::MessagePack.encode(&camera);
+        let value: serde_json::Value = rmp_serde::from_slice(message.as_bytes()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({ "type": "centreCamera", "content": { "x": 1, "y": 2 } })
+        );
+    }
+
+    #[test]
+    fn batches_built_around_encoded_broadcasts_match_a_plain_batch() {
+        let camera = || ServerMessageSingleClient::CentreCamera(Position { x: 1, y: 2 });
+        let log = || ServerMessageAllClients::Log(core_api::LogMessage("hi".to_string()));
+        let broadcasts = [
+            Arc::new(EncodedBroadcast::new(log())),
+            Arc::new(EncodedBroadcast::new(ServerMessageAllClients::MoveCount(300))),
+        ];
+
+        for codec in Codec::ALL {
+            let batch = ServerMessageSingleClient::Batch(core_api::TickBatch {
+                tick: 70000,
+                messages: vec![camera(), camera()],
+                broadcasts: vec![log(), ServerMessageAllClients::MoveCount(300)],
+            });
+            assert_eq!(
+                codec.encode_batch(70000, &[camera(), camera()], &broadcasts),
+                codec.encode(&batch)
+            );
+        }
+    }
+
+    #[test]
+    fn reads_type_of_invalid_message() {
+        let msg = Message::text(r#"{"type":"keypress","content":"sideways"}"#);
+        assert!(Codec::decode::<ClientMessage>(&msg).is_err());
+        assert_eq!(Codec::message_type(&msg), Some("keypress".to_string()));
+
+        assert_eq!(Codec::message_type(&Message::text("not json")), None);
+    }
+
+    #[test]
+    fn clients_cannot_ask_to_spectate() {
+        let msg = Message::text(r#"{"type":"spectate","content":{"mapId":1}}"#);
+        assert!(Codec::decode::<ClientMessage>(&msg).is_err());
+    }
+}
@@ -1,20 +1,118 @@
-use core_api::UserId;
+use core_api::{ClientMessage, UserId};
 use std::{
     collections::HashMap,
     sync::{atomic::AtomicI32, Arc},
+    time::Instant,
 };
-use tokio::sync::{mpsc, RwLock};
-use warp::ws::Message;
+use tokio::sync::RwLock;
+
+use crate::{codec::Codec, outbound::OutboundQueue};
 
 pub static USER_ID_COUNTER: AtomicI32 = AtomicI32::new(1);
 
+/// What a websocket connection was opened for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionRole {
+    Player,
+    /// Watches a map without joining the game
+    Spectator {
+        map_id: i32,
+    },
+}
+
+impl ConnectionRole {
+    /// Whether a message from this connection goes on to the engine. Spectators can watch
+    /// but never play, so only keeping the socket open and leaving get through from them.
+    pub fn allows(&self, message: &ClientMessage) -> bool {
+        match self {
+            ConnectionRole::Player => true,
+            ConnectionRole::Spectator { .. } => matches!(
+                message,
+                ClientMessage::Disconnect | ClientMessage::KeepAlive
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Connection {
+    pub queue: OutboundQueue,
+    /// The wire format this client asked for during the websocket handshake
+    pub codec: Codec,
+    /// Whether the client is playing or only watching
+    pub role: ConnectionRole,
+    /// When any frame (including a pong) was last received from this client
+    pub last_seen: Instant,
+}
+
 #[derive(Debug, Default)]
-pub struct Connections(pub HashMap<i32, mpsc::UnboundedSender<Message>>);
+pub struct Connections(pub HashMap<i32, Connection>);
 
 impl Connections {
-    pub fn new_connection(&mut self, user_id: UserId, sender: mpsc::UnboundedSender<Message>) {
-        self.0.insert(user_id.0, sender);
+    pub fn new_connection(
+        &mut self,
+        user_id: UserId,
+        queue: OutboundQueue,
+        codec: Codec,
+        role: ConnectionRole,
+    ) {
+        self.0.insert(
+            user_id.0,
+            Connection {
+                queue,
+                codec,
+                role,
+                last_seen: Instant::now(),
+            },
+        );
+    }
+
+    /// Records that the client just showed signs of life
+    pub fn touch(&mut self, user_id: UserId) {
+        if let Some(connection) = self.0.get_mut(&user_id.0) {
+            connection.last_seen = Instant::now();
+        }
+    }
+
+    pub fn last_seen(&self, user_id: UserId) -> Option<Instant> {
+        self.0
+            .get(&user_id.0)
+            .map(|connection| connection.last_seen)
     }
 }
 
 pub type ConnectionsLock = Arc<RwLock<Connections>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn touch_updates_last_seen() {
+        let queue = OutboundQueue::new(Codec::Json, 8, Duration::from_secs(10));
+        let mut connections = Connections::default();
+        connections.new_connection(UserId(1), queue, Codec::Json, ConnectionRole::Player);
+
+        let connected_at = connections.last_seen(UserId(1)).unwrap();
+        connections.touch(UserId(1));
+
+        assert!(connections.last_seen(UserId(1)).unwrap() >= connected_at);
+        assert_eq!(connections.last_seen(UserId(2)), None);
+    }
+
+    #[test]
+    fn spectators_can_only_keep_alive_and_leave() {
+        let spectator = ConnectionRole::Spectator { map_id: 1 };
+        let initialize = ClientMessage::Initialize {
+            sprite: core_api::SpriteTexture::PcBoneyBoi,
+            protocol_version: None,
+        };
+
+        assert!(ConnectionRole::Player.allows(&initialize));
+        assert!(!spectator.allows(&initialize));
+        assert!(!spectator.allows(&ClientMessage::Spawn(core_api::SpawnableEnemy::Rat)));
+        assert!(spectator.allows(&ClientMessage::KeepAlive));
+        assert!(spectator.allows(&ClientMessage::Disconnect));
+    }
+}
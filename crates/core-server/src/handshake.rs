@@ -0,0 +1,48 @@
+use core_api::{ClientMessage, PROTOCOL_VERSION};
+
+/// Whether a client speaking `client_version` of the protocol can be served
+pub fn check_protocol_version(client_version: Option<u32>) -> Result<(), String> {
+    match client_version {
+        Some(version) if version == PROTOCOL_VERSION => Ok(()),
+        Some(version) => Err(format!(
+            "Client protocol version {} does not match server protocol version {}, please reload the page",
+            version, PROTOCOL_VERSION
+        )),
+        None => Err(format!(
+            "Client did not send a protocol version, server expects {}, please reload the page",
+            PROTOCOL_VERSION
+        )),
+    }
+}
+
+/// The protocol version sent with a message that starts a game, `None` if the message
+/// is not part of the handshake
+pub fn handshake_version(message: &ClientMessage) -> Option<Option<u32>> {
+    match message {
+        ClientMessage::Register {
+            protocol_version, ..
+        }
+        | ClientMessage::Login {
+            protocol_version, ..
+        }
+        | ClientMessage::Initialize {
+            protocol_version, ..
+        }
+        | ClientMessage::Resume {
+            protocol_version, ..
+        } => Some(*protocol_version),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_matching_version_is_accepted() {
+        assert!(check_protocol_version(Some(PROTOCOL_VERSION)).is_ok());
+        assert!(check_protocol_version(Some(PROTOCOL_VERSION + 1)).is_err());
+        assert!(check_protocol_version(None).is_err());
+    }
+}
@@ -1,4 +1,11 @@
+pub mod admin;
+pub mod codec;
 pub mod connections;
 pub mod disconnect;
+pub mod handshake;
 pub mod message;
+pub mod metrics;
 pub mod new_connection;
+pub mod outbound;
+pub mod settings;
+pub mod shutdown;
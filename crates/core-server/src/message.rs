@@ -3,26 +3,53 @@ use log::trace;
 use tokio::sync::mpsc::UnboundedSender;
 use warp::ws::Message;
 
+use crate::{
+    codec::{Codec, DecodeError},
+    connections::ConnectionRole,
+    handshake::{check_protocol_version, handshake_version},
+    metrics::MESSAGES_IN,
+};
+
 // use super::connections::ConnectionsLock;
 
+/// What happened to a message received from the client
+pub enum MessageOutcome {
+    /// Passed along to the game engine
+    Forwarded,
+    /// A handshake message with a supported protocol version, also passed along
+    HandshakeAccepted,
+    /// A handshake message with an unsupported protocol version, not passed along
+    HandshakeRejected(String),
+    /// Something a spectator isn't allowed to do, not passed along
+    Ignored,
+}
+
 pub async fn handle_message(
     id: UserId,
-    msg: Message,
+    msg: &Message,
+    role: ConnectionRole,
     // connections: &ConnectionsLock,
     sender: &UnboundedSender<(UserId, ClientMessage)>,
-) {
-    // Skip any non-Text messages...
-    let msg = if let Ok(s) = msg.to_str() {
-        s
-    } else {
-        return;
-    };
-
-    trace!("{}", msg);
+) -> Result<MessageOutcome, DecodeError> {
+    // Text frames are JSON and binary frames are MessagePack
+    let request = Codec::decode::<ClientMessage>(msg)?;
 
-    let request = serde_json::from_str::<ClientMessage>(msg);
+    // Logged after decoding rather than as the raw frame so passwords are left out
+    trace!("{:?}", request);
+    MESSAGES_IN.increment(request.kind());
 
-    if let Ok(ref request) = request {
-        sender.send((id, request.clone())).ok();
+    // Dropped here so the engine never sees a spectator try to play, even for a tick
+    if !role.allows(&request) {
+        return Ok(MessageOutcome::Ignored);
     }
+
+    let outcome = match handshake_version(&request).map(check_protocol_version) {
+        Some(Err(reason)) => return Ok(MessageOutcome::HandshakeRejected(reason)),
+        Some(Ok(())) => MessageOutcome::HandshakeAccepted,
+        None => MessageOutcome::Forwarded,
+    };
+
+    sender.send((id, request)).ok();
+
+    Ok(outcome)
 }
@@ -0,0 +1,245 @@
+use core_api::{EngineMetrics, SharedMetrics};
+use std::{
+    fmt::Write,
+    sync::{atomic::Ordering, Arc, Mutex},
+};
+use warp::{
+    http::header::CONTENT_TYPE,
+    reply::{self, Response},
+    Filter, Rejection, Reply,
+};
+
+use crate::{connections::ConnectionsLock, outbound::DROPPED_MESSAGES};
+
+/// Messages received from clients, by `type`
+pub static MESSAGES_IN: Counter = Counter::new();
+
+/// Messages written to clients, by `type`. A message sent to every client counts once
+/// for each of them.
+pub static MESSAGES_OUT: Counter = Counter::new();
+
+/// A count for each of a small, fixed set of labels
+#[derive(Debug, Default)]
+pub struct Counter(Mutex<Vec<(&'static str, u64)>>);
+
+impl Counter {
+    pub const fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    pub fn add(&self, label: &'static str, amount: u64) {
+        let mut counts = self.0.lock().unwrap();
+        match counts.iter_mut().find(|(existing, _)| *existing == label) {
+            Some((_, count)) => *count += amount,
+            None => counts.push((label, amount)),
+        }
+    }
+
+    pub fn increment(&self, label: &'static str) {
+        self.add(label, 1);
+    }
+
+    /// Every label counted so far in alphabetical order
+    pub fn counts(&self) -> Vec<(&'static str, u64)> {
+        let mut counts = self.0.lock().unwrap().clone();
+        counts.sort_unstable();
+        counts
+    }
+}
+
+/// Everything reported by `/api/metrics`, gathered at the time of the request
+pub struct Snapshot {
+    pub connected_sockets: usize,
+    pub messages_in: Vec<(&'static str, u64)>,
+    pub messages_out: Vec<(&'static str, u64)>,
+    pub dropped_messages: u64,
+    pub pending_database_requests: i64,
+    pub engine: EngineMetrics,
+}
+
+/// Writes one metric in the Prometheus text format, each sample is what follows the
+/// metric's name (any labels, or `_sum` and the like) and its value
+fn write_metric(
+    text: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: impl IntoIterator<Item = (String, String)>,
+) {
+    writeln!(text, "# HELP {} {}", name, help).unwrap();
+    writeln!(text, "# TYPE {} {}", name, kind).unwrap();
+    for (suffix, value) in samples {
+        writeln!(text, "{}{} {}", name, suffix, value).unwrap();
+    }
+}
+
+fn unlabelled(value: impl ToString) -> [(String, String); 1] {
+    [(String::new(), value.to_string())]
+}
+
+fn labelled<'a, V: ToString + 'a>(
+    key: &'a str,
+    samples: impl IntoIterator<Item = (impl ToString, V)> + 'a,
+) -> impl Iterator<Item = (String, String)> + 'a {
+    samples.into_iter().map(move |(label, value)| {
+        (
+            format!("{{{}=\"{}\"}}", key, label.to_string()),
+            value.to_string(),
+        )
+    })
+}
+
+impl Snapshot {
+    pub async fn take(connections: &ConnectionsLock, metrics: &SharedMetrics) -> Self {
+        Self {
+            connected_sockets: connections.read().await.0.len(),
+            messages_in: MESSAGES_IN.counts(),
+            messages_out: MESSAGES_OUT.counts(),
+            dropped_messages: DROPPED_MESSAGES.load(Ordering::Relaxed),
+            pending_database_requests: metrics.pending_database_requests.load(Ordering::Relaxed),
+            // The engine only writes with `try_write`, so holding this briefly never stalls it
+            engine: metrics
+                .engine
+                .read()
+                .map(|engine| engine.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The snapshot in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+
+        write_metric(
+            &mut text,
+            "gamejam_connected_sockets",
+            "gauge",
+            "Websocket connections that are open, players and spectators",
+            unlabelled(self.connected_sockets),
+        );
+        write_metric(
+            &mut text,
+            "gamejam_messages_in_total",
+            "counter",
+            "Messages received from clients by type",
+            labelled("type", self.messages_in.iter().copied()),
+        );
+        write_metric(
+            &mut text,
+            "gamejam_messages_out_total",
+            "counter",
+            "Messages sent to clients by type",
+            labelled("type", self.messages_out.iter().copied()),
+        );
+        write_metric(
+            &mut text,
+            "gamejam_dropped_messages_total",
+            "counter",
+            "Messages thrown away because a client could not keep up",
+            unlabelled(self.dropped_messages),
+        );
+        write_metric(
+            &mut text,
+            "gamejam_pending_database_requests",
+            "gauge",
+            "Requests from the game engine the database has not finished",
+            unlabelled(self.pending_database_requests),
+        );
+        write_metric(
+            &mut text,
+            "gamejam_tick_duration_seconds",
+            "summary",
+            "Time taken by game engine ticks",
+            [
+                (
+                    "_sum".to_string(),
+                    self.engine.tick_seconds_total.to_string(),
+                ),
+                ("_count".to_string(), self.engine.ticks.to_string()),
+            ],
+        );
+        write_metric(
+            &mut text,
+            "gamejam_tick_duration_max_seconds",
+            "gauge",
+            "The slowest game engine tick in the last second or so",
+            unlabelled(self.engine.tick_seconds_max),
+        );
+        write_metric(
+            &mut text,
+            "gamejam_entities",
+            "gauge",
+            "Entities with a position by map",
+            labelled("map_id", &self.engine.entities_per_map),
+        );
+        write_metric(
+            &mut text,
+            "gamejam_enemies",
+            "gauge",
+            "Living enemies by type",
+            labelled("type", &self.engine.enemies_by_type),
+        );
+
+        text
+    }
+}
+
+/// GET `api/metrics`, the server's health for Prometheus or anything that reads its format
+pub fn metrics_route(
+    metrics: Arc<SharedMetrics>,
+    connections: ConnectionsLock,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    warp::path!("api" / "metrics")
+        .and(warp::get())
+        .then(move || {
+            let metrics = metrics.clone();
+            let connections = connections.clone();
+            async move {
+                let text = Snapshot::take(&connections, &metrics).await.render();
+                reply::with_header(text, CONTENT_TYPE, "text/plain; version=0.0.4").into_response()
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_adds_up_each_label() {
+        let counter = Counter::new();
+        counter.increment("keypress");
+        counter.increment("tileClick");
+        counter.add("keypress", 2);
+
+        assert_eq!(counter.counts(), [("keypress", 3), ("tileClick", 1)]);
+    }
+
+    #[test]
+    fn snapshot_renders_labelled_samples() {
+        let mut engine = EngineMetrics {
+            ticks: 4,
+            tick_seconds_total: 0.5,
+            ..Default::default()
+        };
+        engine.entities_per_map.insert(2, 7);
+        engine.enemies_by_type.insert("Rat".to_string(), 3);
+
+        let text = Snapshot {
+            connected_sockets: 2,
+            messages_in: vec![("keypress", 5)],
+            messages_out: vec![],
+            dropped_messages: 1,
+            pending_database_requests: 0,
+            engine,
+        }
+        .render();
+
+        assert!(text.contains("gamejam_connected_sockets 2\n"));
+        assert!(text.contains("gamejam_messages_in_total{type=\"keypress\"} 5\n"));
+        assert!(text.contains("# TYPE gamejam_messages_out_total counter\n"));
+        assert!(text.contains("gamejam_tick_duration_seconds_count 4\n"));
+        assert!(text.contains("gamejam_entities{map_id=\"2\"} 7\n"));
+        assert!(text.contains("gamejam_enemies{type=\"Rat\"} 3\n"));
+    }
+}
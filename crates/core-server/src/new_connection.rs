@@ -1,65 +1,230 @@
-use core_api::{ClientMessage, UserId};
-use futures_util::{SinkExt, StreamExt, TryFutureExt};
-use log::{error, info, trace};
-use std::sync::atomic::Ordering;
-use tokio::sync::mpsc::{self, UnboundedSender};
-use tokio_stream::wrappers::UnboundedReceiverStream;
-use warp::ws::WebSocket;
+use core_api::{ClientMessage, ServerMessageSingleClient, UserId, PROTOCOL_VERSION};
+use futures_util::StreamExt;
+use log::{error, info, warn};
+use std::{sync::atomic::Ordering, time::Duration};
+use tokio::{
+    sync::mpsc::UnboundedSender,
+    time::{interval_at, Instant, MissedTickBehavior},
+};
+use warp::{
+    http::HeaderValue,
+    reply::Response,
+    ws::{Message, WebSocket, Ws},
+    Reply,
+};
 
-use crate::{connections::USER_ID_COUNTER, disconnect::handle_disconnect, message::handle_message};
+use crate::{
+    codec::{Codec, DecodeError},
+    connections::{ConnectionRole, USER_ID_COUNTER},
+    disconnect::handle_disconnect,
+    message::{handle_message, MessageOutcome},
+    metrics::MESSAGES_OUT,
+    outbound::{Delivery, OutboundQueue},
+    settings::ConnectionSettings,
+};
 
 use super::connections::ConnectionsLock;
 
+/// Accepts a websocket upgrade, speaking whichever wire format the client offered
+pub fn upgrade_connection(
+    ws: Ws,
+    offered_protocols: Option<String>,
+    role: ConnectionRole,
+    settings: ConnectionSettings,
+    connections: ConnectionsLock,
+    sender: UnboundedSender<(UserId, ClientMessage)>,
+) -> Response {
+    let negotiated_codec = offered_protocols.as_deref().and_then(Codec::negotiate);
+    let codec = negotiated_codec.unwrap_or_default();
+
+    // This will call our function if the handshake succeeds.
+    let mut response = ws
+        .on_upgrade(move |socket| {
+            handle_new_connection(socket, codec, role, settings, connections, sender)
+        })
+        .into_response();
+
+    // Browsers drop the connection unless the chosen protocol is echoed back
+    if let Some(codec) = negotiated_codec {
+        response.headers_mut().insert(
+            "sec-websocket-protocol",
+            HeaderValue::from_static(codec.protocol()),
+        );
+    }
+
+    response
+}
+
+/// Sends the client an answer from the server itself rather than from the game engine
+fn reply(queue: &OutboundQueue, codec: Codec, message: ServerMessageSingleClient) {
+    MESSAGES_OUT.increment(message.kind());
+    queue.push(codec.encode(&message), Delivery::Reliable);
+}
+
 pub async fn handle_new_connection(
     ws: WebSocket,
+    codec: Codec,
+    role: ConnectionRole,
+    settings: ConnectionSettings,
     connections: ConnectionsLock,
     sender: UnboundedSender<(UserId, ClientMessage)>,
 ) {
     let new_id = UserId(USER_ID_COUNTER.fetch_add(1, Ordering::Relaxed));
 
-    info!("New connection: {}", new_id.0);
+    info!("New connection: {} ({:?}, {:?})", new_id.0, codec, role);
 
     // Split the socket into a sender and receive of messages.
-    let (mut user_ws_tx, mut user_ws_rx) = ws.split();
-
-    // Use an unbounded channel to handle buffering and flushing of messages to the websocket
-    let (tx, rx) = mpsc::unbounded_channel();
-    let mut rx = UnboundedReceiverStream::new(rx);
-
-    tokio::task::spawn(async move {
-        while let Some(message) = rx.next().await {
-            trace!("Received client message {:?}", message);
-            user_ws_tx
-                .send(message)
-                .unwrap_or_else(|e| {
-                    error!("websocket send error: {}", e);
-                })
-                .await;
-        }
-    });
+    let (user_ws_tx, mut user_ws_rx) = ws.split();
+
+    // A bounded queue handles buffering and flushing of messages to the websocket
+    let queue = OutboundQueue::new(codec, settings.outbound_queue_capacity, settings.max_lag);
+    tokio::task::spawn(queue.clone().forward(user_ws_tx));
 
     // Add user to the list of active connections
-    connections.write().await.new_connection(new_id, tx);
+    connections
+        .write()
+        .await
+        .new_connection(new_id, queue.clone(), codec, role);
+
+    // Spectators never send `Initialize`, so tell the engine which map they want to watch
+    if let ConnectionRole::Spectator { map_id } = role {
+        sender
+            .send((new_id, ClientMessage::Spectate { map_id }))
+            .ok();
+    }
+
+    let mut protocol_violations: u32 = 0;
+
+    // Half open sockets never deliver a close, so ping the client and drop it once it goes quiet
+    let mut ping_interval = interval_at(
+        Instant::now() + settings.ping_interval,
+        settings.ping_interval,
+    );
+    ping_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
     // Every time the user sends a message, broadcast it to all other users
-    'listening: while let Some(result) = user_ws_rx.next().await {
+    'listening: loop {
+        let result = tokio::select! {
+            result = user_ws_rx.next() => result,
+            _ = queue.hung_up() => {
+                info!("Server hung up on connection (uid={})", new_id.0);
+                break 'listening;
+            }
+            _ = ping_interval.tick() => {
+                let idle = connections
+                    .read()
+                    .await
+                    .last_seen(new_id)
+                    .map_or(Duration::MAX, |last_seen| last_seen.elapsed());
+
+                if idle > settings.idle_timeout {
+                    info!(
+                        "Closing idle connection (uid={}): nothing received for {:?}",
+                        new_id.0, idle
+                    );
+                    // 1001 is the close code for an endpoint going away
+                    queue.push(Message::close_with(1001u16, "Idle timeout"), Delivery::Reliable);
+                    break 'listening;
+                }
+
+                queue.push(Message::ping(Vec::new()), Delivery::Reliable);
+                continue 'listening;
+            }
+        };
+
         let msg = match result {
-            Ok(msg) => msg,
-            Err(e) => {
+            Some(Ok(msg)) => msg,
+            Some(Err(e)) => {
                 error!("websocket error(uid={}): {}", new_id.0, e);
                 break 'listening;
             }
+            None => break 'listening,
         };
-        handle_message(
-            new_id, msg, // &connections,
+
+        // Any frame counts, pongs included
+        connections.write().await.touch(new_id);
+
+        let result = handle_message(
+            new_id, &msg, role, // &connections,
             // &db,
             // &world,
             &sender,
         )
         .await;
+
+        match result {
+            Ok(MessageOutcome::Forwarded)
+            | Ok(MessageOutcome::Ignored)
+            | Err(DecodeError::NotAMessage) => {}
+            Ok(MessageOutcome::HandshakeAccepted) => {
+                reply(
+                    &queue,
+                    codec,
+                    ServerMessageSingleClient::HandshakeAccepted {
+                        server_version: PROTOCOL_VERSION,
+                    },
+                );
+            }
+            Ok(MessageOutcome::HandshakeRejected(reason)) => {
+                info!("Rejecting handshake (uid={}): {}", new_id.0, reason);
+                reply(
+                    &queue,
+                    codec,
+                    ServerMessageSingleClient::HandshakeRejected {
+                        server_version: PROTOCOL_VERSION,
+                        reason,
+                    },
+                );
+                // A client on the wrong version can't play, so don't leave it half working
+                queue.push(
+                    Message::close_with(1002u16, "Unsupported protocol version"),
+                    Delivery::Reliable,
+                );
+                break 'listening;
+            }
+            Err(e) => {
+                protocol_violations += 1;
+                let message_type = Codec::message_type(&msg);
+
+                warn!(
+                    "Protocol violation {}/{} (uid={}, type={:?}): {}",
+                    protocol_violations,
+                    settings.max_protocol_violations,
+                    new_id.0,
+                    message_type,
+                    e
+                );
+
+                // Let the client know so a mismatched client and server are noticed
+                reply(
+                    &queue,
+                    codec,
+                    ServerMessageSingleClient::ProtocolError {
+                        error: e.to_string(),
+                        message_type,
+                    },
+                );
+
+                if protocol_violations > settings.max_protocol_violations {
+                    warn!(
+                        "Closing connection (uid={}): too many protocol violations",
+                        new_id.0
+                    );
+                    // 1008 is the close code for a policy violation
+                    queue.push(
+                        Message::close_with(1008u16, "Too many protocol violations"),
+                        Delivery::Reliable,
+                    );
+                    break 'listening;
+                }
+            }
+        }
     }
 
     // user_ws_rx stream will keep processing as long as the user stays
     // connected. Once they disconnect, then...
     handle_disconnect(new_id, &connections, &sender).await;
+
+    // Lets the writer finish sending anything still queued, like a close frame, then stop
+    queue.close();
 }
@@ -0,0 +1,545 @@
+use core_api::{ServerMessageAllClients, ServerMessageSingleClient};
+use futures_util::{Sink, SinkExt};
+use log::{error, trace, warn};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+use warp::ws::Message;
+
+use crate::{
+    codec::{Codec, EncodedBroadcast},
+    metrics::MESSAGES_OUT,
+};
+
+/// Messages thrown away because a client could not keep up
+pub static DROPPED_MESSAGES: AtomicU64 = AtomicU64::new(0);
+
+/// Messages where only the newest one matters, a newer message with the same key
+/// replaces any older one that is still waiting to be sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoalesceKey {
+    EntityPosition(u32),
+    FullGameMap,
+    CentreCamera,
+    TileHover,
+    MoveCount,
+    Debug,
+    ShutdownNotice,
+}
+
+/// What may happen to a message when a client's queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Always sent, the client would get out of sync without it
+    Reliable,
+    /// Cosmetic, fine to lose under load
+    Droppable,
+    /// Superseded by the next message with the same key
+    Latest(CoalesceKey),
+}
+
+/// How a server message should be treated on the way out
+pub trait Outbound {
+    fn delivery(&self) -> Delivery;
+}
+
+impl Outbound for ServerMessageSingleClient {
+    fn delivery(&self) -> Delivery {
+        match self {
+            ServerMessageSingleClient::EntityPositionChange(update) => {
+                Delivery::Latest(CoalesceKey::EntityPosition(update.entity.idx))
+            }
+            ServerMessageSingleClient::UpdateFullGameMap { .. } => {
+                Delivery::Latest(CoalesceKey::FullGameMap)
+            }
+            ServerMessageSingleClient::CentreCamera(_) => {
+                Delivery::Latest(CoalesceKey::CentreCamera)
+            }
+            ServerMessageSingleClient::TileHover(_) => Delivery::Latest(CoalesceKey::TileHover),
+            ServerMessageSingleClient::PlaySound(_)
+            | ServerMessageSingleClient::ShowAnimation { .. }
+            | ServerMessageSingleClient::ShowDamage { .. } => Delivery::Droppable,
+            ServerMessageSingleClient::AddSprite(_)
+            | ServerMessageSingleClient::RemoveSprite(_)
+            | ServerMessageSingleClient::ShowDialogue { .. }
+            | ServerMessageSingleClient::SessionToken(_)
+            | ServerMessageSingleClient::ResumeFailed
+            | ServerMessageSingleClient::LoggedIn { .. }
+            | ServerMessageSingleClient::LoginFailed { .. }
+            | ServerMessageSingleClient::HandshakeAccepted { .. }
+            | ServerMessageSingleClient::HandshakeRejected { .. }
+            | ServerMessageSingleClient::ProtocolError { .. }
+            | ServerMessageSingleClient::Batch(_) => Delivery::Reliable,
+        }
+    }
+}
+
+impl Outbound for ServerMessageAllClients {
+    fn delivery(&self) -> Delivery {
+        match self {
+            ServerMessageAllClients::MoveCount(_) => Delivery::Latest(CoalesceKey::MoveCount),
+            ServerMessageAllClients::Debug(_) => Delivery::Latest(CoalesceKey::Debug),
+            ServerMessageAllClients::ShutdownNotice { .. } => {
+                Delivery::Latest(CoalesceKey::ShutdownNotice)
+            }
+            ServerMessageAllClients::TileClick(_)
+            | ServerMessageAllClients::Damage(_)
+            | ServerMessageAllClients::Death(_) => Delivery::Droppable,
+            ServerMessageAllClients::Log(_) => Delivery::Reliable,
+        }
+    }
+}
+
+/// One tick's worth of messages for a client, written as a single batch frame
+struct Batch {
+    tick: u32,
+    messages: Vec<ServerMessageSingleClient>,
+    broadcasts: Vec<Arc<EncodedBroadcast>>,
+}
+
+enum Next {
+    Frame(Message),
+    Batch(Batch),
+    Wait,
+    Done,
+}
+
+enum Payload {
+    /// Already encoded, sent as its own frame
+    Frame(Message),
+    /// Sent together with the messages around it from the same tick
+    Batched {
+        tick: u32,
+        message: ServerMessageSingleClient,
+    },
+    /// Sent to every client, batched the same way. Every client shares the one encoding.
+    Broadcast {
+        tick: u32,
+        message: Arc<EncodedBroadcast>,
+    },
+}
+
+impl Payload {
+    /// The engine tick the message came from, if it is sent in a batch
+    fn tick(&self) -> Option<u32> {
+        match self {
+            Payload::Frame(_) => None,
+            Payload::Batched { tick, .. } | Payload::Broadcast { tick, .. } => Some(*tick),
+        }
+    }
+}
+
+struct Queued {
+    payload: Payload,
+    delivery: Delivery,
+}
+
+#[derive(Default)]
+struct State {
+    queue: VecDeque<Queued>,
+    /// When the queue last filled up, cleared once it drains below capacity again
+    full_since: Option<Instant>,
+    hung_up: bool,
+    closed: bool,
+    /// Everything has been written to the websocket and `forward` has returned
+    finished: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    codec: Codec,
+    capacity: usize,
+    max_lag: Duration,
+    /// Wakes the task writing to the websocket
+    message_ready: Notify,
+    /// Wakes the connection so it can stop listening to a client the server hung up on
+    hang_up: Notify,
+}
+
+/// A bounded queue of messages waiting to be written to one websocket.
+///
+/// Once `capacity` messages are waiting the oldest droppable message makes room for a new
+/// one. Reliable and coalesced messages are still accepted past that point, but a client
+/// whose queue stays full for longer than `max_lag`, or grows to twice its capacity, is
+/// considered too slow and hung up on.
+///
+/// Messages from the same engine tick are kept apart until they are written, so they can
+/// still be coalesced or dropped individually, and are then sent together as one batch.
+#[derive(Clone)]
+pub struct OutboundQueue {
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for OutboundQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutboundQueue")
+            .field("capacity", &self.shared.capacity)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl OutboundQueue {
+    pub fn new(codec: Codec, capacity: usize, max_lag: Duration) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State::default()),
+                codec,
+                capacity,
+                max_lag,
+                message_ready: Notify::new(),
+                hang_up: Notify::new(),
+            }),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().unwrap().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&self, message: Message, delivery: Delivery) {
+        let mut state = self.shared.state.lock().unwrap();
+        self.enqueue(&mut state, Payload::Frame(message), delivery);
+        drop(state);
+        self.shared.message_ready.notify_one();
+    }
+
+    /// Queues everything the engine sent this client during one tick
+    pub fn push_batch(&self, tick: u32, messages: Vec<ServerMessageSingleClient>) {
+        let mut state = self.shared.state.lock().unwrap();
+        for message in messages {
+            let delivery = message.delivery();
+            let payload = Payload::Batched { tick, message };
+            self.enqueue(&mut state, payload, delivery);
+        }
+        drop(state);
+        self.shared.message_ready.notify_one();
+    }
+
+    /// Queues a message the engine sent to every client, to go out with this client's batch
+    /// for the same tick
+    pub fn push_broadcast(&self, tick: u32, message: Arc<EncodedBroadcast>) {
+        let mut state = self.shared.state.lock().unwrap();
+        let delivery = message.message().delivery();
+        self.enqueue(&mut state, Payload::Broadcast { tick, message }, delivery);
+        drop(state);
+        self.shared.message_ready.notify_one();
+    }
+
+    fn enqueue(&self, state: &mut State, payload: Payload, delivery: Delivery) {
+        if state.closed {
+            return;
+        }
+
+        if let Delivery::Latest(key) = delivery {
+            // Keep the newest copy at the back so it still arrives after anything sent before it
+            if let Some(index) = state
+                .queue
+                .iter()
+                .position(|queued| queued.delivery == Delivery::Latest(key))
+            {
+                state.queue.remove(index);
+            }
+        }
+
+        if state.queue.len() >= self.shared.capacity {
+            let full_since = *state.full_since.get_or_insert_with(Instant::now);
+
+            if let Some(index) = state
+                .queue
+                .iter()
+                .position(|queued| queued.delivery == Delivery::Droppable)
+            {
+                state.queue.remove(index);
+                DROPPED_MESSAGES.fetch_add(1, Ordering::Relaxed);
+            } else if delivery == Delivery::Droppable {
+                DROPPED_MESSAGES.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+
+            if full_since.elapsed() > self.shared.max_lag
+                || state.queue.len() >= self.shared.capacity * 2
+            {
+                self.mark_lagging(state);
+                return;
+            }
+        }
+
+        state.queue.push_back(Queued { payload, delivery });
+    }
+
+    /// Stops accepting messages, anything already queued is still sent
+    pub fn close(&self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.message_ready.notify_one();
+    }
+
+    /// Drops anything still waiting and closes the websocket with `code`
+    pub fn hang_up(&self, code: u16, reason: &'static str) {
+        let mut state = self.shared.state.lock().unwrap();
+        self.hang_up_locked(&mut state, code, reason);
+    }
+
+    /// Whether the last message, usually a close frame, has been written to the websocket
+    pub fn is_finished(&self) -> bool {
+        self.shared.state.lock().unwrap().finished
+    }
+
+    /// Resolves once the server has hung up on the client, because it fell too far behind
+    /// or was kicked
+    pub async fn hung_up(&self) {
+        self.shared.hang_up.notified().await;
+    }
+
+    fn mark_lagging(&self, state: &mut State) {
+        if state.hung_up {
+            return;
+        }
+        warn!(
+            "Client fell behind with {} messages queued, closing its connection",
+            state.queue.len()
+        );
+
+        DROPPED_MESSAGES.fetch_add(state.queue.len() as u64, Ordering::Relaxed);
+        // 1008 is the close code for a policy violation
+        self.hang_up_locked(state, 1008, "Client is too slow");
+    }
+
+    fn hang_up_locked(&self, state: &mut State, code: u16, reason: &'static str) {
+        if state.hung_up {
+            return;
+        }
+
+        state.queue.clear();
+        state.queue.push_back(Queued {
+            payload: Payload::Frame(Message::close_with(code, reason)),
+            delivery: Delivery::Reliable,
+        });
+        state.hung_up = true;
+        state.closed = true;
+
+        self.shared.message_ready.notify_one();
+        self.shared.hang_up.notify_one();
+    }
+
+    fn pop(&self) -> Next {
+        let mut state = self.shared.state.lock().unwrap();
+        let next = match state.queue.front().map(|queued| queued.payload.tick()) {
+            Some(None) => match state.queue.pop_front() {
+                Some(Queued {
+                    payload: Payload::Frame(message),
+                    ..
+                }) => Next::Frame(message),
+                _ => unreachable!("Only frames have no tick"),
+            },
+            Some(Some(tick)) => {
+                let mut batch = Batch {
+                    tick,
+                    messages: Vec::new(),
+                    broadcasts: Vec::new(),
+                };
+                while state.queue.front().and_then(|queued| queued.payload.tick()) == Some(tick) {
+                    match state.queue.pop_front().map(|queued| queued.payload) {
+                        Some(Payload::Batched { message, .. }) => batch.messages.push(message),
+                        Some(Payload::Broadcast { message, .. }) => batch.broadcasts.push(message),
+                        _ => unreachable!("Only frames have no tick"),
+                    }
+                }
+                Next::Batch(batch)
+            }
+            None if state.closed => return Next::Done,
+            None => return Next::Wait,
+        };
+
+        if state.queue.len() < self.shared.capacity {
+            state.full_since = None;
+        }
+        next
+    }
+
+    /// Writes queued messages to the websocket until the queue is closed and drained
+    pub async fn forward<S>(self, mut sink: S)
+    where
+        S: Sink<Message> + Unpin,
+        S::Error: fmt::Display,
+    {
+        loop {
+            match self.pop() {
+                Next::Frame(message) => {
+                    trace!("Sending message {:?}", message);
+                    if let Err(e) = sink.send(message).await {
+                        error!("websocket send error: {}", e);
+                    }
+                }
+                Next::Batch(batch) => {
+                    trace!(
+                        "Sending batch for tick {}: {:?} {:?}",
+                        batch.tick,
+                        batch.messages,
+                        batch.broadcasts
+                    );
+                    for message in &batch.messages {
+                        MESSAGES_OUT.increment(message.kind());
+                    }
+                    for broadcast in &batch.broadcasts {
+                        MESSAGES_OUT.increment(broadcast.message().kind());
+                    }
+                    let message = self.shared.codec.encode_batch(
+                        batch.tick,
+                        &batch.messages,
+                        &batch.broadcasts,
+                    );
+                    if let Err(e) = sink.send(message).await {
+                        error!("websocket send error: {}", e);
+                    }
+                }
+                Next::Wait => self.shared.message_ready.notified().await,
+                Next::Done => break,
+            }
+        }
+
+        self.shared.state.lock().unwrap().finished = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ae_position::Position;
+    use core_api::{EntityIndex, LogMessage, SpriteTexture, SpriteUpdate};
+
+    fn drain(queue: &OutboundQueue) -> Vec<String> {
+        let mut messages = Vec::new();
+        while let Next::Frame(message) = queue.pop() {
+            messages.push(message.to_str().unwrap_or_default().to_string());
+        }
+        messages
+    }
+
+    fn queue(capacity: usize) -> OutboundQueue {
+        OutboundQueue::new(Codec::Json, capacity, Duration::from_secs(10))
+    }
+
+    #[test]
+    fn newest_coalesced_message_moves_to_the_back() {
+        let queue = queue(8);
+        let position = Delivery::Latest(CoalesceKey::EntityPosition(1));
+
+        queue.push(Message::text("move 1"), position);
+        queue.push(Message::text("add"), Delivery::Reliable);
+        queue.push(Message::text("move 2"), position);
+
+        assert_eq!(drain(&queue), ["add", "move 2"]);
+    }
+
+    #[test]
+    fn full_queue_drops_cosmetic_messages_first() {
+        let queue = queue(2);
+        let dropped = DROPPED_MESSAGES.load(Ordering::Relaxed);
+
+        queue.push(Message::text("sound"), Delivery::Droppable);
+        queue.push(Message::text("add"), Delivery::Reliable);
+        queue.push(Message::text("remove"), Delivery::Reliable);
+        queue.push(Message::text("damage"), Delivery::Droppable);
+
+        assert_eq!(drain(&queue), ["add", "remove"]);
+        assert!(DROPPED_MESSAGES.load(Ordering::Relaxed) >= dropped + 2);
+    }
+
+    #[test]
+    fn client_that_never_catches_up_is_closed() {
+        let queue = queue(2);
+
+        for _ in 0..4 {
+            queue.push(Message::text("add"), Delivery::Reliable);
+        }
+        queue.push(Message::text("ignored"), Delivery::Reliable);
+
+        assert!(matches!(queue.pop(), Next::Frame(message) if message.is_close()));
+        assert!(matches!(queue.pop(), Next::Done));
+    }
+
+    fn position(idx: u32, x: i32) -> ServerMessageSingleClient {
+        ServerMessageSingleClient::EntityPositionChange(SpriteUpdate {
+            entity: EntityIndex { idx },
+            pos: Position { x, y: 0 },
+            sprite: SpriteTexture::Empty,
+        })
+    }
+
+    #[test]
+    fn messages_from_one_tick_are_sent_together() {
+        let queue = queue(8);
+
+        queue.push_batch(1, vec![position(1, 1), position(2, 1)]);
+        queue.push_batch(2, vec![position(1, 2), ServerMessageSingleClient::ResumeFailed]);
+
+        // Entity 1 moved again in tick 2, so only its newest position is still waiting
+        let Next::Batch(first) = queue.pop() else {
+            panic!("expected a batch");
+        };
+        assert_eq!(first.tick, 1);
+        assert_eq!(first.messages.len(), 1);
+
+        let Next::Batch(second) = queue.pop() else {
+            panic!("expected a batch");
+        };
+        assert_eq!(second.tick, 2);
+        assert!(matches!(
+            second.messages[..],
+            [
+                ServerMessageSingleClient::EntityPositionChange(SpriteUpdate {
+                    pos: Position { x: 2, .. },
+                    ..
+                }),
+                ServerMessageSingleClient::ResumeFailed
+            ]
+        ));
+    }
+
+    #[test]
+    fn broadcasts_join_the_batch_for_their_tick() {
+        let queue = queue(8);
+
+        let broadcast = |message| Arc::new(EncodedBroadcast::new(message));
+
+        queue.push_batch(3, vec![position(1, 3)]);
+        queue.push_broadcast(
+            3,
+            broadcast(ServerMessageAllClients::Log(LogMessage("hi".to_string()))),
+        );
+        queue.push_broadcast(4, broadcast(ServerMessageAllClients::MoveCount(6)));
+
+        let Next::Batch(batch) = queue.pop() else {
+            panic!("expected a batch");
+        };
+        assert_eq!(batch.tick, 3);
+        assert_eq!(batch.messages.len(), 1);
+        assert_eq!(batch.broadcasts.len(), 1);
+        assert!(matches!(
+            batch.broadcasts[0].message(),
+            ServerMessageAllClients::Log(_)
+        ));
+
+        let Next::Batch(batch) = queue.pop() else {
+            panic!("expected a batch");
+        };
+        assert_eq!(batch.tick, 4);
+        assert_eq!(batch.broadcasts.len(), 1);
+        assert!(matches!(
+            batch.broadcasts[0].message(),
+            ServerMessageAllClients::MoveCount(6)
+        ));
+    }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Deserializer};
+use std::time::Duration;
+
+/// Limits applied to every websocket connection, read from the `connection` section of the
+/// server config where durations are given in whole seconds
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionSettings {
+    /// Malformed messages a client may send before its connection is closed
+    pub max_protocol_violations: u32,
+    /// How often the server sends a websocket ping to each client
+    #[serde(rename = "ping_interval_secs", deserialize_with = "duration_from_secs")]
+    pub ping_interval: Duration,
+    /// A client that sends nothing at all (not even a pong) for this long is disconnected
+    #[serde(rename = "idle_timeout_secs", deserialize_with = "duration_from_secs")]
+    pub idle_timeout: Duration,
+    /// Messages that may wait to be written to one client before cosmetic ones are dropped
+    pub outbound_queue_capacity: usize,
+    /// A client whose queue stays full for this long is disconnected
+    #[serde(rename = "max_lag_secs", deserialize_with = "duration_from_secs")]
+    pub max_lag: Duration,
+}
+
+fn duration_from_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_secs)
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        Self {
+            max_protocol_violations: 10,
+            ping_interval: Duration::from_secs(15),
+            // Comfortably more than the client's 30 second keep alive
+            idle_timeout: Duration::from_secs(45),
+            outbound_queue_capacity: 512,
+            max_lag: Duration::from_secs(10),
+        }
+    }
+}
+
+impl ConnectionSettings {
+    /// Catches settings that would break every connection, such as a ping every 0 seconds
+    pub fn validate(&self) -> Result<(), String> {
+        for (duration, setting) in [
+            (self.ping_interval, "ping_interval_secs"),
+            (self.idle_timeout, "idle_timeout_secs"),
+        ] {
+            if duration.is_zero() {
+                return Err(format!("connection.{} must be at least 1", setting));
+            }
+        }
+
+        // Every message would overflow an empty queue
+        if self.outbound_queue_capacity == 0 {
+            return Err("connection.outbound_queue_capacity must be at least 1".to_string());
+        }
+
+        Ok(())
+    }
+}
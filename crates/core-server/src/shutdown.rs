@@ -0,0 +1,92 @@
+use log::warn;
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+
+use crate::{connections::ConnectionsLock, outbound::OutboundQueue};
+
+/// Resolves on Ctrl+C, or on SIGTERM where there is one (it's what `docker stop` sends)
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Couldn't listen for Ctrl+C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Couldn't listen for SIGTERM")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Closes every websocket with a close frame and waits up to `timeout` for the frames to
+/// be written. Returns whether every connection was closed in time.
+pub async fn close_all_connections(connections: &ConnectionsLock, timeout: Duration) -> bool {
+    let queues: Vec<OutboundQueue> = connections
+        .read()
+        .await
+        .0
+        .values()
+        .map(|connection| connection.queue.clone())
+        .collect();
+
+    for queue in &queues {
+        // 1001 is the close code for an endpoint going away
+        queue.hang_up(1001, "Server shutting down");
+    }
+
+    let deadline = Instant::now() + timeout;
+    while queues.iter().any(|queue| !queue.is_finished()) {
+        if Instant::now() >= deadline {
+            warn!("Gave up waiting for every connection to close");
+            return false;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codec::Codec,
+        connections::{ConnectionRole, ConnectionsLock},
+    };
+    use core_api::UserId;
+
+    #[tokio::test]
+    async fn every_connection_is_closed() {
+        let connections = ConnectionsLock::default();
+
+        for user_id in 1..=3 {
+            let queue = OutboundQueue::new(Codec::Json, 8, Duration::from_secs(10));
+            tokio::task::spawn(queue.clone().forward(futures_util::sink::drain()));
+            connections.write().await.new_connection(
+                UserId(user_id),
+                queue,
+                Codec::Json,
+                ConnectionRole::Player,
+            );
+        }
+
+        assert!(close_all_connections(&connections, Duration::from_secs(5)).await);
+        assert!(connections
+            .read()
+            .await
+            .0
+            .values()
+            .all(|connection| connection.queue.is_finished()));
+    }
+}
@@ -0,0 +1,331 @@
+use std::{sync::Arc, time::Duration};
+
+use ae_direction::{BodyRelative, Cardinal};
+use ae_position::{Delta, Position};
+use core_api::{ClientMessage, ServerMessageSingleClient, SpriteTexture};
+use core_client::{
+    client::{ClientError, GameClient},
+    message::ServerMessage,
+    sprites::SpriteMirror,
+};
+use futures_util::StreamExt;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::{
+    sync::{mpsc::UnboundedSender, watch, Semaphore},
+    time::{interval_at, sleep_until, Instant, MissedTickBehavior},
+};
+
+use crate::settings::Settings;
+
+/// A move that hasn't been answered with `CentreCamera` by now was blocked or dropped
+const UNANSWERED_AFTER: Duration = Duration::from_secs(2);
+
+const DIRECTIONS: [BodyRelative; 4] = [
+    BodyRelative::Up,
+    BodyRelative::Down,
+    BodyRelative::Left,
+    BodyRelative::Right,
+];
+
+/// What one simulated player saw and did
+#[derive(Debug, Default)]
+pub struct BotReport {
+    /// From connecting until the server handed out a session token
+    pub join_time: Duration,
+    /// From sending a move until its `CentreCamera` arrived
+    pub move_latencies: Vec<Duration>,
+    pub moves: u64,
+    pub attacks: u64,
+    pub talks: u64,
+    pub unanswered_moves: u64,
+    pub messages_received: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    Move,
+    Attack,
+    Talk,
+}
+
+fn choose_action(rng: &mut StdRng, settings: &Settings) -> Action {
+    let roll = rng.gen_range(0.0..settings.actions_per_sec());
+    if roll < settings.attacks_per_sec.max(0.0) {
+        Action::Attack
+    } else if roll < settings.attacks_per_sec.max(0.0) + settings.talks_per_sec.max(0.0) {
+        Action::Talk
+    } else {
+        Action::Move
+    }
+}
+
+fn is_floor(texture: SpriteTexture) -> bool {
+    matches!(
+        texture,
+        SpriteTexture::FloorGrass | SpriteTexture::FloorConcrete | SpriteTexture::FloorSlime
+    )
+}
+
+/// Enemies, and other players, who can be attacked by walking into them
+fn can_attack(texture: SpriteTexture) -> bool {
+    matches!(
+        texture,
+        SpriteTexture::NpcRatFrames4
+            | SpriteTexture::NpcKingRatFrames4
+            | SpriteTexture::NpcSlime
+            | SpriteTexture::PcAntBoi
+            | SpriteTexture::PcAntBoiFrames4
+            | SpriteTexture::PcBoneyBoi
+            | SpriteTexture::PcBoneyBoiFrames4
+            | SpriteTexture::PcGhostBoyFrames8
+            | SpriteTexture::PcSewerKidFrames6
+            | SpriteTexture::PcKidZilla
+    )
+}
+
+/// NPCs placed on the maps, walking into one starts a conversation
+fn can_talk(texture: SpriteTexture) -> bool {
+    matches!(
+        texture,
+        SpriteTexture::NpcFatherNeilFrames6
+            | SpriteTexture::NpcFootballFrames4
+            | SpriteTexture::NpcGoon1Frames4
+            | SpriteTexture::NpcGoon2Frames4
+            | SpriteTexture::NpcGoon3Frames4
+            | SpriteTexture::NpcGoon4Frames4
+            | SpriteTexture::NpcGraceJonesFrames6
+            | SpriteTexture::NpcMallChick1Frames6
+            | SpriteTexture::NpcMallChick2Frames6
+            | SpriteTexture::NpcPersonFrames2
+            | SpriteTexture::NpcRealEstateDickFrames21
+            | SpriteTexture::NpcSmallRatFrames6
+    )
+}
+
+fn neighbour(pos: &Position, direction: BodyRelative) -> Position {
+    pos.add_delta(&Delta::from(Cardinal::from(direction)))
+}
+
+/// A direction with a sprite next to the player that `wanted` picks out
+fn direction_towards(
+    sprites: &SpriteMirror,
+    me: &Position,
+    wanted: impl Fn(SpriteTexture) -> bool,
+) -> Option<BodyRelative> {
+    DIRECTIONS.into_iter().find(|direction| {
+        sprites
+            .at(&neighbour(me, *direction))
+            .any(|(_, sprite)| wanted(sprite.texture))
+    })
+}
+
+/// A random direction with nothing but floor in the way, so the move should succeed
+fn free_direction(rng: &mut StdRng, sprites: &SpriteMirror, me: &Position) -> BodyRelative {
+    let free: Vec<BodyRelative> = DIRECTIONS
+        .into_iter()
+        .filter(|direction| {
+            sprites
+                .at(&neighbour(me, *direction))
+                .all(|(_, sprite)| is_floor(sprite.texture))
+        })
+        .collect();
+
+    if free.is_empty() {
+        DIRECTIONS[rng.gen_range(0..DIRECTIONS.len())]
+    } else {
+        free[rng.gen_range(0..free.len())]
+    }
+}
+
+struct Bot {
+    client: GameClient,
+    report: BotReport,
+    /// When the move being timed was sent
+    move_sent: Option<Instant>,
+}
+
+impl Bot {
+    fn observe(
+        &mut self,
+        message: Option<Result<ServerMessage, ClientError>>,
+    ) -> Result<(), ClientError> {
+        let message = message.unwrap_or(Err(ClientError::Closed))?;
+        self.report.messages_received += 1;
+
+        if let ServerMessage::SingleClient(ServerMessageSingleClient::CentreCamera(_)) = message {
+            if let Some(sent) = self.move_sent.take() {
+                self.report.move_latencies.push(sent.elapsed());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn act(&mut self, rng: &mut StdRng, settings: &Settings) -> Result<(), ClientError> {
+        let Some(me) = self.client.sprites().camera().cloned() else {
+            return Ok(());
+        };
+
+        let sprites = self.client.sprites();
+        let target = match choose_action(rng, settings) {
+            Action::Attack => {
+                direction_towards(sprites, &me, can_attack).map(|d| (d, Action::Attack))
+            }
+            Action::Talk => direction_towards(sprites, &me, can_talk).map(|d| (d, Action::Talk)),
+            Action::Move => None,
+        };
+        // Nobody in reach, so take a step in any direction that isn't blocked
+        let (direction, action) =
+            target.unwrap_or_else(|| (free_direction(rng, sprites, &me), Action::Move));
+
+        match action {
+            Action::Move => {
+                self.report.moves += 1;
+
+                if matches!(self.move_sent, Some(sent) if sent.elapsed() > UNANSWERED_AFTER) {
+                    self.report.unanswered_moves += 1;
+                    self.move_sent = None;
+                }
+                // Only one move is timed at a time, a camera update can't say which move it was for
+                if self.move_sent.is_none() {
+                    self.move_sent = Some(Instant::now());
+                }
+            }
+            Action::Attack => self.report.attacks += 1,
+            Action::Talk => self.report.talks += 1,
+        }
+
+        self.client.send(&ClientMessage::Keypress(direction)).await
+    }
+}
+
+/// Joins the game as player `number`, then plays from when `start` gives the time to
+/// stop until then. `joined` hears whether joining worked.
+pub async fn run_bot(
+    number: usize,
+    settings: Arc<Settings>,
+    logins: Arc<Semaphore>,
+    joined: UnboundedSender<bool>,
+    mut start: watch::Receiver<Option<Instant>>,
+) -> Result<BotReport, ClientError> {
+    let join_started = Instant::now();
+    let client = join(number, &settings, &logins).await;
+    joined.send(client.is_ok()).ok();
+    // The join summary waits for every sender to be dropped, not for the bots to finish
+    drop(joined);
+
+    let mut bot = Bot {
+        client: client?,
+        report: BotReport {
+            join_time: join_started.elapsed(),
+            ..Default::default()
+        },
+        move_sent: None,
+    };
+
+    // Keep reading while everyone else joins, the server closes connections that go quiet
+    let deadline = loop {
+        if let Some(deadline) = *start.borrow() {
+            break deadline;
+        }
+
+        tokio::select! {
+            changed = start.changed() => {
+                if changed.is_err() {
+                    return Ok(bot.report);
+                }
+            }
+            message = bot.client.next() => bot.observe(message)?,
+        }
+    };
+
+    let mut rng = StdRng::from_entropy();
+    let period = Duration::from_secs_f64(1.0 / settings.actions_per_sec());
+    // Spread players out over the first period so they don't all act at once
+    let mut actions = interval_at(Instant::now() + period.mul_f64(rng.gen()), period);
+    actions.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = sleep_until(deadline) => break,
+            _ = actions.tick() => bot.act(&mut rng, &settings).await?,
+            message = bot.client.next() => bot.observe(message)?,
+        }
+    }
+
+    if bot.move_sent.is_some() {
+        bot.report.unanswered_moves += 1;
+    }
+    bot.client.close().await.ok();
+
+    Ok(bot.report)
+}
+
+/// Logs in, or registers on the first run with these settings, and joins the game
+async fn join(
+    number: usize,
+    settings: &Settings,
+    logins: &Semaphore,
+) -> Result<GameClient, ClientError> {
+    let name = format!("{}-{}", settings.name_prefix, number);
+    let mut client = GameClient::connect(&settings.url).await?;
+
+    {
+        let _permit = logins
+            .acquire()
+            .await
+            .expect("The login semaphore is never closed");
+        if let Err(ClientError::Refused(_)) = client.login(&name, &settings.password, false).await {
+            client.login(&name, &settings.password, true).await?;
+        }
+    }
+
+    client.initialize(SpriteTexture::PcBoneyBoi).await?;
+
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_api::{EntityIndex, SpriteUpdate};
+
+    fn sprite(idx: u32, x: i32, y: i32, sprite: SpriteTexture) -> SpriteUpdate {
+        SpriteUpdate {
+            entity: EntityIndex { idx },
+            pos: Position { x, y },
+            sprite,
+        }
+    }
+
+    #[test]
+    fn players_pick_directions_from_what_is_around_them() {
+        let mut sprites = SpriteMirror::default();
+        sprites.apply(&ServerMessageSingleClient::UpdateFullGameMap {
+            camera: Position { x: 5, y: 5 },
+            entities: vec![
+                sprite(1, 5, 5, SpriteTexture::PcBoneyBoi),
+                sprite(2, 5, 4, SpriteTexture::WallBrick),
+                sprite(3, 5, 6, SpriteTexture::NpcSlime),
+                sprite(4, 4, 5, SpriteTexture::NpcPersonFrames2),
+                sprite(5, 6, 5, SpriteTexture::FloorGrass),
+            ],
+        });
+        let me = Position { x: 5, y: 5 };
+
+        assert!(matches!(
+            direction_towards(&sprites, &me, can_attack),
+            Some(BodyRelative::Down)
+        ));
+        assert!(matches!(
+            direction_towards(&sprites, &me, can_talk),
+            Some(BodyRelative::Left)
+        ));
+        for seed in 0..10 {
+            assert!(matches!(
+                free_direction(&mut StdRng::seed_from_u64(seed), &sprites, &me),
+                BodyRelative::Right
+            ));
+        }
+    }
+}
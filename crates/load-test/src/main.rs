@@ -0,0 +1,86 @@
+mod bot;
+mod report;
+mod settings;
+
+use std::sync::Arc;
+
+use bot::run_bot;
+use log::warn;
+use report::Summary;
+use settings::{Settings, USAGE};
+use tokio::{
+    sync::{mpsc, watch, Semaphore},
+    time::Instant,
+};
+
+#[tokio::main]
+async fn main() {
+    if std::env::args().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    pretty_env_logger::init();
+
+    let settings = Arc::new(
+        Settings::from_args(std::env::args().skip(1)).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(2);
+        }),
+    );
+
+    let logins = Arc::new(Semaphore::new(settings.login_concurrency));
+    let (joined_sender, mut joined_receiver) = mpsc::unbounded_channel();
+    let (start_sender, start_receiver) = watch::channel(None);
+
+    println!("Joining {} players to {}", settings.players, settings.url);
+    let join_started = Instant::now();
+
+    let bots: Vec<_> = (0..settings.players)
+        .map(|number| {
+            tokio::task::spawn(run_bot(
+                number,
+                settings.clone(),
+                logins.clone(),
+                joined_sender.clone(),
+                start_receiver.clone(),
+            ))
+        })
+        .collect();
+    drop(joined_sender);
+
+    let mut failed = 0;
+    while let Some(joined) = joined_receiver.recv().await {
+        if !joined {
+            failed += 1;
+        }
+    }
+    println!(
+        "{} players joined in {:.1?}, {} failed",
+        settings.players - failed,
+        join_started.elapsed(),
+        failed
+    );
+
+    // Everyone plays for the same stretch of time, so throughput is measured at full load
+    start_sender
+        .send(Some(Instant::now() + settings.duration))
+        .ok();
+
+    let mut summary = Summary::new(settings.players, settings.duration);
+    for bot in bots {
+        match bot.await {
+            Ok(Ok(report)) => summary.add(report),
+            Ok(Err(error)) => {
+                warn!("A player gave up: {}", error);
+                summary.failed += 1;
+            }
+            Err(error) => {
+                warn!("A player panicked: {}", error);
+                summary.failed += 1;
+            }
+        }
+    }
+
+    println!("{}", summary);
+}
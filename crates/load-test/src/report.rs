@@ -0,0 +1,127 @@
+use std::{fmt, time::Duration};
+
+use crate::bot::BotReport;
+
+/// The nearest-rank percentile of already sorted durations
+fn percentile(sorted: &[Duration], percent: f64) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Everything the players reported, added together
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub players: usize,
+    pub failed: usize,
+    pub play_time: Duration,
+    join_times: Vec<Duration>,
+    move_latencies: Vec<Duration>,
+    moves: u64,
+    attacks: u64,
+    talks: u64,
+    unanswered_moves: u64,
+    messages_received: u64,
+}
+
+impl Summary {
+    pub fn new(players: usize, play_time: Duration) -> Self {
+        Self {
+            players,
+            play_time,
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&mut self, report: BotReport) {
+        self.join_times.push(report.join_time);
+        self.move_latencies.extend(report.move_latencies);
+        self.moves += report.moves;
+        self.attacks += report.attacks;
+        self.talks += report.talks;
+        self.unanswered_moves += report.unanswered_moves;
+        self.messages_received += report.messages_received;
+    }
+
+    fn per_sec(&self, count: u64) -> f64 {
+        count as f64 / self.play_time.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+fn write_percentiles(f: &mut fmt::Formatter<'_>, label: &str, times: &[Duration]) -> fmt::Result {
+    let mut sorted = times.to_vec();
+    sorted.sort_unstable();
+
+    write!(f, "{:<24}", label)?;
+    if sorted.is_empty() {
+        return writeln!(f, "no samples");
+    }
+    for percent in [50.0, 90.0, 99.0, 100.0] {
+        let label = if percent == 100.0 {
+            "max".to_string()
+        } else {
+            format!("p{}", percent)
+        };
+        write!(
+            f,
+            "{} {:>9.1?}  ",
+            label,
+            percentile(&sorted, percent).unwrap()
+        )?;
+    }
+    writeln!(f, "({} samples)", sorted.len())
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} of {} players played for {:.1?}",
+            self.players - self.failed,
+            self.players,
+            self.play_time
+        )?;
+        write_percentiles(f, "Join time", &self.join_times)?;
+        write_percentiles(f, "Move to CentreCamera", &self.move_latencies)?;
+        writeln!(
+            f,
+            "{:<24}{:.1}/s ({} moves, {} attacks, {} talks)",
+            "Inputs sent",
+            self.per_sec(self.moves + self.attacks + self.talks),
+            self.moves,
+            self.attacks,
+            self.talks
+        )?;
+        writeln!(
+            f,
+            "{:<24}{:.1}/s ({} timed moves never answered)",
+            "Moves answered",
+            self.per_sec(self.move_latencies.len() as u64),
+            self.unanswered_moves
+        )?;
+        writeln!(
+            f,
+            "{:<24}{:.1}/s",
+            "Messages received",
+            self.per_sec(self.messages_received)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let times: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+
+        assert_eq!(percentile(&times, 50.0), Some(Duration::from_millis(50)));
+        assert_eq!(percentile(&times, 99.0), Some(Duration::from_millis(99)));
+        assert_eq!(percentile(&times, 100.0), Some(Duration::from_millis(100)));
+        assert_eq!(percentile(&times[..1], 0.0), Some(Duration::from_millis(1)));
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+}
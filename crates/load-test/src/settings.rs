@@ -0,0 +1,148 @@
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+/// Account names can be at most 20 characters, the player number takes up the rest
+const MAX_NAME_PREFIX_LEN: usize = 14;
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub url: String,
+    pub players: usize,
+    pub duration: Duration,
+    /// Wandering keypresses per second for each player
+    pub moves_per_sec: f64,
+    /// Attempts per second for each player to attack whoever is next to them
+    pub attacks_per_sec: f64,
+    /// Attempts per second for each player to talk to an NPC next to them
+    pub talks_per_sec: f64,
+    /// Players are `<prefix>-<number>`, their accounts are created on the first run
+    pub name_prefix: String,
+    pub password: String,
+    /// Every login hashes a password on the server, so only this many are sent at once
+    pub login_concurrency: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            url: "ws://127.0.0.1:8080/api/game".to_string(),
+            players: 100,
+            duration: Duration::from_secs(60),
+            moves_per_sec: 4.0,
+            attacks_per_sec: 0.5,
+            talks_per_sec: 0.2,
+            name_prefix: "load".to_string(),
+            password: "load-test-password".to_string(),
+            login_concurrency: 16,
+        }
+    }
+}
+
+pub const USAGE: &str = "Usage: load-test [--<option> <value>]...
+
+  --url                  Websocket endpoint (ws://127.0.0.1:8080/api/game)
+  --players              Simulated players (100)
+  --duration-secs        How long they play for once everyone has joined (60)
+  --moves-per-sec        Wandering moves per player per second (4)
+  --attacks-per-sec      Attacks per player per second, when someone is in reach (0.5)
+  --talks-per-sec        Talks per player per second, when an NPC is in reach (0.2)
+  --name-prefix          Players are named <prefix>-<number> (load)
+  --password             Password for every player's account
+  --login-concurrency    Logins in flight at once (16)
+";
+
+fn parse<T>(name: &str, value: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|e: T::Err| format!("Invalid {}: {}", name, e))
+}
+
+impl Settings {
+    /// Reads `--name value` and `--name=value` flags over the defaults
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut settings = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), value.to_string()),
+                None => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("{} needs a value", arg))?;
+                    (arg, value)
+                }
+            };
+
+            match name.as_str() {
+                "--url" => settings.url = value,
+                "--players" => settings.players = parse(&name, &value)?,
+                "--duration-secs" => settings.duration = Duration::from_secs(parse(&name, &value)?),
+                "--moves-per-sec" => settings.moves_per_sec = parse(&name, &value)?,
+                "--attacks-per-sec" => settings.attacks_per_sec = parse(&name, &value)?,
+                "--talks-per-sec" => settings.talks_per_sec = parse(&name, &value)?,
+                "--name-prefix" => settings.name_prefix = value,
+                "--password" => settings.password = value,
+                "--login-concurrency" => settings.login_concurrency = parse(&name, &value)?,
+                _ => return Err(format!("Unknown option {}, see --help", name)),
+            }
+        }
+
+        if settings.name_prefix.is_empty() || settings.name_prefix.len() > MAX_NAME_PREFIX_LEN {
+            return Err(format!(
+                "--name-prefix must be 1 to {} characters",
+                MAX_NAME_PREFIX_LEN
+            ));
+        }
+        for (rate, name) in [
+            (settings.moves_per_sec, "--moves-per-sec"),
+            (settings.attacks_per_sec, "--attacks-per-sec"),
+            (settings.talks_per_sec, "--talks-per-sec"),
+        ] {
+            if !rate.is_finite() {
+                return Err(format!("{} must be a finite number", name));
+            }
+        }
+        if settings.actions_per_sec() <= 0.0 {
+            return Err("At least one of the rates must be above zero".to_string());
+        }
+        if settings.login_concurrency == 0 {
+            return Err("--login-concurrency must be at least 1".to_string());
+        }
+
+        Ok(settings)
+    }
+
+    /// How often each player does something, of any kind
+    pub fn actions_per_sec(&self) -> f64 {
+        self.moves_per_sec.max(0.0) + self.attacks_per_sec.max(0.0) + self.talks_per_sec.max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn flags_override_defaults() {
+        let settings =
+            Settings::from_args(args(&["--players", "500", "--talks-per-sec=0"])).unwrap();
+
+        assert_eq!(settings.players, 500);
+        assert_eq!(settings.talks_per_sec, 0.0);
+        assert_eq!(settings.duration, Duration::from_secs(60));
+
+        assert!(Settings::from_args(args(&["--players", "lots"])).is_err());
+        assert!(Settings::from_args(args(&["--name-prefix", "much-too-long-prefix"])).is_err());
+        assert!(Settings::from_args(args(&["--bogus", "1"])).is_err());
+        assert!(Settings::from_args(args(&["--moves-per-sec", "NaN"])).is_err());
+        assert!(Settings::from_args(args(&["--attacks-per-sec", "inf"])).is_err());
+    }
+}
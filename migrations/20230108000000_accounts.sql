@@ -0,0 +1,7 @@
+-- Players log in to an account before they can join the game, names are unique ignoring case
+CREATE TABLE accounts (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    password_hash TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
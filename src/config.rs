@@ -0,0 +1,361 @@
+use core_database::DatabaseConfig;
+use core_engine::resources::config::EngineConfig;
+use core_server::settings::ConnectionSettings;
+use serde::Deserialize;
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+/// Read when no config file is named, if it exists
+const DEFAULT_CONFIG_FILE: &str = "config.ron";
+
+/// Everything the server can be configured with, see `config.ron` for an example
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub connection: ConnectionSettings,
+    pub database: DatabaseConfig,
+    pub engine: EngineConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: IpAddr,
+    pub port: u16,
+    /// Served at the root, it's where the production build of the client ends up
+    pub static_dir: PathBuf,
+    /// Origins allowed to read the public REST API from a browser, any origin when empty
+    pub cors_origins: Vec<String>,
+    /// The admin API turns every request away unless a token is configured
+    pub admin_token: Option<String>,
+    /// How many seconds players are warned for before the server stops
+    pub shutdown_countdown_secs: u32,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 8080,
+            static_dir: PathBuf::from("client/dist"),
+            cors_origins: Vec::new(),
+            admin_token: None,
+            shutdown_countdown_secs: 10,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn address(&self) -> SocketAddr {
+        SocketAddr::new(self.host, self.port)
+    }
+}
+
+type Apply = fn(&mut Config, &str) -> Result<(), String>;
+
+/// Settings that can be overridden by an environment variable with this name, or by a
+/// command line flag with the same name in lower case and dashes (`PORT` is `--port`)
+const OVERRIDES: &[(&str, Apply)] = &[
+    ("HOST", |config, value| {
+        parse(value, &mut config.server.host)
+    }),
+    ("PORT", |config, value| {
+        parse(value, &mut config.server.port)
+    }),
+    ("STATIC_DIR", |config, value| {
+        parse(value, &mut config.server.static_dir)
+    }),
+    ("CORS_ORIGINS", |config, value| {
+        config.server.cors_origins = value
+            .split(',')
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .map(String::from)
+            .collect();
+        Ok(())
+    }),
+    ("ADMIN_TOKEN", |config, value| {
+        config.server.admin_token = Some(value.to_string()).filter(|token| !token.is_empty());
+        Ok(())
+    }),
+    ("SHUTDOWN_COUNTDOWN_SECS", |config, value| {
+        parse(value, &mut config.server.shutdown_countdown_secs)
+    }),
+    ("MAX_PROTOCOL_VIOLATIONS", |config, value| {
+        parse(value, &mut config.connection.max_protocol_violations)
+    }),
+    ("PING_INTERVAL_SECS", |config, value| {
+        parse_secs(value, &mut config.connection.ping_interval)
+    }),
+    ("IDLE_TIMEOUT_SECS", |config, value| {
+        parse_secs(value, &mut config.connection.idle_timeout)
+    }),
+    ("OUTBOUND_QUEUE_CAPACITY", |config, value| {
+        parse(value, &mut config.connection.outbound_queue_capacity)
+    }),
+    ("MAX_LAG_SECS", |config, value| {
+        parse_secs(value, &mut config.connection.max_lag)
+    }),
+    ("DATABASE_FILE", |config, value| {
+        parse(value, &mut config.database.file)
+    }),
+    ("DATABASE_MAX_CONNECTIONS", |config, value| {
+        parse(value, &mut config.database.max_connections)
+    }),
+    ("SESSION_GRACE_PERIOD_SECS", |config, value| {
+        parse(value, &mut config.engine.session_grace_period_secs)
+    }),
+    ("INSTANCE_ENTRY_TIMEOUT_SECS", |config, value| {
+        parse(value, &mut config.engine.instance_entry_timeout_secs)
+    }),
+    ("SPAWN_INTERVAL_SECS", |config, value| {
+        parse(value, &mut config.engine.spawn_interval_secs)
+    }),
+    ("DEBUG_INTERVAL_SECS", |config, value| {
+        parse(value, &mut config.engine.debug_interval_secs)
+    }),
+    ("MAPS_DIR", |config, value| {
+        parse(value, &mut config.engine.maps_dir)
+    }),
+    ("DATA_DIR", |config, value| {
+        parse(value, &mut config.engine.data_dir)
+    }),
+    ("WATCH_DATA", |config, value| {
+        parse(value, &mut config.engine.watch_data)
+    }),
+    ("START_MAP_ID", |config, value| {
+        parse(value, &mut config.engine.start_map_id)
+    }),
+    ("ENEMY_MAP_ID", |config, value| {
+        parse(value, &mut config.engine.enemy_map_id)
+    }),
+    ("TICK_RATE", |config, value| {
+        parse(value, &mut config.engine.tick_rate)
+    }),
+    ("RNG_SEED", |config, value| {
+        let mut seed: u64 = 0;
+        parse(value, &mut seed)?;
+        config.engine.rng_seed = Some(seed);
+        Ok(())
+    }),
+];
+
+fn parse<T>(value: &str, field: &mut T) -> Result<(), String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    *field = value.parse().map_err(|e: T::Err| e.to_string())?;
+    Ok(())
+}
+
+fn parse_secs(value: &str, field: &mut Duration) -> Result<(), String> {
+    let mut secs: u64 = 0;
+    parse(value, &mut secs)?;
+    *field = Duration::from_secs(secs);
+    Ok(())
+}
+
+fn flag_name(env_name: &str) -> String {
+    format!("--{}", env_name.to_lowercase().replace('_', "-"))
+}
+
+/// Splits `--name value` and `--name=value` arguments into pairs
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Vec<(String, String)>, String> {
+    let mut args = args.into_iter();
+    let mut flags = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            return Err(format!("Unexpected argument {}", arg));
+        }
+
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} needs a value", arg))?;
+                (arg, value)
+            }
+        };
+
+        flags.push((name, value));
+    }
+
+    Ok(flags)
+}
+
+/// The text printed for `--help`
+pub fn usage() -> String {
+    let mut usage = String::from(
+        "Usage: gamejam [--config <file>] [--<option> <value>]...\n\n\
+         Settings are read from the config file (--config or CONFIG_FILE, config.ron by\n\
+         default), then environment variables, then these options:\n\n",
+    );
+
+    for (env_name, _) in OVERRIDES {
+        usage.push_str(&format!("  {:<30} {}\n", flag_name(env_name), env_name));
+    }
+
+    usage
+}
+
+impl Config {
+    pub fn from_ron(text: &str) -> Result<Self, String> {
+        ron::from_str(text).map_err(|e| e.to_string())
+    }
+
+    fn from_file(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read config file {}: {}", path.display(), e))?;
+
+        Self::from_ron(&text).map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+    }
+
+    /// Builds the config from the config file (named by `--config` or `CONFIG_FILE`), then
+    /// environment variables, then the remaining command line flags
+    pub fn load(
+        args: impl IntoIterator<Item = String>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, String> {
+        let flags = parse_args(args)?;
+
+        let named_file = flags
+            .iter()
+            .find(|(name, _)| name == "--config")
+            .map(|(_, value)| value.clone())
+            .or_else(|| env("CONFIG_FILE"));
+
+        let mut config = match named_file {
+            Some(file) => Self::from_file(Path::new(&file))?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Self::default(),
+        };
+
+        config.apply_overrides(&flags, env)?;
+        config.connection.validate()?;
+
+        Ok(config)
+    }
+
+    fn apply_overrides(
+        &mut self,
+        flags: &[(String, String)],
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<(), String> {
+        for (env_name, apply) in OVERRIDES {
+            if let Some(value) = env(env_name) {
+                apply(self, &value).map_err(|e| format!("Invalid {}: {}", env_name, e))?;
+            }
+        }
+
+        for (name, value) in flags {
+            if name == "--config" {
+                continue;
+            }
+
+            let (_, apply) = OVERRIDES
+                .iter()
+                .find(|(env_name, _)| flag_name(env_name) == *name)
+                .ok_or_else(|| format!("Unknown option {}, see --help", name))?;
+
+            apply(self, value).map_err(|e| format!("Invalid {}: {}", name, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<(String, String)> {
+        parse_args(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    #[test]
+    fn example_config_file_is_valid() {
+        let config = Config::from_ron(include_str!("../config.ron")).unwrap();
+        let defaults = Config::default();
+
+        assert_eq!(config.server.address(), defaults.server.address());
+        assert_eq!(
+            config.connection.idle_timeout,
+            defaults.connection.idle_timeout
+        );
+        assert_eq!(config.database.file, defaults.database.file);
+        assert_eq!(config.engine.start_map_id, defaults.engine.start_map_id);
+    }
+
+    #[test]
+    fn missing_sections_use_defaults() {
+        let config = Config::from_ron("(server: (port: 9000))").unwrap();
+
+        assert_eq!(config.server.port, 9000);
+        assert_eq!(config.server.static_dir, PathBuf::from("client/dist"));
+        assert_eq!(config.database.max_connections, 5);
+        assert!(Config::from_ron("(server: (prot: 9000))").is_err());
+    }
+
+    #[test]
+    fn flags_win_over_env_which_wins_over_the_file() {
+        let mut config =
+            Config::from_ron("(server: (port: 9000), engine: (start_map_id: 2))").unwrap();
+        let env = |name: &str| match name {
+            "PORT" => Some("9001".to_string()),
+            "PING_INTERVAL_SECS" => Some("3".to_string()),
+            _ => None,
+        };
+
+        config
+            .apply_overrides(
+                &args(&["--port", "9002", "--cors-origins=a.com, b.com"]),
+                env,
+            )
+            .unwrap();
+
+        assert_eq!(config.server.port, 9002);
+        assert_eq!(config.connection.ping_interval, Duration::from_secs(3));
+        assert_eq!(config.engine.start_map_id, 2);
+        assert_eq!(config.server.cors_origins, ["a.com", "b.com"]);
+    }
+
+    #[test]
+    fn bad_overrides_are_reported() {
+        let mut config = Config::default();
+
+        assert!(config
+            .apply_overrides(&args(&["--port", "lots"]), |_| None)
+            .is_err());
+        assert!(config
+            .apply_overrides(&args(&["--prot", "1"]), |_| None)
+            .is_err());
+        assert!(parse_args(["--port".to_string()]).is_err());
+    }
+
+    #[test]
+    fn settings_that_would_break_connections_are_refused() {
+        let env = |name: &str| match name {
+            "PING_INTERVAL_SECS" => Some("0".to_string()),
+            _ => None,
+        };
+
+        assert_eq!(
+            Config::load(["--config".to_string(), "config.ron".to_string()], env).unwrap_err(),
+            "connection.ping_interval_secs must be at least 1"
+        );
+
+        let config = Config::from_ron("(connection: (outbound_queue_capacity: 0))").unwrap();
+        assert!(config.connection.validate().is_err());
+    }
+}
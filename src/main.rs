@@ -1,16 +1,41 @@
+mod config;
+
+use config::{usage, Config};
 use core_api::{
-    ClientMessage, DatabaseRequest, DatabaseResponse, ServerMessageAllClients,
-    ServerMessageSingleClient, UserId,
+    AdminCommand, AdminReply, ClientMessage, DatabaseRequest, DatabaseResponse,
+    ServerMessageAllClients, SharedMetrics, TickOutput, UserId,
+};
+use core_database::{
+    accounts::{register_account, verify_login, Account, AccountError},
+    database_setup, increment_db_move_count_and_get_total,
 };
-use core_database::{database_setup, increment_db_move_count_and_get_total};
 use core_engine::{
-    data::{player_configs::PlayerConfigs, player_configs_str},
-    start_game_engine,
+    data::{game_data::load_game_data, map_data::load_maps},
+    EngineBuilder, EngineChannels,
+};
+use core_server::{
+    admin::admin_routes,
+    codec::{EncodedBroadcast, EncodedMessage},
+    connections::{ConnectionRole, ConnectionsLock},
+    metrics::{metrics_route, MESSAGES_OUT},
+    new_connection::upgrade_connection,
+    outbound::Outbound,
+    settings::ConnectionSettings,
+    shutdown::{close_all_connections, shutdown_signal},
+};
+use log::{error, info};
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
 };
-use core_server::{connections::ConnectionsLock, new_connection::handle_new_connection};
-use log::info;
-use tokio::sync::mpsc::{self, UnboundedSender};
-use warp::{ws::Message, Filter};
+use tokio::{
+    sync::{
+        mpsc::{self, UnboundedSender},
+        oneshot,
+    },
+    task::JoinSet,
+};
+use warp::Filter;
 
 // hello!
 
@@ -18,12 +43,59 @@ use warp::{ws::Message, Filter};
 
 // hey
 
+/// How long to wait for close frames to reach every client
+const CLOSE_CONNECTIONS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Ticks the engine may run ahead of the task passing their messages on to each connection,
+/// after that it waits for it to catch up
+const ENGINE_OUTPUT_CAPACITY: usize = 64;
+
+/// What the game engine is told about a register or login attempt
+fn account_response(result: Result<Account, AccountError>) -> DatabaseResponse {
+    match result {
+        Ok(account) => DatabaseResponse::LoggedIn {
+            account_id: account.id,
+            name: account.name,
+        },
+        Err(error) => {
+            if let AccountError::Database(ref db_error) = error {
+                error!("Account query failed: {}", db_error);
+            }
+            DatabaseResponse::LoginFailed(error.to_string())
+        }
+    }
+}
+
 fn main() {
+    if std::env::args().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", usage());
+        return;
+    }
+
+    let config = Config::load(std::env::args().skip(1), |name| std::env::var(name).ok())
+        .unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(2);
+        });
+    let Config {
+        server: server_config,
+        connection: connection_settings,
+        database: database_config,
+        engine: engine_config,
+    } = config;
+
+    let data = load_game_data(&engine_config.data_dir).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(2);
+    });
+
+    let maps = load_maps(&engine_config, &data.dialogue_contents).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(2);
+    });
+
     let (client_sender, client_receiver) = mpsc::unbounded_channel::<(UserId, ClientMessage)>();
-    let (server_sender_single_client, mut server_receiver_single_client) =
-        mpsc::unbounded_channel::<(UserId, ServerMessageSingleClient)>();
-    let (server_sender_all_clients, mut server_receiver_all_clients) =
-        mpsc::unbounded_channel::<ServerMessageAllClients>();
+    let (server_sender, mut server_receiver) = mpsc::channel::<TickOutput>(ENGINE_OUTPUT_CAPACITY);
 
     let (engine_to_db_sender, mut engine_to_db_receiver) =
         mpsc::unbounded_channel::<(UserId, DatabaseRequest)>();
@@ -31,15 +103,31 @@ fn main() {
     let (db_to_engine_sender, db_to_engine_receiver) =
         mpsc::unbounded_channel::<(UserId, DatabaseResponse)>();
 
+    let (admin_sender, admin_receiver) =
+        mpsc::unbounded_channel::<(AdminCommand, oneshot::Sender<AdminReply>)>();
+
+    let (engine_shutdown_sender, engine_shutdown_receiver) = oneshot::channel::<()>();
+
+    // Published to by the engine and the database listener, read by `api/metrics`
+    let metrics = Arc::new(SharedMetrics::default());
+    let engine_metrics = metrics.clone();
+
+    let engine = EngineBuilder::new(engine_config, data)
+        .maps(maps)
+        .metrics(engine_metrics);
+    // What the engine is playing with, including reloads, for `api/player-stats`
+    let player_configs = engine.shared_player_configs();
+
     // Initialize the Bevy game engine
-    std::thread::spawn(move || {
-        start_game_engine(
+    let engine = std::thread::spawn(move || {
+        engine.run(EngineChannels {
             client_receiver,
-            server_sender_single_client,
-            server_sender_all_clients,
-            engine_to_db_sender,
-            db_to_engine_receiver,
-        );
+            server_sender,
+            db_sender: engine_to_db_sender,
+            db_receiver: db_to_engine_receiver,
+            admin_receiver,
+            shutdown_receiver: engine_shutdown_receiver,
+        });
     });
 
     tokio::runtime::Builder::new_multi_thread()
@@ -49,21 +137,40 @@ fn main() {
         .block_on(async {
             pretty_env_logger::init();
 
-            let db = database_setup().await;
+            let db = database_setup(&database_config).await;
+            let db_2 = db.clone();
             // let db = warp::any().map(move || db.clone());
 
             let sender = warp::any().map(move || client_sender.clone());
+            let connection_settings = warp::any().map(move || connection_settings);
 
             // Websocket setup
             let connections = ConnectionsLock::default();
             let connections_2 = connections.clone();
-            let connections_3 = connections.clone();
+            let connections_4 = connections.clone();
+            let connections_5 = connections.clone();
+            let connections_6 = connections.clone();
+            let db_metrics = metrics.clone();
 
             let connections_filter = warp::any().map(move || connections.clone());
 
-            // Database listener
-            tokio::task::spawn(async move {
-                while let Some((user_id, db_response)) = engine_to_db_receiver.recv().await {
+            // Database listener, runs until the engine has stopped and every request is handled
+            let db_listener = tokio::task::spawn(async move {
+                // Registrations and logins still hashing, finished before the database closes
+                let mut account_tasks = JoinSet::new();
+
+                loop {
+                    let request = tokio::select! {
+                        // Collect finished tasks as they go, or the set keeps growing
+                        Some(_) = account_tasks.join_next(), if !account_tasks.is_empty() => {
+                            continue
+                        }
+                        request = engine_to_db_receiver.recv() => request,
+                    };
+                    let Some((user_id, db_response)) = request else {
+                        break;
+                    };
+
                     match db_response {
                         DatabaseRequest::Placeholder => {
                             let move_count = increment_db_move_count_and_get_total(&db).await;
@@ -71,37 +178,71 @@ fn main() {
                             db_to_engine_sender
                                 .send((user_id, DatabaseResponse::MoveCount(move_count)))
                                 .ok();
+                            db_metrics
+                                .pending_database_requests
+                                .fetch_sub(1, Ordering::Relaxed);
+                        }
+                        // Password hashing is slow on purpose, so don't hold up other requests
+                        DatabaseRequest::Register { name, password } => {
+                            let db = db.clone();
+                            let db_to_engine_sender = db_to_engine_sender.clone();
+                            let db_metrics = db_metrics.clone();
+                            account_tasks.spawn(async move {
+                                let result = register_account(&db, &name, &password.0).await;
+                                db_to_engine_sender
+                                    .send((user_id, account_response(result)))
+                                    .ok();
+                                db_metrics
+                                    .pending_database_requests
+                                    .fetch_sub(1, Ordering::Relaxed);
+                            });
+                        }
+                        DatabaseRequest::Login { name, password } => {
+                            let db = db.clone();
+                            let db_to_engine_sender = db_to_engine_sender.clone();
+                            let db_metrics = db_metrics.clone();
+                            account_tasks.spawn(async move {
+                                let result = verify_login(&db, &name, &password.0).await;
+                                db_to_engine_sender
+                                    .send((user_id, account_response(result)))
+                                    .ok();
+                                db_metrics
+                                    .pending_database_requests
+                                    .fetch_sub(1, Ordering::Relaxed);
+                            });
                         }
                     }
                 }
+
+                while account_tasks.join_next().await.is_some() {}
             });
 
-            // Listener for messages to communicate to all clients
+            // Listener for everything the engine sends during a tick. Each tick is queued in
+            // full before the next, so every client gets one batch frame per tick.
             tokio::task::spawn(async move {
-                while let Some(all_clients_message) = server_receiver_all_clients.recv().await {
-                    let serialized_message: String =
-                        serde_json::to_string(&all_clients_message).expect("Serialize should work");
+                while let Some(output) = server_receiver.recv().await {
+                    let connections = connections_2.read().await;
 
-                    info!("Sending to all: {}", serialized_message);
-                    for (&_uid, sender) in connections_2.read().await.0.iter() {
-                        sender.send(Message::text(&serialized_message)).ok();
-                    }
-                }
-            });
+                    for (user_id, messages) in output.batches {
+                        info!(
+                            "Sending only to user {} (tick {}): {:?}",
+                            user_id.0, output.tick, messages
+                        );
 
-            // Listener for messages to communicate to specific clients
-            tokio::task::spawn(async move {
-                while let Some((user_id, single_client_message)) =
-                    server_receiver_single_client.recv().await
-                {
-                    let serialized_message: String = serde_json::to_string(&single_client_message)
-                        .expect("Serialize should work");
+                        if let Some(connection) = connections.0.get(&user_id.0) {
+                            connection.queue.push_batch(output.tick, messages);
+                        }
+                    }
 
-                    info!("Sending only to user {}: {}", user_id.0, serialized_message);
+                    for message in output.broadcasts {
+                        info!("Sending to all (tick {}): {:?}", output.tick, message);
 
-                    for (&uid, sender) in connections_3.read().await.0.iter() {
-                        if uid == user_id.0 {
-                            sender.send(Message::text(&serialized_message)).ok();
+                        // Encoded once and shared by every client's batch for the same tick
+                        let broadcast = Arc::new(EncodedBroadcast::new(message));
+                        for connection in connections.0.values() {
+                            connection
+                                .queue
+                                .push_broadcast(output.tick, broadcast.clone());
                         }
                     }
                 }
@@ -111,46 +252,145 @@ fn main() {
             let game = warp::path!("api" / "game")
                 // The `ws()` filter will prepare Websocket handshake...
                 .and(warp::ws())
-                .and(connections_filter)
+                // The client picks its wire format by offering a websocket subprotocol
+                .and(warp::header::optional::<String>("sec-websocket-protocol"))
+                .and(connection_settings)
+                .and(connections_filter.clone())
                 // .and(db)
-                .and(sender)
+                .and(sender.clone())
                 .map(
                     |ws: warp::ws::Ws,
+                     offered_protocols: Option<String>,
+                     settings: ConnectionSettings,
                      connections: ConnectionsLock,
                      //  db: DatabaseLock,
                      sender: UnboundedSender<(UserId, ClientMessage)>| {
-                        // This will call our function if the handshake succeeds.
-                        ws.on_upgrade(move |socket| {
-                            handle_new_connection(
-                                socket,
-                                connections,
-                                //  db,
-                                sender,
-                            )
-                        })
+                        upgrade_connection(
+                            ws,
+                            offered_protocols,
+                            ConnectionRole::Player,
+                            settings,
+                            connections,
+                            sender,
+                        )
+                    },
+                );
+
+            // GET /spectate/:map_id -> websocket upgrade that watches a map without playing
+            let spectate = warp::path!("api" / "spectate" / i32)
+                .and(warp::ws())
+                .and(warp::header::optional::<String>("sec-websocket-protocol"))
+                .and(connection_settings)
+                .and(connections_filter)
+                .and(sender)
+                .map(
+                    |map_id: i32,
+                     ws: warp::ws::Ws,
+                     offered_protocols: Option<String>,
+                     settings: ConnectionSettings,
+                     connections: ConnectionsLock,
+                     sender: UnboundedSender<(UserId, ClientMessage)>| {
+                        upgrade_connection(
+                            ws,
+                            offered_protocols,
+                            ConnectionRole::Spectator { map_id },
+                            settings,
+                            connections,
+                            sender,
+                        )
                     },
                 );
 
             // If you need to set REST endpoints you can use the example below
 
-            let any_origin_get = warp::cors().allow_any_origin().allow_method("GET");
+            let cors = if server_config.cors_origins.is_empty() {
+                warp::cors().allow_any_origin()
+            } else {
+                warp::cors().allow_origins(server_config.cors_origins.iter().map(String::as_str))
+            };
+            let cors_get = cors.allow_method("GET");
 
             // GET /game-config returns a `200 OK` with a JSON array of ids:
             let player_stats = warp::path!("api" / "player-stats")
-                .map(|| {
-                    warp::reply::json(&ron::from_str::<PlayerConfigs>(player_configs_str).unwrap())
-                })
-                .with(any_origin_get);
+                .map(move || warp::reply::json(&*player_configs.read()))
+                .with(cors_get);
 
             // // GET / -> index html
             // let index = warp::path::end()
             //     .map(|| warp::reply::html(r#"<html>There is nothing to see here.</html>"#));
 
             // Serve static directory -- not currently used
-            let index = warp::fs::dir("client/dist");
+            let index = warp::fs::dir(server_config.static_dir.clone());
+
+            if server_config.admin_token.is_none() {
+                info!("No admin token is configured, the admin API is disabled");
+            }
+            let admin = admin_routes(
+                server_config.admin_token.clone(),
+                admin_sender,
+                connections_4,
+            );
+
+            // GET /metrics -> server health in the Prometheus text format
+            let metrics = metrics_route(metrics, connections_6);
+
+            let routes = index
+                .or(player_stats)
+                .or(game)
+                .or(spectate)
+                .or(admin)
+                .or(metrics);
+
+            // The server stops accepting connections as soon as a shutdown starts, sockets
+            // that are already open are left to be closed below
+            let (stop_accepting_sender, stop_accepting_receiver) = oneshot::channel::<()>();
+            let (_, server) =
+                warp::serve(routes).bind_with_graceful_shutdown(server_config.address(), async {
+                    stop_accepting_receiver.await.ok();
+                });
+            let server = tokio::task::spawn(server);
+
+            shutdown_signal().await;
+            info!("Shutting down, no longer accepting connections");
+            stop_accepting_sender.send(()).ok();
+
+            // Warn everyone still playing, a second signal skips the rest of the countdown
+            let countdown = async {
+                for seconds_remaining in (1..=server_config.shutdown_countdown_secs).rev() {
+                    // Not from the engine, so it has no tick and is sent as its own frame
+                    let notice = ServerMessageAllClients::ShutdownNotice { seconds_remaining };
+                    let mut encoded_notice = EncodedMessage::new(&notice);
+                    let connections = connections_5.read().await;
+                    for connection in connections.0.values() {
+                        connection
+                            .queue
+                            .push(encoded_notice.get(connection.codec), notice.delivery());
+                    }
+                    MESSAGES_OUT.add(notice.kind(), connections.0.len() as u64);
+                    drop(connections);
+
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            };
+            tokio::select! {
+                _ = countdown => {}
+                _ = shutdown_signal() => info!("Skipping the rest of the shutdown countdown"),
+            }
+
+            // The engine drops its database sender when it exits, which ends the listener
+            // once it has worked through everything still queued
+            engine_shutdown_sender.send(()).ok();
+            tokio::task::spawn_blocking(move || engine.join())
+                .await
+                .ok();
+            info!("Game engine stopped");
 
-            let routes = index.or(player_stats).or(game);
+            db_listener.await.ok();
+            db_2.read().await.0.close().await;
+            info!("Database closed");
 
-            warp::serve(routes).run(([0, 0, 0, 0], 8080)).await;
+            close_all_connections(&connections_5, CLOSE_CONNECTIONS_TIMEOUT).await;
+            server.await.ok();
+            info!("Shutdown complete");
         });
 }